@@ -0,0 +1,220 @@
+use std::{
+    str::FromStr,
+    sync::Arc,
+};
+use anyhow::anyhow;
+use clap::{Args, Subcommand};
+use ethers::prelude::*;
+use ethers::core::utils::{hex, keccak256};
+
+use crate::{
+    chain::{self, Client},
+    config::Config,
+    game_pool::{GamePoolContract, TokenContract},
+    token::TokenAmount,
+};
+
+
+#[derive(Debug, Args)]
+pub struct AdminArgs {
+    /// Print the calls transactions would make instead of sending them,
+    /// without connecting to the chain
+    #[clap(long)]
+    pub dry_run: bool,
+    #[clap(subcommand)]
+    pub command: AdminCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AdminCommand {
+    /// Show the pool's rewards, fees, entry fee and reward fee divider
+    Status,
+    /// Show which pool roles an account holds
+    Roles { account: String },
+    /// Set the entry fee, passed to `setEntryFee` as is
+    SetEntryFee { fee: u32 },
+    /// Set the divider used to take fees from entry fees
+    SetRewardFeeDiv { div: u32 },
+    /// Withdraw the collected fees
+    WithdrawFees,
+    /// Withdraw every token held by the pool
+    WithdrawAllFunds,
+    /// Add rewards to the pool (token base units)
+    AddRewards { amount: String },
+    GrantRole { role: Role, account: String },
+    RevokeRole { role: Role, account: String },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Role {
+    Admin,
+    Manager,
+    FeeManager,
+}
+
+impl Role {
+    const ALL: [Role; 3] = [Role::Admin, Role::Manager, Role::FeeManager];
+
+    async fn hash(&self, contract: &GamePoolContract<Client>) -> anyhow::Result<[u8; 32]> {
+        let hash = match self {
+            Role::Admin => contract.default_admin_role().call().await?,
+            Role::Manager => contract.manager_role().call().await?,
+            Role::FeeManager => contract.fee_manager_role().call().await?,
+        };
+        Ok(hash)
+    }
+
+    /// The hash as OpenZeppelin's `AccessControl` derives it, for dry runs.
+    fn local_hash(&self) -> [u8; 32] {
+        match self {
+            Role::Admin => [0; 32],
+            Role::Manager => keccak256("MANAGER_ROLE"),
+            Role::FeeManager => keccak256("FEE_MANAGER_ROLE"),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Manager => "manager",
+            Role::FeeManager => "fee-manager",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Role::ALL.iter()
+            .find(|role| role.name() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown role '{}', expected admin, manager or fee-manager", s))
+    }
+}
+
+
+fn parse_address(address: &str) -> anyhow::Result<H160> {
+    H160::from_str(address).map_err(|_| anyhow!("Invalid address [{}]", address))
+}
+
+async fn token_decimals(config: &Config, client: Arc<Client>) -> u8 {
+    if let Some(address) = &config.chain.token_address {
+        if let Ok(address) = H160::from_str(address) {
+            if let Ok(decimals) = TokenContract::new(address, client).decimals().call().await {
+                return decimals;
+            }
+        }
+    }
+    config.economy.token_decimals
+}
+
+async fn print_status(config: &Config, contract: &GamePoolContract<Client>, client: Arc<Client>) -> anyhow::Result<()> {
+    let decimals = token_decimals(config, client).await;
+    let rewards = TokenAmount::from(contract.game_pool_rewards().call().await?);
+    let fees = TokenAmount::from(contract.game_pool_fees().call().await?);
+    let entry_fee = TokenAmount::from(contract.entry_fee().call().await?);
+    let reward_fee_div = contract.reward_fee_div().call().await?;
+    println!("gamePoolRewards: {} ({})", rewards.format(decimals), rewards);
+    println!("gamePoolFees:    {} ({})", fees.format(decimals), fees);
+    println!("entryFee:        {} ({})", entry_fee.format(decimals), entry_fee);
+    println!("rewardFeeDiv:    {}", reward_fee_div);
+    Ok(())
+}
+
+async fn print_roles(contract: &GamePoolContract<Client>, account: H160) -> anyhow::Result<()> {
+    for role in Role::ALL.iter() {
+        let hash = role.hash(contract).await?;
+        let has_role = contract.has_role(hash, account).call().await?;
+        println!("{:<12} {}", role.name(), has_role);
+    }
+    Ok(())
+}
+
+/// The transaction `command` sends, `role_hash` is that of the role it
+/// grants or revokes.
+fn transaction<M: Middleware>(
+    contract: &GamePoolContract<M>,
+    command: AdminCommand,
+    role_hash: Option<[u8; 32]>,
+) -> anyhow::Result<ContractCall<M, ()>> {
+    let call = match command {
+        AdminCommand::Status | AdminCommand::Roles { .. } => {
+            return Err(anyhow!("Status and roles only read from the chain, there is no transaction to send"));
+        }
+        AdminCommand::SetEntryFee { fee } => contract.set_entry_fee(fee),
+        AdminCommand::SetRewardFeeDiv { div } => {
+            if div == 0 {
+                return Err(anyhow!("Reward fee divider cannot be zero"));
+            }
+            contract.set_reward_fee_div(div)
+        }
+        AdminCommand::WithdrawFees => contract.withdraw_fees(),
+        AdminCommand::WithdrawAllFunds => contract.withdraw_all_funds(),
+        AdminCommand::AddRewards { amount } => {
+            let amount = U256::from_dec_str(&amount)
+                .map_err(|_| anyhow!("Invalid amount [{}]", amount))?;
+            contract.add_rewards(amount)
+        }
+        AdminCommand::GrantRole { account, .. } => {
+            contract.grant_role(role_hash.expect("role hash"), parse_address(&account)?)
+        }
+        AdminCommand::RevokeRole { account, .. } => {
+            contract.revoke_role(role_hash.expect("role hash"), parse_address(&account)?)
+        }
+    };
+    Ok(call)
+}
+
+fn role_of(command: &AdminCommand) -> Option<Role> {
+    match command {
+        AdminCommand::GrantRole { role, .. } | AdminCommand::RevokeRole { role, .. } => Some(*role),
+        _ => None,
+    }
+}
+
+/// Prints the call `command` would make. Encoding it only needs the pool's
+/// address, neither the node nor the signer are touched.
+fn dry_run(address: H160, command: AdminCommand) -> anyhow::Result<()> {
+    let (provider, _) = Provider::mocked();
+    let contract = GamePoolContract::new(address, Arc::new(provider));
+    let role_hash = role_of(&command).map(|role| role.local_hash());
+    let call = transaction(&contract, command, role_hash)?;
+    let calldata = call.calldata().ok_or_else(|| anyhow!("Transaction has no calldata"))?;
+    println!("call:     {}", call.function.signature());
+    println!("to:       {:?}", call.tx.to());
+    println!("calldata: 0x{}", hex::encode(calldata.as_ref()));
+    Ok(())
+}
+
+async fn submit(call: ContractCall<Client, ()>) -> anyhow::Result<()> {
+    let pending = call.legacy().send().await?;
+    println!("Sent transaction. Hash [{:#x}]", *pending);
+    match pending.await? {
+        Some(receipt) => println!("Transaction mined. Block [{:?}] Status [{:?}]", receipt.block_number, receipt.status),
+        None => println!("Transaction was dropped"),
+    }
+    Ok(())
+}
+
+
+pub async fn run(config: Config, args: AdminArgs) -> anyhow::Result<()> {
+    let address = parse_address(&config.chain.game_pool_address)?;
+    if args.dry_run {
+        return dry_run(address, args.command);
+    }
+
+    let client = chain::connect_client(&config.chain).await;
+    let contract = GamePoolContract::new(address, client.clone());
+    let role_hash = match role_of(&args.command) {
+        Some(role) => Some(role.hash(&contract).await?),
+        None => None,
+    };
+    let call = match args.command {
+        AdminCommand::Status => return print_status(&config, &contract, client).await,
+        AdminCommand::Roles { account } => return print_roles(&contract, parse_address(&account)?).await,
+        command => transaction(&contract, command, role_hash)?,
+    };
+
+    submit(call).await
+}
@@ -0,0 +1,144 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::config::AntiCheatConfig;
+
+
+// a flick turns the aim around at least this much
+const FLICK_ANGLE: f64 = std::f64::consts::PI * 5. / 6.;
+
+/// Watches one connection's inputs.
+///
+/// Inputs over the rate limits are dropped. Dropped and invalid inputs, a
+/// cadence too regular for a hand and aim that keeps flicking around faster
+/// than a hand can all add to an anomaly score, which wears off over time.
+/// Players over `ban_score` are banned.
+#[derive(Debug)]
+pub struct InputMonitor {
+    score: f64,
+    last_decay: Instant,
+    targets: VecDeque<Instant>,
+    splits: VecDeque<Instant>,
+    // milliseconds between the last targets
+    intervals: VecDeque<f64>,
+    // whether each of the last targets was a flick
+    flicks: VecDeque<bool>,
+    last_target: Option<(f64, Instant)>,
+}
+
+impl InputMonitor {
+    pub fn new(now: Instant) -> InputMonitor {
+        InputMonitor {
+            score: 0.,
+            last_decay: now,
+            targets: VecDeque::new(),
+            splits: VecDeque::new(),
+            intervals: VecDeque::new(),
+            flicks: VecDeque::new(),
+            last_target: None,
+        }
+    }
+
+    pub fn score(&self) -> f64 {
+        self.score
+    }
+
+    pub fn should_ban(&self, config: &AntiCheatConfig) -> bool {
+        config.enabled && self.score >= config.ban_score
+    }
+
+    /// Whether the target can be applied.
+    pub fn target(&mut self, x: f64, y: f64, now: Instant, config: &AntiCheatConfig) -> bool {
+        if !config.enabled {
+            return true;
+        }
+        self.decay(now, config);
+        if !x.is_finite() || !y.is_finite() {
+            self.score += config.invalid_input_score;
+            return false;
+        }
+        if !within_rate(&mut self.targets, config.max_targets_per_sec, now) {
+            self.score += config.rate_limit_score;
+            return false;
+        }
+
+        let angle = y.atan2(x);
+        if let Some((last_angle, last_at)) = self.last_target {
+            let since = now.duration_since(last_at);
+            let mut turn = (angle - last_angle).abs();
+            if turn > std::f64::consts::PI {
+                turn = 2. * std::f64::consts::PI - turn;
+            }
+            push_window(&mut self.intervals, since.as_secs_f64() * 1000., config.pattern_window);
+            push_window(
+                &mut self.flicks,
+                turn >= FLICK_ANGLE && since <= Duration::from_millis(config.flick_ms),
+                config.pattern_window,
+            );
+        }
+        self.last_target = Some((angle, now));
+        self.check_patterns(config);
+        true
+    }
+
+    /// Whether the split can be applied.
+    pub fn split(&mut self, now: Instant, config: &AntiCheatConfig) -> bool {
+        if !config.enabled {
+            return true;
+        }
+        self.decay(now, config);
+        if !within_rate(&mut self.splits, config.max_splits_per_sec, now) {
+            self.score += config.rate_limit_score;
+            return false;
+        }
+        true
+    }
+
+    fn decay(&mut self, now: Instant, config: &AntiCheatConfig) {
+        let elapsed = now.duration_since(self.last_decay).as_secs_f64();
+        self.score = (self.score - elapsed * config.score_decay_per_sec).max(0.);
+        self.last_decay = now;
+    }
+
+    fn check_patterns(&mut self, config: &AntiCheatConfig) {
+        if self.intervals.len() == config.pattern_window {
+            let mean = self.intervals.iter().sum::<f64>() / self.intervals.len() as f64;
+            let variance = self.intervals.iter().map(|i| (i - mean).powi(2)).sum::<f64>() / self.intervals.len() as f64;
+            if variance.sqrt() < config.min_jitter_ms {
+                self.score += config.pattern_score;
+                self.intervals.clear();
+            }
+        }
+        if self.flicks.len() == config.pattern_window {
+            if self.flicks.iter().filter(|flick| **flick).count() * 2 >= config.pattern_window {
+                self.score += config.pattern_score;
+                self.flicks.clear();
+            }
+        }
+    }
+}
+
+/// Records an action at `now` unless `per_sec` were already made in the last
+/// second.
+fn within_rate(times: &mut VecDeque<Instant>, per_sec: u32, now: Instant) -> bool {
+    while let Some(first) = times.front() {
+        if now.duration_since(*first) < Duration::from_secs(1) {
+            break;
+        }
+        times.pop_front();
+    }
+    if times.len() >= per_sec as usize {
+        return false;
+    }
+    times.push_back(now);
+    true
+}
+
+fn push_window<T>(window: &mut VecDeque<T>, value: T, size: usize) {
+    window.push_back(value);
+    while window.len() > size {
+        window.pop_front();
+    }
+}
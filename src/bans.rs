@@ -0,0 +1,199 @@
+use std::{
+    convert::TryFrom,
+    fmt,
+    fs,
+    net::IpAddr,
+    path::PathBuf,
+    str::FromStr,
+};
+use ethers::prelude::H160;
+use serde::{Deserialize, Serialize};
+
+
+/// An ip, or a range of them in CIDR notation like `203.0.113.0/24`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl IpRange {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ip, prefix) = match s.split_once('/') {
+            Some((ip, prefix)) => (ip, Some(prefix)),
+            None => (s, None),
+        };
+        let network: IpAddr = ip.parse().map_err(|_| format!("'{}' is not an ip or ip range", s))?;
+        let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().ok()
+                .filter(|prefix| *prefix <= max_prefix)
+                .ok_or_else(|| format!("'{}' has an invalid prefix length", s))?,
+            None => max_prefix,
+        };
+        Ok(IpRange { network, prefix })
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// What a ban applies to, written as an eth address or an ip range.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum BanTarget {
+    /// Lowercase eth address.
+    Address(String),
+    IpRange(IpRange),
+}
+
+impl FromStr for BanTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if H160::from_str(s).is_ok() {
+            return Ok(BanTarget::Address(s.to_lowercase()));
+        }
+        s.parse()
+            .map(BanTarget::IpRange)
+            .map_err(|_| format!("'{}' is not an eth address, ip or ip range", s))
+    }
+}
+
+impl TryFrom<String> for BanTarget {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<BanTarget> for String {
+    fn from(target: BanTarget) -> String {
+        target.to_string()
+    }
+}
+
+impl fmt::Display for BanTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BanTarget::Address(address) => write!(f, "{}", address),
+            BanTarget::IpRange(range) => write!(f, "{}", range),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ban {
+    pub target: BanTarget,
+    pub reason: String,
+    /// Unix time the ban ends, never when unset.
+    pub until: Option<u64>,
+}
+
+impl Ban {
+    fn is_active(&self, now: u64) -> bool {
+        self.until.map(|until| until > now).unwrap_or(true)
+    }
+}
+
+/// Banned eth addresses and ip ranges, saved as JSON to `path` on every
+/// change so they outlive a restart.
+#[derive(Debug, Default)]
+pub struct BanList {
+    path: Option<PathBuf>,
+    bans: Vec<Ban>,
+}
+
+impl BanList {
+    /// Reads the bans saved at `path`. A missing file is an empty list.
+    pub fn load(path: Option<PathBuf>) -> Result<BanList, String> {
+        let bans = match &path {
+            Some(path) if path.exists() => {
+                let contents = fs::read_to_string(path)
+                    .map_err(|err| format!("Cannot read ban list {}: {}", path.display(), err))?;
+                serde_json::from_str(&contents)
+                    .map_err(|err| format!("Cannot parse ban list {}: {}", path.display(), err))?
+            }
+            _ => Vec::new(),
+        };
+        Ok(BanList { path, bans })
+    }
+
+    /// Adds the ban, replacing any other ban of the same target.
+    pub fn add(&mut self, ban: Ban) {
+        self.bans.retain(|other| other.target != ban.target);
+        self.bans.push(ban);
+        self.save();
+    }
+
+    /// Whether there was a ban to lift.
+    pub fn remove(&mut self, target: &BanTarget) -> bool {
+        let count = self.bans.len();
+        self.bans.retain(|ban| ban.target != *target);
+        let removed = self.bans.len() < count;
+        if removed {
+            self.save();
+        }
+        removed
+    }
+
+    /// Bans still in force, expired ones are dropped.
+    pub fn active(&mut self, now: u64) -> &[Ban] {
+        let count = self.bans.len();
+        self.bans.retain(|ban| ban.is_active(now));
+        if self.bans.len() < count {
+            self.save();
+        }
+        &self.bans
+    }
+
+    pub fn is_address_banned(&self, address: &str, now: u64) -> bool {
+        let address = address.to_lowercase();
+        self.bans.iter().any(|ban| match &ban.target {
+            BanTarget::Address(banned) => ban.is_active(now) && *banned == address,
+            BanTarget::IpRange(_) => false,
+        })
+    }
+
+    pub fn is_ip_banned(&self, ip: IpAddr, now: u64) -> bool {
+        self.bans.iter().any(|ban| match &ban.target {
+            BanTarget::IpRange(range) => ban.is_active(now) && range.contains(ip),
+            BanTarget::Address(_) => false,
+        })
+    }
+
+    fn save(&self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        let saved = serde_json::to_string_pretty(&self.bans)
+            .map_err(|err| err.to_string())
+            .and_then(|json| fs::write(path, json).map_err(|err| err.to_string()));
+        if let Err(err) = saved {
+            println!("Failed to save the ban list. Path [{}] Error [{}]", path.display(), err);
+        }
+    }
+}
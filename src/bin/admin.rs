@@ -0,0 +1,22 @@
+use clap::Parser;
+
+use agario_rust::admin::{self, AdminArgs};
+use agario_rust::config::ConfigArgs;
+
+
+/// Inspect and manage the game pool contract, same as `agario_rust admin`
+#[derive(Debug, Parser)]
+struct Cli {
+    #[clap(flatten)]
+    config: ConfigArgs,
+    #[clap(flatten)]
+    admin: AdminArgs,
+}
+
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let config = cli.config.load()?;
+    admin::run(config, cli.admin).await
+}
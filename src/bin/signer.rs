@@ -0,0 +1,34 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use ethers::prelude::*;
+use tokio::net::TcpListener;
+
+use agario_rust::signer;
+
+
+/// Stand-in remote signer: serves a keystore wallet to the game server over
+/// a websocket, point `chain.remote_signer_url` at it.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Encrypted JSON keystore to sign with
+    #[clap(long)]
+    keystore: PathBuf,
+    /// File holding the keystore password, prompted for when not given
+    #[clap(long)]
+    password_file: Option<PathBuf>,
+    /// Address to listen on, keep it reachable by the game server only
+    #[clap(long, default_value = "127.0.0.1:8600")]
+    bind: String,
+}
+
+
+#[tokio::main]
+async fn main() -> agario_rust::Result<()> {
+    let cli = Cli::parse();
+    let password = signer::read_password(cli.password_file.as_deref())?;
+    let wallet = LocalWallet::decrypt_keystore(&cli.keystore, password)?;
+    let listener = TcpListener::bind(&cli.bind).await?;
+    println!("Signing as {:#x} on ws://{}", wallet.address(), cli.bind);
+    signer::serve(wallet, listener).await
+}
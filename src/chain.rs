@@ -0,0 +1,179 @@
+use std::sync::Arc;
+use ethers::prelude::*;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::time::Duration;
+
+use crate::{
+    config::{ChainConfig, Config},
+    crypto::entry_fee_paid_event_listener,
+    game_pool::{self, Winner, game_pool_reward_added_listener, pool_parameters_listener},
+    monitor::{WalletStatus, wallet_monitor},
+    signer::{self, ServerSigner},
+    token::TokenAmount,
+};
+
+
+pub type Client = SignerMiddleware<Provider<Ws>, ServerSigner>;
+
+
+/// Everything the game needs to hear about from the chain.
+#[derive(Debug)]
+pub enum ChainEvent {
+    EntryFeePaid(String),
+    /// Amount and the sender of the deposit, when known.
+    RewardsAdded(U256, Option<String>),
+    EntryFeeChanged(U256),
+    TokenDecimalsChanged(u8),
+    WalletStatus(WalletStatus),
+    PayoutSettled(TokenAmount),
+}
+
+/// The game's side of the chain: incoming contract events and
+/// outgoing payouts.
+pub struct ChainBackend {
+    pub events: UnboundedReceiver<ChainEvent>,
+    pub win_tx: UnboundedSender<Winner>,
+}
+
+/// Connects to the websocket provider, retrying until it succeeds, and
+/// wraps it with the server's signing wallet.
+pub async fn connect_client(config: &ChainConfig) -> Arc<Client> {
+    let ws = loop {
+        if let Ok(ws_) = Ws::connect(&config.provider_ws_url).await {
+            println!("Connected to provider");
+            break ws_;
+        } else {
+            println!("Failed to connect provider. Will attemp again in 3 seconds");
+            tokio::time::sleep(Duration::from_secs(3)).await;
+        }
+    };
+    let provider = Provider::new(ws).interval(Duration::from_millis(2000));
+
+    let signer = signer::load(config).await
+        .unwrap_or_else(|err| panic!("Failed to load the signer: {}", err));
+    println!("Signing as {:#x}", signer.address());
+    let client = SignerMiddleware::new(provider, signer);
+    Arc::new(client)
+}
+
+pub async fn connect(config: &Config) -> ChainBackend {
+    let no_entry_fee = config.economy.no_entry_fee;
+    let config = &config.chain;
+    let client = connect_client(config).await;
+
+    let (events_tx, events) = unbounded_channel();
+
+    // the first event, so no tokens are converted with the wrong decimals
+    match &config.token_address {
+        Some(token_address) => {
+            let decimals = game_pool::token_decimals(&config.provider_http_url, token_address).await;
+            println!("Token decimals read. Decimals [{}]", decimals);
+            events_tx.send(ChainEvent::TokenDecimalsChanged(decimals)).ok();
+        }
+        None => println!("TOKEN_ADDRESS is not set, using the configured token decimals"),
+    }
+
+    if !no_entry_fee {
+        tokio::spawn(
+            entry_fee_paid_event_listener(
+                config.fee_manager_address.clone(),
+                config.game_pool_address.clone(),
+                client.clone(),
+                events_tx.clone(),
+            )
+        );
+    }
+
+    tokio::spawn(
+        game_pool_reward_added_listener(
+            config.provider_http_url.clone(),
+            events_tx.clone(),
+            config.game_pool_address.clone(),
+        )
+    );
+
+    tokio::spawn(
+        pool_parameters_listener(
+            config.provider_http_url.clone(),
+            events_tx.clone(),
+            config.game_pool_address.clone(),
+        )
+    );
+
+    tokio::spawn(
+        wallet_monitor(
+            client.clone(),
+            config.game_pool_address.clone(),
+            events_tx.clone(),
+        )
+    );
+
+    let win_tx = game_pool::winner_listener(
+        &config.game_pool_address,
+        client,
+        events_tx,
+    );
+
+    ChainBackend {
+        events,
+        win_tx,
+    }
+}
+
+
+/// Stand-in for the contracts, used to drive a server without a node.
+pub struct MockChain {
+    events: UnboundedSender<ChainEvent>,
+    winners: UnboundedReceiver<Winner>,
+}
+
+pub fn mock() -> (ChainBackend, MockChain) {
+    let (events_tx, events) = unbounded_channel();
+    let (win_tx, winners) = unbounded_channel();
+    let backend = ChainBackend {
+        events,
+        win_tx,
+    };
+    let mock = MockChain {
+        events: events_tx,
+        winners,
+    };
+    (backend, mock)
+}
+
+impl MockChain {
+    pub fn entry_fee_paid(&self, eth_address: &str) {
+        self.events.send(ChainEvent::EntryFeePaid(eth_address.to_lowercase())).ok();
+    }
+
+    pub fn rewards_added(&self, amount: U256) {
+        self.events.send(ChainEvent::RewardsAdded(amount, None)).ok();
+    }
+
+    pub fn rewards_added_by(&self, sender: &str, amount: U256) {
+        self.events.send(ChainEvent::RewardsAdded(amount, Some(sender.to_lowercase()))).ok();
+    }
+
+    pub fn entry_fee_changed(&self, fee: U256) {
+        self.events.send(ChainEvent::EntryFeeChanged(fee)).ok();
+    }
+
+    pub fn token_decimals_changed(&self, decimals: u8) {
+        self.events.send(ChainEvent::TokenDecimalsChanged(decimals)).ok();
+    }
+
+    pub fn wallet_status(&self, signer_balance: U256, pool_rewards: U256) {
+        self.events.send(ChainEvent::WalletStatus(WalletStatus {
+            signer_balance,
+            pool_rewards: TokenAmount::from(pool_rewards),
+        })).ok();
+    }
+
+    pub fn payout_settled(&self, winner: &Winner) {
+        self.events.send(ChainEvent::PayoutSettled(TokenAmount::from(winner.amount))).ok();
+    }
+
+    pub async fn next_winner(&mut self) -> Option<Winner> {
+        self.winners.recv().await
+    }
+}
@@ -0,0 +1,235 @@
+use std::{
+    collections::HashMap,
+    fs,
+    net::IpAddr,
+    path::PathBuf,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::CollusionConfig;
+use crate::rooms::RoomId;
+use crate::token::TokenAmount;
+
+
+/// Mass a player took from another, by eating their cells or mass they
+/// ejected.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MassTransfer {
+    pub from: String,
+    pub to: String,
+    pub mass: f64,
+    /// Unix time, in seconds.
+    pub at: u64,
+}
+
+/// A player entering a room.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Join {
+    pub address: String,
+    pub ip: IpAddr,
+    pub room_id: RoomId,
+    /// Unix time, in seconds.
+    pub at: u64,
+}
+
+/// Why a player's payouts are held.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Flag {
+    /// `from` keeps feeding the player and gets next to nothing back.
+    OneWayFeeding { from: String, mass: u64, count: u32 },
+    /// The player shared a room with `with`, connected from the same ip.
+    SharedIp { with: String, ip: IpAddr },
+    /// The player and `with` keep joining the same room together.
+    SynchronizedJoins { with: String, count: u32 },
+}
+
+/// A payout waiting for an admin to approve or reject it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeldPayout {
+    pub id: u32,
+    pub address: String,
+    pub amount: TokenAmount,
+    pub flags: Vec<Flag>,
+    /// Unix time, in seconds.
+    pub held_at: u64,
+}
+
+/// Payouts waiting for review, saved as JSON to `path` on every change so
+/// they outlive a restart.
+#[derive(Debug, Default)]
+pub struct HeldPayouts {
+    path: Option<PathBuf>,
+    payouts: Vec<HeldPayout>,
+}
+
+impl HeldPayouts {
+    /// Reads the payouts saved at `path`. A missing file is an empty list.
+    pub fn load(path: Option<PathBuf>) -> Result<HeldPayouts, String> {
+        let payouts = match &path {
+            Some(path) if path.exists() => {
+                let contents = fs::read_to_string(path)
+                    .map_err(|err| format!("Cannot read held payouts {}: {}", path.display(), err))?;
+                serde_json::from_str(&contents)
+                    .map_err(|err| format!("Cannot parse held payouts {}: {}", path.display(), err))?
+            }
+            _ => Vec::new(),
+        };
+        Ok(HeldPayouts { path, payouts })
+    }
+
+    /// Id for the next payout, past any loaded from disk.
+    pub fn next_id(&self) -> u32 {
+        self.payouts.iter().map(|payout| payout.id).max().unwrap_or(0) + 1
+    }
+
+    pub fn add(&mut self, payout: HeldPayout) {
+        self.payouts.push(payout);
+        self.save();
+    }
+
+    pub fn remove(&mut self, id: u32) -> Option<HeldPayout> {
+        let index = self.payouts.iter().position(|payout| payout.id == id)?;
+        let payout = self.payouts.remove(index);
+        self.save();
+        Some(payout)
+    }
+
+    pub fn all(&self) -> &[HeldPayout] {
+        &self.payouts
+    }
+
+    /// Sum of every payout, they are still owed.
+    pub fn total(&self) -> TokenAmount {
+        self.payouts.iter().fold(TokenAmount::zero(), |total, payout| total + payout.amount)
+    }
+
+    fn save(&self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        let saved = serde_json::to_string_pretty(&self.payouts)
+            .map_err(|err| err.to_string())
+            .and_then(|json| fs::write(path, json).map_err(|err| err.to_string()));
+        if let Err(err) = saved {
+            println!("Failed to save held payouts. Path [{}] Error [{}]", path.display(), err);
+        }
+    }
+}
+
+
+/// Remembers mass transfers and joins for `window_secs` and looks for the
+/// patterns of one person playing several wallets.
+#[derive(Debug)]
+pub struct CollusionDetector {
+    config: CollusionConfig,
+    transfers: Vec<MassTransfer>,
+    joins: Vec<Join>,
+}
+
+impl CollusionDetector {
+    pub fn new(config: CollusionConfig) -> CollusionDetector {
+        CollusionDetector {
+            config,
+            transfers: Vec::new(),
+            joins: Vec::new(),
+        }
+    }
+
+    pub fn update_config(&mut self, config: CollusionConfig) {
+        self.config = config;
+    }
+
+    pub fn record_transfer(&mut self, transfer: MassTransfer) {
+        if self.config.enabled && transfer.from != transfer.to {
+            self.transfers.push(transfer);
+        }
+    }
+
+    pub fn record_join(&mut self, join: Join) {
+        if self.config.enabled {
+            self.joins.push(join);
+        }
+    }
+
+    /// Drops everything older than `window_secs`.
+    pub fn forget_old(&mut self, now: u64) {
+        let since = now.saturating_sub(self.config.window_secs);
+        self.transfers.retain(|transfer| transfer.at >= since);
+        self.joins.retain(|join| join.at >= since);
+    }
+
+    /// Everything suspicious about `address`, empty when they look clean.
+    pub fn flags(&self, address: &str) -> Vec<Flag> {
+        if !self.config.enabled {
+            return Vec::new();
+        }
+        let mut flags = self.feeding_flags(address);
+        flags.extend(self.join_flags(address));
+        flags
+    }
+
+    fn feeding_flags(&self, address: &str) -> Vec<Flag> {
+        // mass and count received from each player, and mass given back
+        let mut pairs: HashMap<&str, (f64, u32, f64)> = HashMap::new();
+        for transfer in self.transfers.iter() {
+            if transfer.to == address {
+                let pair = pairs.entry(transfer.from.as_str()).or_default();
+                pair.0 += transfer.mass;
+                pair.1 += 1;
+            } else if transfer.from == address {
+                pairs.entry(transfer.to.as_str()).or_default().2 += transfer.mass;
+            }
+        }
+        let mut feeders: Vec<(&str, (f64, u32, f64))> = pairs.into_iter()
+            .filter(|(_, (received, count, given))| {
+                *received >= self.config.feed_min_mass as f64
+                    && *count >= self.config.feed_min_count
+                    && *received >= (received + given) * self.config.feed_one_way_ratio
+            })
+            .collect();
+        feeders.sort_by_key(|(from, _)| *from);
+        feeders.into_iter()
+            .map(|(from, (received, count, _))| Flag::OneWayFeeding {
+                from: from.to_string(),
+                mass: received.round() as u64,
+                count,
+            })
+            .collect()
+    }
+
+    fn join_flags(&self, address: &str) -> Vec<Flag> {
+        let own: Vec<&Join> = self.joins.iter().filter(|join| join.address == address).collect();
+        let mut shared_ip: Vec<(&str, IpAddr)> = Vec::new();
+        let mut together: HashMap<&str, u32> = HashMap::new();
+        for join in own.iter() {
+            let others = self.joins.iter()
+                .filter(|other| other.address != address && other.room_id == join.room_id);
+            for other in others {
+                // every local connection shares the loopback address
+                if self.config.flag_shared_ip
+                    && other.ip == join.ip
+                    && !join.ip.is_loopback()
+                    && !shared_ip.iter().any(|(with, _)| *with == other.address)
+                {
+                    shared_ip.push((other.address.as_str(), other.ip));
+                }
+                if join.at.max(other.at) - join.at.min(other.at) <= self.config.join_window_secs {
+                    *together.entry(other.address.as_str()).or_default() += 1;
+                }
+            }
+        }
+
+        let mut flags: Vec<Flag> = shared_ip.into_iter()
+            .map(|(with, ip)| Flag::SharedIp { with: with.to_string(), ip })
+            .collect();
+        let mut synchronized: Vec<(&str, u32)> = together.into_iter()
+            .filter(|(_, count)| *count >= self.config.synchronized_joins)
+            .collect();
+        synchronized.sort();
+        flags.extend(synchronized.into_iter()
+            .map(|(with, count)| Flag::SynchronizedJoins { with: with.to_string(), count }));
+        flags
+    }
+}
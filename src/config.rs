@@ -1,50 +1,915 @@
-use std::env;
+use std::{
+    collections::HashMap,
+    env,
+    fmt,
+    fs,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use ethers::prelude::H160;
+use serde::{Deserialize, Serialize};
+use toml::Value;
 
+use crate::token::{self, MassConversion};
+use crate::win::WinConditionKind;
+
+
+/// Env variables from before the config file existed, kept so the old
+/// `.env` files keep working. Anything else can be set with
+/// `AGARIO__<SECTION>__<KEY>`, e.g. `AGARIO__GAMEPLAY__MAX_PLAYERS=50`.
+const LEGACY_ENV_VARS: [(&str, &str); 10] = [
+    ("WS_PORT", "server.ws_port"),
+    ("CHAIN_ID", "chain.chain_id"),
+    ("PROVIDER_HTTP_URL", "chain.provider_http_url"),
+    ("PROVIDER_WS_URL", "chain.provider_ws_url"),
+    ("SECRET_KEY", "chain.secret_key"),
+    ("FEE_MANAGER_ADDRESS", "chain.fee_manager_address"),
+    ("GAME_POOL_ADDRESS", "chain.game_pool_address"),
+    ("TOKEN_ADDRESS", "chain.token_address"),
+    ("MULTIPLIER", "economy.multiplier"),
+    ("NO_ENTRY_FEE", "economy.no_entry_fee"),
+];
+const ENV_PREFIX: &str = "AGARIO__";
+
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
-    pub ws_port: i32,
+    pub server: ServerConfig,
+    pub chain: ChainConfig,
+    pub gameplay: GameplayConfig,
+    pub economy: EconomyConfig,
+    pub tournament: TournamentConfig,
+    pub collusion: CollusionConfig,
+    pub anti_cheat: AntiCheatConfig,
+    pub connections: ConnectionConfig,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub ws_port: u16,
+    /// Overrides `ws_port`, see `BindAddress` for the accepted forms.
+    pub bind: Option<String>,
+    /// Eth addresses allowed to call the admin RPCs.
+    pub admin_addresses: Vec<String>,
+}
+
+impl ServerConfig {
+    pub fn bind_address(&self) -> Result<BindAddress, String> {
+        match &self.bind {
+            Some(bind) => bind.parse(),
+            None => Ok(BindAddress::Tcp(SocketAddr::from(([0, 0, 0, 0], self.ws_port)))),
+        }
+    }
+}
+
+
+/// Where the server listens: `0.0.0.0:3000`, `[::]:3000` or
+/// `unix:/run/agario.sock` for a fronting proxy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BindAddress {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl FromStr for BindAddress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err(String::from("unix socket path is empty"));
+            }
+            return Ok(BindAddress::Unix(PathBuf::from(path)));
+        }
+        s.parse()
+            .map(BindAddress::Tcp)
+            .map_err(|_| format!("'{}' is not an ip:port, [ipv6]:port or unix:/path address", s))
+    }
+}
+
+impl fmt::Display for BindAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindAddress::Tcp(addr) => write!(f, "{}", addr),
+            BindAddress::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChainConfig {
     pub chain_id: u32,
     pub provider_http_url: String,
     pub provider_ws_url: String,
-    pub secret_key: String,
+    /// Raw hex private key. One of `secret_key`, `keystore_path` and
+    /// `remote_signer_url` picks the signer.
+    pub secret_key: Secret,
+    /// Encrypted JSON keystore.
+    pub keystore_path: Option<PathBuf>,
+    /// File holding the keystore password, prompted for when unset.
+    pub keystore_password_file: Option<PathBuf>,
+    /// Websocket url of a remote signer, see `signer::RemoteSigner`.
+    pub remote_signer_url: Option<String>,
     pub fee_manager_address: String,
     pub game_pool_address: String,
+    pub token_address: Option<String>,
+}
+
+/// A config value that is never printed, `Debug` and `Display` show
+/// `<redacted>` instead.
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: String) -> Secret {
+        Secret(value)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(<redacted>)")
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<redacted>")
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameplayConfig {
+    /// Players per room.
+    pub max_players: u32,
+    /// Rooms are opened as the existing ones fill, up to this many.
+    pub max_rooms: u32,
+    /// Seconds a slot found by `find_match` is held for the player.
+    pub match_reservation_secs: u64,
+    /// Seconds a public room stays open with nobody in it, the last one is
+    /// never closed.
+    pub empty_room_secs: u64,
+    pub width: u32,
+    pub height: u32,
+    /// Movement updates per second.
+    pub ticks_per_sec: u64,
+    /// World snapshots sent to players per second.
+    pub updates_per_sec: u64,
+    /// Snapshots sent as deltas before the next full keyframe.
+    pub keyframe_interval: u32,
+    /// Snapshots kept per player to build deltas on. A client that takes
+    /// longer to acknowledge one gets keyframes.
+    pub snapshot_history: usize,
+    pub max_food_in_game: usize,
+    pub food_to_add_per_tick: u64,
+    /// Milliseconds between food spawns.
+    pub food_loop_tick: u64,
+    /// How a room picks its winners. Battle royale rounds have their own.
+    pub win_condition: WinConditionKind,
+    /// Seconds between two winners.
+    pub win_time: u64,
+    /// Mass a player needs before they can win.
+    pub win_mass_threshold: u64,
+    /// Mass that wins outright with the `mass_target` condition.
+    pub win_mass_target: u64,
+    /// Seconds on top that win with the `king_of_the_hill` condition.
+    pub hold_secs: u64,
+    /// Share of the map's mass that wins with the `map_share` condition.
+    pub win_map_share: f64,
+    pub default_mass: f64,
+    pub default_food_mass: f64,
+    pub init_cell_speed: f64,
+    pub log_base: f64,
+    pub init_mass_log: f64,
+    /// Milliseconds before split cells can merge again.
+    pub merge_time: u64,
+    pub max_split_num: usize,
+    pub split_momentum: f64,
+    pub minimum_visible_range: f64,
+    /// Number of teams players are split into, 0 for free for all.
+    pub teams: u32,
+    /// Mass a cell throws out with `eject`.
+    pub eject_mass: f64,
+    /// How far in front of the cell ejected mass lands.
+    pub eject_distance: f64,
+    /// Play in rounds of `win_time` seconds inside a shrinking safe zone.
+    pub battle_royale: bool,
+    /// Players needed in the room before a battle royale round starts.
+    pub round_min_players: u32,
+    /// Size of the safe zone at the end of a round, as a fraction of the
+    /// arena.
+    pub zone_final_size: f64,
+    /// Mass per second each cell outside the safe zone loses.
+    pub zone_damage: f64,
+    /// Lets players leave with their mass paid out, never in battle royale.
+    pub cash_out: bool,
+    /// Seconds a player has to go without damage before they are cashed
+    /// out.
+    pub cash_out_secs: u64,
+}
+
+impl Default for GameplayConfig {
+    fn default() -> Self {
+        GameplayConfig {
+            max_players: 100,
+            max_rooms: 10,
+            match_reservation_secs: 10,
+            empty_room_secs: 300,
+            width: 5000,
+            height: 5000,
+            ticks_per_sec: 60,
+            updates_per_sec: 60,
+            keyframe_interval: 300,
+            snapshot_history: 64,
+            max_food_in_game: 10000,
+            food_to_add_per_tick: 10,
+            food_loop_tick: 200,
+            win_condition: WinConditionKind::Timed,
+            win_time: 60,
+            win_mass_threshold: 1000,
+            win_mass_target: 2000,
+            hold_secs: 30,
+            win_map_share: 0.5,
+            default_mass: 10.,
+            default_food_mass: 1.,
+            init_cell_speed: 5.,
+            log_base: 10.,
+            init_mass_log: 1.,
+            merge_time: 5000,
+            max_split_num: 16,
+            split_momentum: 25.,
+            minimum_visible_range: 550.,
+            teams: 0,
+            eject_mass: 10.,
+            eject_distance: 100.,
+            battle_royale: false,
+            round_min_players: 2,
+            zone_final_size: 0.1,
+            zone_damage: 5.,
+            cash_out: true,
+            cash_out_secs: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EconomyConfig {
+    /// Whole tokens one unit of mass is worth.
     pub multiplier: u32,
     pub no_entry_fee: bool,
+    /// Mass put into play for every ticket bought, on top of the player's
+    /// default mass.
+    pub new_player_food_to_add: u64,
+    /// Food that has to be left in the stack to let a free player in.
+    pub free_entry_min_food: u64,
+    /// Share of the winner's mass that is paid out, the rest goes back to
+    /// the food stack.
+    pub win_percentage: f64,
+    /// Share of a cashed out player's mass kept by the house, it goes back
+    /// to the food stack.
+    pub cash_out_fee: f64,
+    /// Used until the token contract reports its decimals.
+    pub token_decimals: u8,
+    /// Wei the signer needs to keep paying for `awardWinner` transactions.
+    pub min_signer_balance: u64,
+}
+
+impl Default for EconomyConfig {
+    fn default() -> Self {
+        EconomyConfig {
+            multiplier: 1,
+            no_entry_fee: false,
+            new_player_food_to_add: 90,
+            free_entry_min_food: 100,
+            win_percentage: 0.9,
+            cash_out_fee: 0.1,
+            token_decimals: token::DEFAULT_DECIMALS,
+            min_signer_balance: 10_000_000_000_000_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TournamentConfig {
+    /// Unix times, in seconds, tournaments start at.
+    pub start_times: Vec<u64>,
+    /// Seconds before the start that registration opens.
+    pub registration_secs: u64,
+    /// Players per heat. A round with this many players or fewer is the
+    /// final.
+    pub heat_size: u32,
+    /// Best players of each heat that go through to the next round.
+    pub advance_per_heat: u32,
+    /// Length of every heat and of the final.
+    pub heat_secs: u64,
+    /// Percent of the prize pool for each place of the final, first place
+    /// first. What is left goes back to the public rooms.
+    pub prize_percentages: Vec<u32>,
+}
+
+impl Default for TournamentConfig {
+    fn default() -> Self {
+        TournamentConfig {
+            start_times: Vec::new(),
+            registration_secs: 600,
+            heat_size: 10,
+            advance_per_heat: 3,
+            heat_secs: 180,
+            prize_percentages: vec![50, 30, 20],
+        }
+    }
+}
+
+impl TournamentConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        require(&mut errors, self.heat_size >= 2, "tournament.heat_size must be at least 2");
+        require(
+            &mut errors,
+            self.advance_per_heat > 0 && self.advance_per_heat < self.heat_size,
+            "tournament.advance_per_heat must be between 1 and tournament.heat_size - 1",
+        );
+        require(&mut errors, self.heat_secs > 0, "tournament.heat_secs must be positive");
+        require(&mut errors, self.prize_percentages.iter().sum::<u32>() <= 100, "tournament.prize_percentages must add up to 100 or less");
+        require(
+            &mut errors,
+            self.prize_percentages.len() <= self.heat_size as usize,
+            "tournament.prize_percentages can't have more places than tournament.heat_size",
+        );
+        errors
+    }
+}
+
+
+/// Thresholds for `collusion::CollusionDetector`. Payouts of flagged players
+/// are held until an admin reviews them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CollusionConfig {
+    pub enabled: bool,
+    /// Seconds mass transfers and joins are remembered.
+    pub window_secs: u64,
+    /// Mass one player has to feed another before it is looked at.
+    pub feed_min_mass: u64,
+    /// Times one player has to feed another before it is looked at.
+    pub feed_min_count: u32,
+    /// Share of the mass between two players that has to go one way.
+    pub feed_one_way_ratio: f64,
+    /// Flag players who shared a room from the same ip.
+    pub flag_shared_ip: bool,
+    /// Seconds apart two joins of the same room count as together.
+    pub join_window_secs: u64,
+    /// Joins together before two players are flagged.
+    pub synchronized_joins: u32,
+    /// JSON file held payouts are kept in, they are lost on restart when
+    /// unset.
+    pub held_payouts_path: Option<PathBuf>,
+}
+
+impl Default for CollusionConfig {
+    fn default() -> Self {
+        CollusionConfig {
+            enabled: true,
+            window_secs: 3600,
+            feed_min_mass: 100,
+            feed_min_count: 3,
+            feed_one_way_ratio: 0.9,
+            flag_shared_ip: true,
+            join_window_secs: 2,
+            synchronized_joins: 3,
+            held_payouts_path: None,
+        }
+    }
+}
+
+impl CollusionConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        require(&mut errors, self.window_secs > 0, "collusion.window_secs must be positive");
+        require(&mut errors, self.feed_min_count > 0, "collusion.feed_min_count must be positive");
+        require(
+            &mut errors,
+            self.feed_one_way_ratio > 0.5 && self.feed_one_way_ratio <= 1.,
+            "collusion.feed_one_way_ratio must be in (0.5, 1]",
+        );
+        require(&mut errors, self.synchronized_joins > 0, "collusion.synchronized_joins must be positive");
+        errors
+    }
+}
+
+
+/// Limits and thresholds for `anticheat::InputMonitor`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AntiCheatConfig {
+    pub enabled: bool,
+    /// `target` inputs kept per second, the rest are dropped.
+    pub max_targets_per_sec: u32,
+    /// `split` inputs kept per second, the rest are dropped.
+    pub max_splits_per_sec: u32,
+    /// Added to the score for a target that is not a number.
+    pub invalid_input_score: f64,
+    /// Added to the score for each dropped input.
+    pub rate_limit_score: f64,
+    /// Targets looked at together for bot-like patterns.
+    pub pattern_window: usize,
+    /// Targets whose timing varies less than this, in milliseconds, come
+    /// from a timer rather than a hand.
+    pub min_jitter_ms: f64,
+    /// Milliseconds within which turning the aim around counts as a flick.
+    pub flick_ms: u64,
+    /// Added to the score for a bot-like pattern.
+    pub pattern_score: f64,
+    /// Score that wears off every second.
+    pub score_decay_per_sec: f64,
+    /// Players reaching this score are disconnected and banned.
+    pub ban_score: f64,
+    pub ban_secs: u64,
+}
+
+impl Default for AntiCheatConfig {
+    fn default() -> Self {
+        AntiCheatConfig {
+            enabled: true,
+            max_targets_per_sec: 120,
+            max_splits_per_sec: 5,
+            invalid_input_score: 10.,
+            rate_limit_score: 1.,
+            pattern_window: 60,
+            min_jitter_ms: 0.5,
+            flick_ms: 30,
+            pattern_score: 5.,
+            score_decay_per_sec: 0.5,
+            ban_score: 30.,
+            ban_secs: 86400,
+        }
+    }
 }
 
-pub fn load_config() -> Config {
-    let ws_port = env::var("WS_PORT")
-        .expect("Missing WS_PORT env variable!")
-        .parse()
-        .expect("Could not parse WS_PORT to integer");
-    let chain_id = env::var("CHAIN_ID")
-        .expect("Missing CHAIN_ID env variable!")
-        .parse()
-        .expect("Could not parse CHAIN_ID to unsigned integer");
-    let provider_http_url = env::var("PROVIDER_HTTP_URL").expect("Missing PROVIPROVIDER_HTTP_URLDER_WS_URL env variable!");
-    let provider_ws_url = env::var("PROVIDER_WS_URL").expect("Missing PROVIDER_WS_URL env variable!");
-    let secret_key = env::var("SECRET_KEY").expect("Missing SECRET_KEY env variable!");
-    let fee_manager_address = env::var("FEE_MANAGER_ADDRESS").expect("Missing FEE_MANAGER_ADDRESS env variable!");
-    let game_pool_address = env::var("GAME_POOL_ADDRESS").expect("Missing GAME_POOL_ADDRESS env variable!");
-    let multiplier = env::var("MULTIPLIER")
-        .expect("Missing MULTIPLIER env variable!")
-        .parse()
-        .expect("Could not parse MULTIPLIER to unsigned integer");
-    let no_entry_fee = env::var("NO_ENTRY_FEE")
-        .expect("Missing NO_ENTRY_FEE env variable!")
-        .parse()
-        .expect("Could not parse NO_ENTRY_FEE to bool");
-
-    Config {
-        ws_port,
-        chain_id,
-        provider_http_url,
-        provider_ws_url,
-        secret_key,
-        fee_manager_address,
-        game_pool_address,
-        multiplier,
-        no_entry_fee,
-    }
-
-}
\ No newline at end of file
+impl AntiCheatConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        require(&mut errors, self.max_targets_per_sec > 0, "anti_cheat.max_targets_per_sec must be positive");
+        require(&mut errors, self.max_splits_per_sec > 0, "anti_cheat.max_splits_per_sec must be positive");
+        require(&mut errors, self.pattern_window >= 2, "anti_cheat.pattern_window must be at least 2");
+        require(&mut errors, self.score_decay_per_sec >= 0., "anti_cheat.score_decay_per_sec must not be negative");
+        require(&mut errors, self.ban_score > 0., "anti_cheat.ban_score must be positive");
+        errors
+    }
+}
+
+
+/// Limits on connections per ip, checked before the websocket handshake.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectionConfig {
+    pub max_connections_per_ip: u32,
+    pub max_connection_attempts_per_min: u32,
+    /// Failed authentications in a minute before the ip is turned away.
+    pub max_failed_auths_per_min: u32,
+    /// Seconds a connection has to finish the handshake and authenticate.
+    pub auth_timeout_secs: u64,
+    /// JSON file the ban list is kept in, bans are lost on restart when
+    /// unset.
+    pub ban_list_path: Option<PathBuf>,
+    /// Responses and notifications a client can leave unread before it is
+    /// disconnected.
+    pub max_queued_messages: usize,
+    /// Seconds a client can keep missing world updates before it is
+    /// disconnected.
+    pub max_behind_secs: u64,
+    /// Header a reverse proxy puts the client's ip in, e.g.
+    /// `X-Forwarded-For`, read on the unix socket and from
+    /// `trusted_proxies`. Unset, the per ip limits, ip bans and shared ip
+    /// flags see a proxy's clients as one ip, and don't apply on the unix
+    /// socket at all.
+    pub real_ip_header: Option<String>,
+    /// Proxies allowed to set `real_ip_header`.
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        ConnectionConfig {
+            max_connections_per_ip: 10,
+            max_connection_attempts_per_min: 60,
+            max_failed_auths_per_min: 10,
+            auth_timeout_secs: 3,
+            ban_list_path: None,
+            max_queued_messages: 512,
+            max_behind_secs: 10,
+            real_ip_header: None,
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+impl ConnectionConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        require(&mut errors, self.max_connections_per_ip > 0, "connections.max_connections_per_ip must be positive");
+        require(&mut errors, self.max_connection_attempts_per_min > 0, "connections.max_connection_attempts_per_min must be positive");
+        require(&mut errors, self.max_failed_auths_per_min > 0, "connections.max_failed_auths_per_min must be positive");
+        require(&mut errors, self.auth_timeout_secs > 0, "connections.auth_timeout_secs must be positive");
+        require(&mut errors, self.max_queued_messages > 0, "connections.max_queued_messages must be positive");
+        require(&mut errors, self.max_behind_secs > 0, "connections.max_behind_secs must be positive");
+        errors
+    }
+}
+
+
+/// Every problem found while loading the config.
+#[derive(Debug)]
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Invalid configuration:")?;
+        for error in &self.0 {
+            writeln!(f, "  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+
+impl Config {
+    /// Builds the config from the defaults, then `file`, then the
+    /// environment, then `overrides` given as `section.key=value`.
+    pub fn load(file: Option<&Path>, overrides: &[String]) -> Result<Config, ConfigError> {
+        Config::load_with_env(file, env::vars(), overrides)
+    }
+
+    /// Like `load`, reading env variables from `vars` instead of the
+    /// process environment.
+    pub fn load_with_env(
+        file: Option<&Path>,
+        vars: impl IntoIterator<Item = (String, String)>,
+        overrides: &[String],
+    ) -> Result<Config, ConfigError> {
+        let vars: HashMap<String, String> = vars.into_iter().collect();
+        let mut errors = Vec::new();
+        let mut value = Value::try_from(Config::default()).expect("Default config is not serializable");
+
+        if let Some(path) = file {
+            match fs::read_to_string(path).map_err(|e| e.to_string())
+                .and_then(|contents| contents.parse::<Value>().map_err(|e| e.to_string())) {
+                Ok(file_value) => merge(&mut value, file_value),
+                Err(err) => errors.push(format!("{}: {}", path.display(), err)),
+            }
+        }
+
+        for (var, key) in LEGACY_ENV_VARS.iter() {
+            if let Some(raw) = vars.get(*var) {
+                if let Err(err) = set_key(&mut value, key, raw) {
+                    errors.push(format!("{}: {}", var, err));
+                }
+            }
+        }
+
+        let mut prefixed: Vec<(&String, &String)> = vars.iter()
+            .filter(|(var, _)| var.starts_with(ENV_PREFIX))
+            .collect();
+        prefixed.sort();
+        for (var, raw) in prefixed {
+            let key = var[ENV_PREFIX.len()..].to_lowercase().replace("__", ".");
+            if let Err(err) = set_key(&mut value, &key, raw) {
+                errors.push(format!("{}: {}", var, err));
+            }
+        }
+
+        for arg in overrides {
+            let result = match arg.split_once('=') {
+                Some((key, raw)) => set_key(&mut value, key.trim(), raw),
+                None => Err(String::from("expected section.key=value")),
+            };
+            if let Err(err) = result {
+                errors.push(format!("--set {}: {}", arg, err));
+            }
+        }
+
+        let config = match deserialize(value) {
+            Ok(config) => config,
+            Err(deserialize_errors) => {
+                errors.extend(deserialize_errors);
+                return Err(ConfigError(errors));
+            }
+        };
+        errors.extend(config.validate());
+
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError(errors))
+        }
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        match self.server.bind_address() {
+            Ok(BindAddress::Tcp(addr)) if addr.port() == 0 => {
+                errors.push(String::from("server.ws_port must be set (WS_PORT)"));
+            }
+            Ok(_) => (),
+            Err(err) => errors.push(format!("server.bind: {}", err)),
+        }
+
+        let chain = &self.chain;
+        require(&mut errors, chain.chain_id != 0, "chain.chain_id must be set (CHAIN_ID)");
+        require(&mut errors, url::Url::parse(&chain.provider_http_url).is_ok(), "chain.provider_http_url must be a valid url (PROVIDER_HTTP_URL)");
+        require(
+            &mut errors,
+            chain.provider_ws_url.starts_with("ws://") || chain.provider_ws_url.starts_with("wss://"),
+            "chain.provider_ws_url must be a ws:// or wss:// url (PROVIDER_WS_URL)",
+        );
+        let signers = [
+            !chain.secret_key.is_empty(),
+            chain.keystore_path.is_some(),
+            chain.remote_signer_url.is_some(),
+        ];
+        require(
+            &mut errors,
+            signers.iter().filter(|set| **set).count() == 1,
+            "exactly one of chain.secret_key (SECRET_KEY), chain.keystore_path and chain.remote_signer_url must be set",
+        );
+        require(
+            &mut errors,
+            chain.remote_signer_url.as_deref()
+                .map(|url| url.starts_with("ws://") || url.starts_with("wss://"))
+                .unwrap_or(true),
+            "chain.remote_signer_url must be a ws:// or wss:// url",
+        );
+        require(
+            &mut errors,
+            chain.keystore_path.as_deref().map(Path::is_file).unwrap_or(true),
+            "chain.keystore_path must be an existing file",
+        );
+        require(&mut errors, is_address(&chain.game_pool_address), "chain.game_pool_address must be an address (GAME_POOL_ADDRESS)");
+        require(
+            &mut errors,
+            self.economy.no_entry_fee || is_address(&chain.fee_manager_address),
+            "chain.fee_manager_address must be an address (FEE_MANAGER_ADDRESS)",
+        );
+        require(
+            &mut errors,
+            chain.token_address.as_deref().map(is_address).unwrap_or(true),
+            "chain.token_address must be an address (TOKEN_ADDRESS)",
+        );
+        require(
+            &mut errors,
+            self.server.admin_addresses.iter().all(|a| is_address(a)),
+            "server.admin_addresses must all be addresses",
+        );
+
+        errors.extend(self.gameplay.validate());
+        errors.extend(self.economy.validate());
+        errors.extend(self.tournament.validate());
+        errors.extend(self.collusion.validate());
+        errors.extend(self.anti_cheat.validate());
+        errors.extend(self.connections.validate());
+        require(
+            &mut errors,
+            self.tournament.heat_size <= self.gameplay.max_players,
+            "tournament.heat_size must fit in gameplay.max_players",
+        );
+        errors
+    }
+
+    /// Changes from `self` to `new` that only take effect after a restart.
+    pub fn restart_required_changes(&self, new: &Config) -> Vec<String> {
+        let mut errors = Vec::new();
+        let fields = [
+            (self.server.ws_port == new.server.ws_port, "server.ws_port"),
+            (self.server.bind == new.server.bind, "server.bind"),
+            (self.chain == new.chain, "chain"),
+            (self.gameplay.width == new.gameplay.width, "gameplay.width"),
+            (self.gameplay.height == new.gameplay.height, "gameplay.height"),
+            // players already in play keep the team they were given
+            (self.gameplay.teams == new.gameplay.teams, "gameplay.teams"),
+            // changes the value of all the mass already in play
+            (self.economy.multiplier == new.economy.multiplier, "economy.multiplier"),
+            // the EntryFeePaid listener is only started when entry fees are on
+            (self.economy.no_entry_fee == new.economy.no_entry_fee, "economy.no_entry_fee"),
+            // bans and held payouts are read from these once, at startup
+            (self.connections.ban_list_path == new.connections.ban_list_path, "connections.ban_list_path"),
+            (self.collusion.held_payouts_path == new.collusion.held_payouts_path, "collusion.held_payouts_path"),
+        ];
+        for &(unchanged, field) in fields.iter() {
+            require(&mut errors, unchanged, &format!("{} cannot change while the server is running", field));
+        }
+        errors
+    }
+
+    pub fn is_admin(&self, eth_address: &str) -> bool {
+        self.server.admin_addresses.iter().any(|a| a.to_lowercase() == eth_address.to_lowercase())
+    }
+
+    /// The config as TOML, with the secret key blanked out.
+    pub fn to_redacted_toml(&self) -> String {
+        let mut config = self.clone();
+        if !config.chain.secret_key.is_empty() {
+            config.chain.secret_key = Secret::new(String::from("<redacted>"));
+        }
+        toml::to_string_pretty(&config).expect("Config is not serializable")
+    }
+}
+
+
+/// Command line flags for loading the config, shared by every binary.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct ConfigArgs {
+    /// Env file to load before reading env variables [default: .env]
+    #[clap(long, global = true)]
+    pub env_file: Option<PathBuf>,
+    /// TOML config file, env variables and `--set` take precedence over it
+    #[clap(long, global = true)]
+    pub config: Option<PathBuf>,
+    /// Override a config value, e.g. `--set gameplay.max_players=50`
+    #[clap(long = "set", value_name = "KEY=VALUE", global = true)]
+    pub overrides: Vec<String>,
+}
+
+impl ConfigArgs {
+    pub fn load(&self) -> Result<Config, ConfigError> {
+        match &self.env_file {
+            Some(path) => {
+                if let Err(err) = dotenv::from_path(path) {
+                    return Err(ConfigError(vec![format!("{}: {}", path.display(), err)]));
+                }
+            }
+            None => {
+                dotenv::dotenv().ok();
+            }
+        }
+        Config::load(self.config.as_deref(), &self.overrides)
+    }
+}
+
+impl GameplayConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        require(&mut errors, self.max_players > 0, "gameplay.max_players must be positive");
+        require(&mut errors, self.max_rooms > 0, "gameplay.max_rooms must be positive");
+        require(&mut errors, self.match_reservation_secs > 0, "gameplay.match_reservation_secs must be positive");
+        require(&mut errors, self.empty_room_secs > 0, "gameplay.empty_room_secs must be positive");
+        require(&mut errors, self.default_mass > 0., "gameplay.default_mass must be positive");
+        require(&mut errors, self.default_food_mass > 0., "gameplay.default_food_mass must be positive");
+        // players spawn at least one default cell radius away from the border
+        let spawn_margin = 4.0 + self.default_mass.max(0.).sqrt() * 6.0;
+        require(
+            &mut errors,
+            self.width as f64 > spawn_margin && self.height as f64 > spawn_margin,
+            "gameplay.width and gameplay.height must be larger than a default cell",
+        );
+        require(&mut errors, self.ticks_per_sec > 0 && self.ticks_per_sec <= 1000, "gameplay.ticks_per_sec must be between 1 and 1000");
+        require(&mut errors, self.updates_per_sec > 0 && self.updates_per_sec <= 1000, "gameplay.updates_per_sec must be between 1 and 1000");
+        require(&mut errors, self.snapshot_history > 0, "gameplay.snapshot_history must be positive");
+        require(&mut errors, self.food_loop_tick > 0, "gameplay.food_loop_tick must be positive");
+        require(&mut errors, self.win_time > 0, "gameplay.win_time must be positive");
+        require(&mut errors, self.win_mass_target > 0, "gameplay.win_mass_target must be positive");
+        require(&mut errors, self.hold_secs > 0, "gameplay.hold_secs must be positive");
+        require(&mut errors, self.win_map_share > 0. && self.win_map_share <= 1., "gameplay.win_map_share must be between 0 and 1");
+        require(&mut errors, self.init_cell_speed > 0., "gameplay.init_cell_speed must be positive");
+        require(&mut errors, self.log_base > 1., "gameplay.log_base must be greater than 1");
+        require(&mut errors, self.max_split_num > 0, "gameplay.max_split_num must be positive");
+        require(&mut errors, self.split_momentum >= 1., "gameplay.split_momentum must be at least 1");
+        require(&mut errors, self.minimum_visible_range > 0., "gameplay.minimum_visible_range must be positive");
+        require(&mut errors, self.teams != 1, "gameplay.teams must be 0 or at least 2");
+        require(&mut errors, self.eject_mass > 0., "gameplay.eject_mass must be positive");
+        require(&mut errors, self.eject_distance >= 0., "gameplay.eject_distance must not be negative");
+        require(&mut errors, self.round_min_players > 0, "gameplay.round_min_players must be positive");
+        require(&mut errors, self.zone_final_size > 0. && self.zone_final_size <= 1., "gameplay.zone_final_size must be between 0 and 1");
+        require(&mut errors, self.zone_damage >= 0., "gameplay.zone_damage must not be negative");
+        require(&mut errors, self.cash_out_secs > 0, "gameplay.cash_out_secs must be positive");
+        errors
+    }
+}
+
+impl EconomyConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        require(&mut errors, self.win_percentage > 0. && self.win_percentage <= 1., "economy.win_percentage must be in (0, 1]");
+        require(&mut errors, (0. ..=1.).contains(&self.cash_out_fee), "economy.cash_out_fee must be in [0, 1]");
+        require(
+            &mut errors,
+            MassConversion::new(self.token_decimals, self.multiplier).is_some(),
+            "economy.multiplier must be positive and small enough for economy.token_decimals",
+        );
+        errors
+    }
+}
+
+
+fn require(errors: &mut Vec<String>, ok: bool, message: &str) {
+    if !ok {
+        errors.push(message.to_string());
+    }
+}
+
+fn is_address(address: &str) -> bool {
+    H160::from_str(address).is_ok()
+}
+
+/// Deserializes the merged config. Serde stops at the first mistake, so when
+/// there is one every key is tried again on its own over the defaults to
+/// report all of them.
+fn deserialize(value: Value) -> Result<Config, Vec<String>> {
+    let err = match value.clone().try_into::<Config>() {
+        Ok(config) => return Ok(config),
+        Err(err) => err,
+    };
+
+    let defaults = Value::try_from(Config::default()).expect("Default config is not serializable");
+    let mut errors = Vec::new();
+    let sections = match value {
+        Value::Table(sections) => sections,
+        _ => return Err(vec![err.to_string()]),
+    };
+    for (section, keys) in sections {
+        let pieces: Vec<(String, Value)> = match keys {
+            Value::Table(keys) => keys.into_iter()
+                .map(|(key, value)| {
+                    let mut table = toml::value::Table::new();
+                    table.insert(key.clone(), value);
+                    (format!("{}.{}", section, key), Value::Table(table))
+                })
+                .collect(),
+            other => vec![(section.clone(), other)],
+        };
+        for (name, piece) in pieces {
+            let mut overlay = toml::value::Table::new();
+            overlay.insert(section.clone(), piece);
+            let mut single = defaults.clone();
+            merge(&mut single, Value::Table(overlay));
+            if let Err(err) = single.try_into::<Config>() {
+                errors.push(format!("{}: {}", name, err));
+            }
+        }
+    }
+    if errors.is_empty() {
+        errors.push(err.to_string());
+    }
+    Err(errors)
+}
+
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Table(base), Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Sets `section.key` from a raw string, parsed as the type of the value it
+/// replaces.
+fn set_key(root: &mut Value, key: &str, raw: &str) -> Result<(), String> {
+    let (section, field) = key.split_once('.')
+        .ok_or_else(|| format!("'{}' is not of the form section.key", key))?;
+    let table = root.get_mut(section)
+        .and_then(Value::as_table_mut)
+        .ok_or_else(|| format!("unknown config section '{}'", section))?;
+    let value = match table.get(field) {
+        Some(Value::Integer(_)) => raw.trim().parse().map(Value::Integer)
+            .map_err(|_| format!("expected an integer, got '{}'", raw))?,
+        Some(Value::Float(_)) => raw.trim().parse().map(Value::Float)
+            .map_err(|_| format!("expected a number, got '{}'", raw))?,
+        Some(Value::Boolean(_)) => raw.trim().parse().map(Value::Boolean)
+            .map_err(|_| format!("expected true or false, got '{}'", raw))?,
+        Some(Value::Array(_)) => Value::Array(raw.split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| item.parse().map(Value::Integer).unwrap_or_else(|_| Value::String(item.to_string())))
+            .collect()),
+        // unset optional values are strings, unknown keys are caught when deserializing
+        Some(Value::String(_)) | None => Value::String(raw.to_string()),
+        Some(_) => return Err(format!("'{}' cannot be set from a single value", key)),
+    };
+    table.insert(field.to_string(), value);
+    Ok(())
+}
@@ -4,16 +4,16 @@ use std::{
     sync::Arc,
 };
 use ethers::prelude::*;
-use ethers::core::k256::ecdsa::SigningKey;
+use tokio::sync::mpsc::UnboundedSender;
 
 
-use crate::Game;
+use crate::chain::{ChainEvent, Client};
 
 pub async fn entry_fee_paid_event_listener(
     fee_manager_addr: String,
     game_pool_addr: String,
-    client: Arc<SignerMiddleware<Provider<Ws>, Wallet<SigningKey>>>,
-    game: Game
+    client: Arc<Client>,
+    events: UnboundedSender<ChainEvent>,
 ) -> anyhow::Result<()> {
 
     let fee_manager_addr = H160::from_str(&fee_manager_addr).expect("Invalid fee manager address format");
@@ -31,7 +31,7 @@ pub async fn entry_fee_paid_event_listener(
     let mut stream = client.provider().watch(&filter).await?.stream();
     while let Some(log) = stream.next().await {
         if game_pool_addr == H160::from(log.topics[2]) {
-            game.lock().unwrap().ticket_bought(format!("{:#x}", H160::from(log.topics[1])));
+            events.send(ChainEvent::EntryFeePaid(format!("{:#x}", H160::from(log.topics[1]))))?;
 
         }
     }
@@ -3,21 +3,20 @@
 use std::collections::{HashMap};
 use std::marker::Copy;
 use std::net::SocketAddr;
-use std::time::SystemTime;
+use std::time::{Instant, SystemTime};
 use std::u8;
 use std::{
-    sync::Arc,
+    sync::{Arc, Mutex, atomic::{AtomicU32, Ordering}},
 };
 use ethers::core::k256::ecdsa::SigningKey;
 use ethers::prelude::*;
 use ethers::prelude::H160;
 use std::str::FromStr;
-use tokio::sync::mpsc::{Sender, UnboundedSender};
-use jsonrpc_core::Value;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinHandle;
 use rand::{thread_rng, Rng};
 use tokio::time::{self, Duration};
 use serde::{Serialize, Deserialize};
-use serde_json::json;
 use tokio_tungstenite::tungstenite::Message;
 
 
@@ -25,35 +24,19 @@ use crate::PeerMap;
 use crate::game_pool::Winner;
 use crate::utils::SplitOneMut;
 use crate::grid::Grid;
+use crate::outbox::{Frame, Outbox};
+use crate::protocol::{Channel, Entity, Metadata, Notification, Owner, Owners, Quantizer, Stamp, View, ZoneStatus};
+use crate::snapshot::SnapshotEncoder;
+use crate::collusion::MassTransfer;
+use crate::config::{AntiCheatConfig, CollusionConfig, ConnectionConfig, EconomyConfig, GameplayConfig, TournamentConfig};
+use crate::token::{MassConversion, TokenAmount};
+use crate::tournament::unix_now;
+use crate::win::{self, Side, Standings, WinCondition};
 
 
 type Players = HashMap<String, Player>;
 type Food = Vec<FoodCell>;
 
-const MAX_FOOD_IN_GAME: usize = 10000;
-const NEW_PLAYER_FOOD_TO_ADD: u64 = 90; // user gets a default mass of 10 so 100 - 10 = 90
-const FOOD_TO_ADD_PER_TICK: u64 = 10;
-const FOOD_LOOP_TICK: u64 = 200; // milliseconds
-
-const WIN_TIME: u64 = 60;
-const WIN_MASS_THRESHOLD: i32 = 1000;
-const WIN_PERCENTAGE: f64 = 0.9;
-
-const TICKS_PER_SEC: u64 = 60;
-const UPDATES_PER_SEC: u64 = 60;
-const DEFAULT_MASS: f64 = 10.;
-const DEFAULT_FOOD_MASS: f64 = 1.;
-const INIT_CELL_SPEED: f64 = 5.;
-const GAME_WIDTH: u32 = 5000;
-const GAME_HEIGHT: u32 = 5000;
-const LOG_BASE: f64 = 10.;
-const INIT_MASS_LOG: f64 = 1.;
-const MERGE_TIME: u128 = 5000;
-const MAX_SPLIT_NUM: usize = 16;
-const SPLIT_MOMENTUM: f64 = 25.;
-const MINIMUM_VISIBLE_RANGE: f64 = 550.;
-const MAX_PLAYERS: i32 = 100;
-const ENTRY_FEE: i32 = 100;
 
 
 abigen!(
@@ -68,6 +51,18 @@ pub enum GameError {
     PlayerAlreadyInGame,
     NoTicketsAvailable,
     NoMoreRewards,
+    RoomOutOfRewards,
+    PayoutsPaused,
+    RoomNotFound,
+    NotInvited,
+    RoundInProgress,
+    TournamentNotFound,
+    RegistrationClosed,
+    AlreadyRegistered,
+    NotInGame,
+    CashOutNotAllowed,
+    CashOutInProgress,
+    PayoutNotFound,
 }
 
 impl GameError {
@@ -76,17 +71,25 @@ impl GameError {
             GameError::ServerFull => "Server is full!",
             GameError::PlayerAlreadyInGame => "You are already in game!",
             GameError::NoTicketsAvailable => "You have no tickets to play!",
-            GameError::NoMoreRewards => "Server is out of free rewards to give a free entry!"
+            GameError::NoMoreRewards => "Server is out of free rewards to give a free entry!",
+            GameError::RoomOutOfRewards => "This room is out of rewards to spawn you with!",
+            GameError::PayoutsPaused => "Server cannot pay out winners right now, entries are paused!",
+            GameError::RoomNotFound => "There is no room with this id!",
+            GameError::NotInvited => "You are not invited to this room!",
+            GameError::RoundInProgress => "A round is in progress, wait for the next one!",
+            GameError::TournamentNotFound => "There is no tournament with this id!",
+            GameError::RegistrationClosed => "Registration for this tournament is not open!",
+            GameError::AlreadyRegistered => "You are already registered for this tournament!",
+            GameError::NotInGame => "You are not in game!",
+            GameError::CashOutNotAllowed => "Cashing out is not allowed in this room!",
+            GameError::CashOutInProgress => "You are already cashing out!",
+            GameError::PayoutNotFound => "There is no held payout with this id!",
         };
         desc.to_string()
     }
 }
 
 
-pub trait ToBytes {
-    fn to_bytes(&self) -> Vec<u8>;
-}
-
 trait RadiusTrait {
     fn radius(&self) -> f64;
 }
@@ -133,8 +136,15 @@ pub struct Position {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct PlayerCell {
+    #[serde(skip)]
+    id: u32,
     #[serde(skip)]
     player_id: String,
+    // numeric id of the player on the wire
+    #[serde(skip)]
+    owner: u32,
+    #[serde(skip)]
+    team: Option<u32>,
     pos: Position,
     #[serde(skip)]
     mass: f64,
@@ -148,65 +158,146 @@ struct PlayerCell {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct FoodCell {
+    #[serde(skip)]
+    id: u32,
     pos: Position,
     hue: f64,
     #[serde(skip)]
     mass: f64,
     #[serde(skip)]
     radius: f64,
+    // the player who ejected this mass
+    #[serde(skip)]
+    ejected_by: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 struct Player {
     id: String,
+    // numeric id of the player on the wire
+    owner: u32,
     addr: SocketAddr,
-    tx: Sender<Message>,
+    tx: Outbox,
     cells: Vec<PlayerCell>,
     target: Option<Position>,
     visible_range: f64,
+    team: Option<u32>,
+    // set while the player waits to cash out
+    cash_out_started: Option<Instant>,
+    // shared by the clones the update loops send from
+    snapshots: Arc<Mutex<SnapshotEncoder>>,
+    protocol_version: u32,
+    // seq of the last input processed, 0 for none
+    last_input: u32,
+    // seq of an input taken since the last move, stamped once it has run
+    queued_input: Option<u32>,
+}
+
+/// A rectangle of the arena, the safe zone in battle royale rounds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Zone {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Zone {
+    fn arena(config: &GameplayConfig) -> Zone {
+        Zone { x: 0., y: 0., width: config.width as f64, height: config.height as f64 }
+    }
+
+    /// A zone `size` times the arena, somewhere inside it.
+    fn random_inside(config: &GameplayConfig, size: f64) -> Zone {
+        let mut rng = thread_rng();
+        let width = config.width as f64 * size;
+        let height = config.height as f64 * size;
+        Zone {
+            x: rng.gen_range(0.0..=config.width as f64 - width),
+            y: rng.gen_range(0.0..=config.height as f64 - height),
+            width,
+            height,
+        }
+    }
+
+    /// The zone `progress` of the way from this one to `to`.
+    fn shrink_towards(&self, to: &Zone, progress: f64) -> Zone {
+        let lerp = |from: f64, to: f64| from + (to - from) * progress;
+        Zone {
+            x: lerp(self.x, to.x),
+            y: lerp(self.y, to.y),
+            width: lerp(self.width, to.width),
+            height: lerp(self.height, to.height),
+        }
+    }
+
+    fn contains(&self, pos: Position) -> bool {
+        self.x <= pos.x && pos.x <= self.x + self.width &&
+            self.y <= pos.y && pos.y <= self.y + self.height
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Round {
+    started: Instant,
+    final_zone: Zone,
+}
+
+/// A team's standing, sent in `notify_update_metadata`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TeamScore {
+    pub team: u32,
+    pub mass: u32,
+    pub players: Vec<String>,
 }
 
 
 impl PlayerCell {
-    fn new(player_id: String, pos: Position) -> PlayerCell {
+    fn new(player_id: String, owner: u32, team: Option<u32>, pos: Position, mass: f64) -> PlayerCell {
         PlayerCell {
+            id: next_entity_id(),
             player_id: player_id,
+            owner: owner,
+            team: team,
             pos: pos,
-            mass: DEFAULT_MASS,
-            radius: mass_to_radius(DEFAULT_MASS),
+            mass: mass,
+            radius: mass_to_radius(mass),
             hue: generate_random_hue(),
             momentum: 1.,
             last_split: None,
         }
     }
 
-    fn speed(&self, target_dist: f64) -> f64 {
+    fn speed(&self, target_dist: f64, config: &GameplayConfig) -> f64 {
         // game point per tick
         let x = ((target_dist - 20.) / 20.).min(1.).max(0.);
-        (INIT_CELL_SPEED / (self.mass.log(LOG_BASE) - INIT_MASS_LOG + 1.) + self.momentum) * x
+        (config.init_cell_speed / (self.mass.log(config.log_base) - config.init_mass_log + 1.) + self.momentum) * x
     }
 
-    fn split(&mut self) -> Option<PlayerCell> {
-        if self.mass < DEFAULT_MASS * 2. {
+    fn split(&mut self, config: &GameplayConfig) -> Option<PlayerCell> {
+        if self.mass < config.default_mass * 2. {
             return None;
         }
         self.update_mass(self.mass / 2.);
         self.last_split = Some(SystemTime::now());
         Some(PlayerCell {
+            id: next_entity_id(),
             player_id: self.player_id.clone(),
+            owner: self.owner,
+            team: self.team,
             pos: self.pos,
             mass: self.mass,
             radius: self.radius,
             hue: self.hue,
-            momentum: SPLIT_MOMENTUM,
+            momentum: config.split_momentum,
             last_split: self.last_split,
         })
     }
 
-    fn can_merge(&self) -> bool{
+    fn can_merge(&self, merge_time: u64) -> bool{
         if let Some(last_split) = self.last_split {
             if let Ok(elapsed) = last_split.elapsed() {
-                if elapsed.as_millis() > MERGE_TIME {
+                if elapsed.as_millis() > merge_time as u128 {
                     return true;
                 } else {
                     return false;
@@ -220,59 +311,92 @@ impl PlayerCell {
         self.mass = value;
         self.radius = mass_to_radius(self.mass);
     }
-}
 
-impl ToBytes for PlayerCell {
-    fn to_bytes(&self) -> Vec<u8> {
-        let mut data = Vec::new();
-        data.extend((self.pos.x as f32).to_le_bytes());
-        data.extend((self.pos.y as f32).to_le_bytes());
-        data.extend((self.radius as f32).to_le_bytes());
-        data.extend((self.hue as u8).to_le_bytes());
-        data
+    /// Throws `config.eject_mass` out towards `target`, as food anyone can
+    /// eat, teammates included.
+    fn eject(&mut self, target: Position, config: &GameplayConfig) -> Option<FoodCell> {
+        if self.mass < config.eject_mass * 2. {
+            return None;
+        }
+        let dist = (target.x.powf(2.) + target.y.powf(2.)).sqrt();
+        if dist == 0. {
+            return None;
+        }
+        self.update_mass(self.mass - config.eject_mass);
+        let mut food = FoodCell::new(self.pos, config.eject_mass);
+        // lands clear of the cell so it isn't eaten right back
+        let offset = self.radius + food.radius + config.eject_distance;
+        food.pos.x = (self.pos.x + target.x / dist * offset).max(0.).min(config.width as f64);
+        food.pos.y = (self.pos.y + target.y / dist * offset).max(0.).min(config.height as f64);
+        food.hue = self.hue;
+        food.ejected_by = Some(self.player_id.clone());
+        Some(food)
     }
 }
 
 impl CellTrait for PlayerCell {}
 
+impl PlayerCell {
+    fn entity(&self, quantizer: &Quantizer) -> Entity {
+        quantizer.entity(self.id, self.owner, self.pos.x, self.pos.y, self.radius, self.hue)
+    }
+}
+
 impl FoodCell {
-    fn new() -> FoodCell {
+    fn new(pos: Position, mass: f64) -> FoodCell {
         FoodCell {
-            pos: random_position(),
+            id: next_entity_id(),
+            pos: pos,
             hue: generate_random_hue(),
-            mass: DEFAULT_FOOD_MASS,
-            radius: mass_to_radius(DEFAULT_FOOD_MASS),
+            mass: mass,
+            radius: mass_to_radius(mass),
+            ejected_by: None,
         }
     }
 }
 
-impl ToBytes for FoodCell {
-    fn to_bytes(&self) -> Vec<u8> {
-        let mut data = Vec::new();
-        data.extend((self.pos.x as f32).to_le_bytes());
-        data.extend((self.pos.y as f32).to_le_bytes());
-        data.extend((self.hue as u8).to_le_bytes());
-        data
+impl CellTrait for FoodCell {}
+
+impl FoodCell {
+    fn entity(&self, quantizer: &Quantizer) -> Entity {
+        quantizer.entity(self.id, 0, self.pos.x, self.pos.y, self.radius, self.hue)
     }
 }
 
-impl CellTrait for FoodCell {}
-
 impl Player {
-    fn new_player(addr: SocketAddr, eth_address: String, tx: Sender<Message>, pos: Position) -> Player{
+    fn new_player(
+        addr: SocketAddr,
+        eth_address: String,
+        team: Option<u32>,
+        tx: Outbox,
+        protocol_version: u32,
+        pos: Position,
+        config: &GameplayConfig,
+    ) -> Player {
+        let owner = next_entity_id();
         Player {
             id: eth_address.clone(),
+            owner: owner,
             addr: addr,
             tx: tx,
-            cells: vec![PlayerCell::new(eth_address, pos)],
+            cells: vec![PlayerCell::new(eth_address, owner, team, pos, config.default_mass)],
             target: None,
-            visible_range: MINIMUM_VISIBLE_RANGE,
-
+            visible_range: config.minimum_visible_range,
+            team: team,
+            cash_out_started: None,
+            snapshots: Arc::new(Mutex::new(SnapshotEncoder::new(protocol_version))),
+            protocol_version,
+            last_input: 0,
+            queued_input: None,
         }
     }
 
-    fn update_visible_range(&mut self) {
-        self.visible_range = 120. * (self.radius() - 22.).max(0.).sqrt() + MINIMUM_VISIBLE_RANGE;
+    fn is_teammate(&self, other: &Player) -> bool {
+        self.team.is_some() && self.team == other.team
+    }
+
+    fn update_visible_range(&mut self, minimum_visible_range: f64) {
+        self.visible_range = 120. * (self.radius() - 22.).max(0.).sqrt() + minimum_visible_range;
     }
 
     fn is_visible(&self, other: &impl PositionTrait) -> bool {
@@ -368,11 +492,12 @@ fn mass_to_radius(mass: f64) -> f64 {
     4.0 + mass.sqrt() * 6.0
 }
 
-fn random_position() -> Position {
+fn random_position(config: &GameplayConfig) -> Position {
     let mut rng = thread_rng();
+    let margin = mass_to_radius(config.default_mass);
     Position {
-        x: rng.gen_range(mass_to_radius(DEFAULT_MASS)..=GAME_WIDTH as f64).floor(),
-        y: rng.gen_range(mass_to_radius(DEFAULT_MASS)..=GAME_HEIGHT as f64).floor(),
+        x: rng.gen_range(margin..=config.width as f64).floor(),
+        y: rng.gen_range(margin..=config.height as f64).floor(),
     }
 }
 
@@ -383,25 +508,36 @@ fn distance_between_circles<T, U>(a: &T, b: &U) -> f64
     a.distance_to(b) - a.radius() - b.radius()
 }
 
+fn owner_entry(player: &Player) -> Owner {
+    Owner { id: player.owner, address: player.id.clone(), team: player.team }
+}
+
+static NEXT_ENTITY_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Id a cell, food or player keeps for life, unique across rooms.
+fn next_entity_id() -> u32 {
+    NEXT_ENTITY_ID.fetch_add(1, Ordering::Relaxed)
+}
+
 fn generate_random_hue() -> f64 {
     let mut rng = thread_rng();
     rng.gen_range(0.0..360.0)
 }
 
 
-fn get_new_player_position(players: &Players) -> Position {
+fn get_new_player_position(players: &Players, config: &GameplayConfig) -> Position {
 
     if players.is_empty() {
-        return random_position();
+        return random_position(config);
     }
 
     let mut best_pos = None;
     let mut best_dist = 0.;
     for _ in 1..10 {
         let mut min_dist = f64::INFINITY;
-        let rand_pos = random_position();
+        let rand_pos = random_position(config);
         for player in players.values() {
-            let tmp_cell = PlayerCell::new(String::new(), rand_pos);
+            let tmp_cell = PlayerCell::new(String::new(), 0, None, rand_pos, config.default_mass);
             let dist = distance_between_circles(player, &tmp_cell);
             if dist < min_dist {
                 min_dist = dist
@@ -417,7 +553,7 @@ fn get_new_player_position(players: &Players) -> Position {
 
     match best_pos {
         Some(pos) => pos,
-        None => random_position()
+        None => random_position(config)
     }
 }
 
@@ -428,10 +564,14 @@ struct State {
 }
 
 
-#[derive(Debug)]
-struct GameConfig {
-    no_entry_fee: bool,
-    multiplier: u32,
+#[derive(Debug, Clone, Default)]
+pub struct GameConfig {
+    pub gameplay: GameplayConfig,
+    pub economy: EconomyConfig,
+    pub tournament: TournamentConfig,
+    pub collusion: CollusionConfig,
+    pub anti_cheat: AntiCheatConfig,
+    pub connections: ConnectionConfig,
 }
 
 #[derive(Debug)]
@@ -442,9 +582,24 @@ pub struct Game {
     peer_map: PeerMap,
     eth_addr_peer_map: crate::EthAddrPeerMap,
     socket_addr_to_eth_address: HashMap<SocketAddr, String>,
-    address_tickets_map: HashMap<String, i32>,
     config: GameConfig,
+    conversion: MassConversion,
+    // tokens received that did not add up to a whole unit of mass yet
+    reward_dust: TokenAmount,
+    // reloaded config waiting for the next tick
+    pending_config: Option<GameConfig>,
+    win_condition: Box<dyn WinCondition>,
+    // seconds left in the battle royale round
     win_counter: u64,
+    // the battle royale round being played
+    round: Option<Round>,
+    // fractions of mass lost outside the safe zone or left over when paying
+    // out in whole units, not a whole unit yet
+    mass_dust: f64,
+    // mass players took from each other, collected by the room manager
+    mass_transfers: Vec<MassTransfer>,
+    // moves applied, stamped on snapshots
+    tick: u32,
 }
 
 
@@ -452,15 +607,13 @@ impl Game {
     pub fn new(
         peer_map: crate::PeerMap,
         eth_addr_peer_map: crate::EthAddrPeerMap,
-        no_entry_fee: bool,
-        multiplier: u32,
+        config: GameConfig,
     ) -> Game {
 
-        let config = GameConfig {
-            no_entry_fee,
-            multiplier,
-        };
-
+        let win_counter = config.gameplay.win_time;
+        let win_condition = win::from_config(&config.gameplay);
+        let conversion = MassConversion::new(config.economy.token_decimals, config.economy.multiplier)
+            .expect("Invalid multiplier or token decimals");
         Game {
             players: HashMap::new(),
             food: Vec::new(),
@@ -468,95 +621,210 @@ impl Game {
             peer_map: peer_map,
             eth_addr_peer_map: eth_addr_peer_map,
             socket_addr_to_eth_address: HashMap::new(),
-            address_tickets_map: HashMap::new(),
             config,
-            win_counter: WIN_TIME,
+            conversion,
+            reward_dust: TokenAmount::zero(),
+            pending_config: None,
+            win_condition,
+            win_counter,
+            round: None,
+            mass_dust: 0.,
+            mass_transfers: Vec::new(),
+            tick: 0,
         }
     }
 
-    pub fn enter_game(&mut self, addr: SocketAddr, eth_address: String) -> Result<(), GameError> {
-        if self.players.len() as i32 >= MAX_PLAYERS {
+    /// Checks that `eth_address` can play in this room, tickets and payouts
+    /// are checked by the room manager before.
+    pub fn can_enter(&self, eth_address: &str) -> Result<(), GameError> {
+        let economy = &self.config.economy;
+        if self.is_full() {
             return Err(GameError::ServerFull)
-        } else if self.players.contains_key(&eth_address) {
+        } else if self.players.contains_key(eth_address) {
             return Err(GameError::PlayerAlreadyInGame);
-        } else if self.config.no_entry_fee && self.food_stack < 100 {
+        } else if self.round.is_some() {
+            return Err(GameError::RoundInProgress);
+        } else if economy.no_entry_fee && self.food_stack < economy.free_entry_min_food {
             return Err(GameError::NoMoreRewards);
         }
+        Ok(())
+    }
 
-        if self.config.no_entry_fee {
-            self.add_player(addr, eth_address.clone())?;
+    pub fn is_full(&self) -> bool {
+        self.players.len() >= self.max_players()
+    }
+
+    /// Of this room, private rooms can have fewer than the server config.
+    pub fn max_players(&self) -> usize {
+        self.config.gameplay.max_players as usize
+    }
+
+    pub fn player_count(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn has_player(&self, addr: SocketAddr) -> bool {
+        self.socket_addr_to_eth_address.contains_key(&addr)
+    }
+
+    pub fn food_stack(&self) -> u64 {
+        self.food_stack
+    }
+
+    /// Mass a player spawns with, taken from the food stack when they enter
+    /// for free. A ticket pays for it on top of the food it adds.
+    fn spawn_mass(&self) -> u64 {
+        if self.config.economy.no_entry_fee {
+            self.config.gameplay.default_mass.ceil() as u64
         } else {
-            let remaining_tickets = self.use_ticket(&eth_address)?;
-            self.add_player(addr, eth_address.clone())?;
-            self.notify_player_by_id(&eth_address, "notify_tickets_update", json!([remaining_tickets]));
+            0
         }
-
-        Ok(())
     }
 
-    pub fn add_player(&mut self, addr: SocketAddr, player_addr: String) -> Result<(), GameError> {
+    /// Adds the player, who speaks `protocol_version`, and returns the team
+    /// they play for, if teams are on. A free player's mass comes out of
+    /// the food stack, they are refused when it isn't there.
+    pub fn add_player(&mut self, addr: SocketAddr, player_addr: String, protocol_version: u32) -> Result<Option<u32>, GameError> {
         // fix detection if player is in game
         if self.socket_addr_to_eth_address.contains_key(&addr) {
             return Err(GameError::PlayerAlreadyInGame)
         }
+        let spawn_mass = self.spawn_mass();
+        if self.food_stack < spawn_mass {
+            return Err(GameError::RoomOutOfRewards);
+        }
 
         let tx = self.peer_map.lock().unwrap().get(&addr).unwrap().clone();
 
         let player = Player::new_player(
             addr,
             player_addr,
+            self.next_team(),
             tx,
-            get_new_player_position(&self.players)
+            protocol_version,
+            get_new_player_position(&self.players, &self.config.gameplay),
+            &self.config.gameplay,
         );
         println!("new player entered the game. Player ID [{}]", player.id);
+        self.food_stack -= spawn_mass;
         self.socket_addr_to_eth_address.insert(addr, player.id.clone());
+        let team = player.team;
+        let joined = owner_entry(&player);
+        for other in self.players.values() {
+            self.notify_player(other, Notification::Owners(Owners {
+                added: vec![joined.clone()],
+                removed: Vec::new(),
+                own: None,
+            }));
+        }
+        let mut owners = self.owners();
+        owners.push(joined);
+        self.notify_player(&player, Notification::Owners(Owners {
+            added: owners,
+            removed: Vec::new(),
+            own: Some(player.owner),
+        }));
         self.players.insert(player.id.clone(), player);
 
-        Ok(())
+        Ok(team)
     }
 
-    pub fn ticket_bought(&mut self, eth_address: String) {
+    /// The team with the fewest players, lowest number first.
+    fn next_team(&self) -> Option<u32> {
+        (0..self.config.gameplay.teams).min_by_key(|&team| {
+            self.players.values().filter(|p| p.team == Some(team)).count()
+        })
+    }
+
+    pub fn ticket_bought(&mut self) {
         // Player is expected to join game immediatley so the tokens
         // they paid for entry fee is immedialtley in play
-        self.food_stack += NEW_PLAYER_FOOD_TO_ADD;
+        self.food_stack += self.config.economy.new_player_food_to_add;
+    }
 
-        *self.address_tickets_map.entry(eth_address.clone()).or_default() += 1;
-        let tickets = *self.address_tickets_map.get(&eth_address).unwrap();
-        self.notify_player_by_id(&eth_address, "notify_tickets_update", json!([tickets]));
+    /// Moves `mass` of food not in play yet out of this room, as much as
+    /// there is.
+    pub fn take_food_stack(&mut self, mass: u64) -> u64 {
+        let mass = mass.min(self.food_stack);
+        self.food_stack -= mass;
+        mass
     }
 
-    fn use_ticket(&mut self, user: &str) -> Result<i32, GameError> {
-        let tickets = self.address_tickets_map.get_mut(user);
-        match tickets {
-            Some(tickets) => {
-                if *tickets == 0 {
-                    return Err(GameError::NoTicketsAvailable);
-                }
-                *tickets -= 1;
-                Ok(*tickets)
+    pub fn add_food_stack(&mut self, mass: u64) {
+        self.food_stack = self.food_stack.saturating_add(mass);
+    }
+
+    /// Takes every player out and all the mass in the room with them.
+    /// Returns the mass each player held and the total.
+    pub fn empty(&mut self) -> (Vec<(String, u64)>, u64) {
+        let player_ids: Vec<String> = self.players.keys().cloned().collect();
+        let mut players = Vec::new();
+        for player_id in player_ids {
+            if let Some(player) = self.remove_player(&player_id) {
+                let mass = self.payable_mass(&player);
+                players.push((player_id, mass));
             }
-            None => Err(GameError::NoTicketsAvailable)
         }
+        let total = players.iter().map(|(_, mass)| mass).sum::<u64>() + self.food_mass() + self.food_stack;
+        self.food.clear();
+        self.food_stack = 0;
+        (players, total)
     }
 
-    pub fn get_available_tickets(&self, eth_address: &str) -> i32 {
-        if let Some(tickets) = self.address_tickets_map.get(eth_address) {
-            return *tickets;
-        } else {
-            return 0;
+    /// Takes the tokens kept until they make up a whole unit of mass.
+    pub fn take_reward_dust(&mut self) -> TokenAmount {
+        std::mem::replace(&mut self.reward_dust, TokenAmount::zero())
+    }
+
+    /// Tokens this room can end up paying out: the mass held by players plus
+    /// the rewards not in anyone's hands yet.
+    pub fn obligations(&self) -> TokenAmount {
+        let player_mass: f64 = self.players.values().map(|p| p.mass()).sum();
+        self.reward_balance() + self.conversion.mass_to_tokens(player_mass.ceil() as u64)
+    }
+
+    pub fn set_token_decimals(&mut self, decimals: u8) {
+        if decimals == self.conversion.decimals() {
+            return;
+        }
+        match MassConversion::new(decimals, self.config.economy.multiplier) {
+            Some(conversion) => {
+                println!("Token decimals set. Decimals [{}]", decimals);
+                self.conversion = conversion;
+            }
+            None => println!("Ignoring unusable token decimals. Decimals [{}]", decimals),
         }
     }
 
-    pub fn get_server_info(&self) -> serde_json::Value{
-        json!({
-            "max_players": MAX_PLAYERS,
-            "entry_fee": ENTRY_FEE,
-        })
+    /// Swaps in `config` at the start of the next tick.
+    pub fn queue_config(&mut self, config: GameConfig) {
+        self.pending_config = Some(config);
+    }
+
+    fn apply_pending_config(&mut self) {
+        let config = match self.pending_config.take() {
+            Some(config) => config,
+            None => return,
+        };
+        // battle royale rounds end no later than the new win time
+        self.win_counter = self.win_counter.min(config.gameplay.win_time);
+        // the conversion keeps following the decimals read from the token
+        self.config = config;
+        if self.win_condition.kind() == self.config.gameplay.win_condition {
+            self.win_condition.update_config(&self.config.gameplay);
+        } else {
+            self.win_condition = win::from_config(&self.config.gameplay);
+        }
+        if !self.config.gameplay.battle_royale && self.round.take().is_some() {
+            println!("Battle royale turned off, ending the round");
+            self.win_counter = self.config.gameplay.win_time;
+        }
+        println!("Config reloaded. Max players [{}] Win time [{}]", self.config.gameplay.max_players, self.config.gameplay.win_time);
     }
 
     fn add_food(&mut self, mut amount: u64) {
         // add food to game field
-        if self.food.len() >= MAX_FOOD_IN_GAME {
+        if self.food.len() >= self.config.gameplay.max_food_in_game {
             return
         }
         if amount > self.food_stack {
@@ -564,19 +832,41 @@ impl Game {
         }
         self.food_stack -= amount;
         for i in 0..amount {
-            self.food.push(FoodCell::new())
+            let pos = random_position(&self.config.gameplay);
+            self.food.push(FoodCell::new(pos, self.config.gameplay.default_food_mass))
         }
     }
 
     fn remove_player(&mut self, player_id: &String) -> Option<Player> {
         println!("Removing player. Player ID [{}]", player_id);
-        self.players.remove(player_id)
+        let player = self.players.remove(player_id)?;
+        self.socket_addr_to_eth_address.remove(&player.addr);
+        for other in self.players.values() {
+            self.notify_player(other, Notification::Owners(Owners {
+                added: Vec::new(),
+                removed: vec![player.owner],
+                own: None,
+            }));
+        }
+        Some(player)
     }
 
+    /// Sets where the player heads, relative to their position. Targets
+    /// that are not numbers are dropped and the rest are kept within a map's
+    /// length.
     pub fn set_target(&mut self, addr: SocketAddr, x: f64, y: f64) {
+        if !x.is_finite() || !y.is_finite() {
+            println!("Dropping invalid target. Addr [{}]", addr);
+            return;
+        }
+        let width = self.config.gameplay.width as f64;
+        let height = self.config.gameplay.height as f64;
         if let Some(player_id) = self.socket_addr_to_eth_address.get(&addr) {
             if let Some(player) = self.players.get_mut(player_id) {
-                player.target = Some(Position{x: x, y: y});
+                player.target = Some(Position{
+                    x: x.max(-width).min(width),
+                    y: y.max(-height).min(height),
+                });
             }
         } else {
             println!(
@@ -588,11 +878,12 @@ impl Game {
 
     pub fn split(&mut self, addr: SocketAddr) {
         if let Some(player_id) = self.socket_addr_to_eth_address.get(&addr) {
+            let config = &self.config.gameplay;
             let player = self.players.get_mut(player_id).unwrap();
             for i in 0..player.cells.len() {
-                if player.cells.len() < MAX_SPLIT_NUM {
+                if player.cells.len() < config.max_split_num {
                     let cell = &mut player.cells[i];
-                    if let Some(new_cell) = cell.split() {
+                    if let Some(new_cell) = cell.split(config) {
                         player.cells.push(new_cell);
                     }
                 }
@@ -601,37 +892,152 @@ impl Game {
         }
     }
 
+    /// The player's input `seq` was applied or dropped. Snapshots report it
+    /// from the next move on, the first that shows its effect.
+    pub fn input_processed(&mut self, addr: SocketAddr, seq: u32) {
+        if let Some(player_id) = self.socket_addr_to_eth_address.get(&addr) {
+            if let Some(player) = self.players.get_mut(player_id) {
+                player.queued_input = Some(seq);
+            }
+        }
+    }
+
+    fn stamp_inputs(&mut self) {
+        for player in self.players.values_mut() {
+            if let Some(seq) = player.queued_input.take() {
+                player.last_input = seq;
+            }
+        }
+    }
+
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    pub fn eject(&mut self, addr: SocketAddr) {
+        if let Some(player_id) = self.socket_addr_to_eth_address.get(&addr) {
+            let config = &self.config.gameplay;
+            let player = self.players.get_mut(player_id).unwrap();
+            let target = match player.target {
+                Some(target) => target,
+                None => return,
+            };
+            let player_pos = player.position();
+            for cell in &mut player.cells {
+                let cell_target = Position {
+                    x: player_pos.x + target.x - cell.pos.x,
+                    y: player_pos.y + target.y - cell.pos.y
+                };
+                if let Some(food) = cell.eject(cell_target, config) {
+                    self.food.push(food);
+                }
+            }
+        }
+    }
+
+    /// Starts the player's cash out, they are paid for their mass if they
+    /// take no damage for `cash_out_secs`. Returns the seconds to wait.
+    pub fn start_cash_out(&mut self, addr: SocketAddr) -> Result<u64, GameError> {
+        let config = &self.config.gameplay;
+        // mass entered with for free is only won, never cashed
+        if !config.cash_out || config.battle_royale || self.config.economy.no_entry_fee {
+            return Err(GameError::CashOutNotAllowed);
+        }
+        let player_id = self.socket_addr_to_eth_address.get(&addr).ok_or(GameError::NotInGame)?;
+        let player = self.players.get_mut(player_id).ok_or(GameError::NotInGame)?;
+        if player.cash_out_started.is_some() {
+            return Err(GameError::CashOutInProgress);
+        }
+        player.cash_out_started = Some(Instant::now());
+        println!("Player cashing out. Player ID [{}]", player.id);
+        Ok(config.cash_out_secs)
+    }
+
+    fn notify_cash_outs_cancelled(&self, player_ids: &[String]) {
+        for player_id in player_ids {
+            if let Some(player) = self.players.get(player_id) {
+                println!("Cash out cancelled, player took damage. Player ID [{}]", player_id);
+                self.notify_player(player, Notification::CashOutCancelled);
+            }
+        }
+    }
+
+    /// Takes out the players whose cash out went through, with the mass to
+    /// pay each of them. The house fee goes back to the food stack.
+    fn take_cash_outs(&mut self) -> Vec<(Player, u64)> {
+        let wait = Duration::from_secs(self.config.gameplay.cash_out_secs);
+        let ready: Vec<String> = self.players.values()
+            .filter(|p| p.cash_out_started.map(|started| started.elapsed() >= wait).unwrap_or(false))
+            .map(|p| p.id.clone())
+            .collect();
+        let mut cash_outs = Vec::new();
+        for player_id in ready {
+            if let Some(player) = self.remove_player(&player_id) {
+                let mass = self.payable_mass(&player);
+                let fee = ((mass as f64 * self.config.economy.cash_out_fee).ceil() as u64).min(mass);
+                self.food_stack += fee;
+                cash_outs.push((player, mass - fee));
+            }
+        }
+        cash_outs
+    }
+
+    /// The player applied snapshot `seq`, later ones can be deltas of it.
+    pub fn ack_snapshot(&self, addr: SocketAddr, seq: u32) {
+        if let Some(player) = self.player_by_addr(addr) {
+            player.snapshots.lock().unwrap().ack(seq);
+        }
+    }
+
+    /// The player lost track of the snapshots, the next ones are keyframes.
+    pub fn request_keyframe(&self, addr: SocketAddr) {
+        if let Some(player) = self.player_by_addr(addr) {
+            player.snapshots.lock().unwrap().resync();
+        }
+    }
+
+    /// Who the owner ids of the player cells in this room stand for.
+    pub fn owners(&self) -> Vec<Owner> {
+        self.players.values().map(owner_entry).collect()
+    }
+
+    fn player_by_addr(&self, addr: SocketAddr) -> Option<&Player> {
+        self.socket_addr_to_eth_address.get(&addr)
+            .and_then(|player_id| self.players.get(player_id))
+    }
+
     pub fn player_lost_connection(&mut self, addr: SocketAddr) {
         if let Some(player_id) = self.socket_addr_to_eth_address.remove(&addr) {
             self.remove_player(&player_id);
         }
     }
 
-    fn notify_player(&self, player: &Player , method: &str, params: Value) {
-        let message = json!({
-                "method": method,
-                "params": params,
-        }).to_string();
-        let tx = player.tx.clone();
-        tokio::spawn(async move {
-            tx.send(Message::text(message)).await;
-        });
+    fn notify_player(&self, player: &Player, notification: Notification) {
+        player.tx.send(Message::text(notification.encode_for(player.protocol_version)));
     }
 
-    fn notify_player_by_id(&self, id: &str , method: &str, params: Value) {
-        if let Some(tx) = self.eth_addr_peer_map.lock().unwrap().get(id) {
-            let tx = tx.clone();
-            let message = json!({
-                "method": method,
-                "params": params,
-            }).to_string();
-            tokio::spawn(async move {
-                tx.send(Message::text(message)).await;
-            });
-        }
+    /// Moves fractions of mass to the food stack once they add up to whole
+    /// units.
+    fn add_mass_dust(&mut self, mass: f64) {
+        self.mass_dust += mass;
+        let whole = self.mass_dust.floor();
+        self.food_stack += whole as u64;
+        self.mass_dust -= whole;
+    }
+
+    /// The whole units of a player's mass, which is what they can be paid.
+    /// Zone damage and splitting leave fractions, those go to the food stack.
+    fn payable_mass(&mut self, player: &Player) -> u64 {
+        let mass = player.mass();
+        let whole = mass.floor();
+        self.add_mass_dust(mass - whole);
+        whole as u64
     }
 
     fn move_players(&mut self) {
+        let config = &self.config.gameplay;
+        let width = config.width as f64;
+        let height = config.height as f64;
         for player in self.players.values_mut() {
             let player_pos = player.position();
             for cell in &mut player.cells {
@@ -643,7 +1049,7 @@ impl Game {
                         };
                         let target_dist = (cell_target.x.powf(2.) + cell_target.y.powf(2.)).sqrt();
                         let rad = cell_target.y.atan2(cell_target.x);
-                        let cell_speed = cell.speed(target_dist);
+                        let cell_speed = cell.speed(target_dist, config);
                         let delta_y = cell_speed * rad.sin();
                         let delta_x = cell_speed * rad.cos();
                         cell.pos.y += delta_y;
@@ -658,11 +1064,11 @@ impl Game {
 
                         // Apply padding between cell and game border
                         let border_padding = cell.radius / 3.;
-                        if cell.pos.x > GAME_WIDTH as f64 - border_padding {
-                            cell.pos.x = GAME_WIDTH as f64 - border_padding;
+                        if cell.pos.x > width - border_padding {
+                            cell.pos.x = width - border_padding;
                         }
-                        if cell.pos.y > GAME_HEIGHT as f64 - border_padding {
-                            cell.pos.y = GAME_HEIGHT as f64 - border_padding;
+                        if cell.pos.y > height - border_padding {
+                            cell.pos.y = height - border_padding;
                         }
                         if cell.pos.x < border_padding {
                             cell.pos.x = border_padding;
@@ -686,7 +1092,7 @@ impl Game {
                     let dist = cell.distance_to(other_cell);
                     let total_radius = cell.radius + other_cell.radius;
                     if dist < total_radius {
-                        if cell.can_merge() && other_cell.can_merge() {
+                        if cell.can_merge(config.merge_time) && other_cell.can_merge(config.merge_time) {
                             if dist < total_radius / 1.75 {
                                 other_cell.update_mass(other_cell.mass + cell.mass);
                                 other_cell.last_split = None;
@@ -719,61 +1125,96 @@ impl Game {
     }
 
     fn check_players_collisions(&mut self) {
+        let mut cancelled = Vec::new();
+        let mut transfers = Vec::new();
         let mut players_vec: Vec<&mut Player> = self.players.values_mut().collect();
 
         for i in 0..players_vec.len() {
             let (player, mut other_players) = players_vec.split_one_mut(i);
 
             for other_player in &mut other_players {
+                if player.is_teammate(other_player) {
+                    continue;
+                }
+                let cells_before = other_player.cells.len();
+                let mut eaten = 0.;
                 for cell in &mut player.cells {
                     other_player.cells.retain(|other_cell| {
                         if cell.is_collide(other_cell) {
                             if cell.mass > other_cell.mass * 1.1 {
                                 cell.update_mass(cell.mass + other_cell.mass);
+                                eaten += other_cell.mass;
                                 return false;
                             }
                         }
                         true
                     });
                 }
+                if eaten > 0. {
+                    transfers.push(MassTransfer {
+                        from: other_player.id.clone(),
+                        to: player.id.clone(),
+                        mass: eaten,
+                        at: unix_now(),
+                    });
+                }
+                if other_player.cells.len() < cells_before && other_player.cash_out_started.take().is_some() {
+                    cancelled.push(other_player.id.clone());
+                }
             }
 
-            player.update_visible_range();
+            player.update_visible_range(self.config.gameplay.minimum_visible_range);
         }
 
-        // remove players that have no more cells
-        let mut players = std::mem::take(&mut self.players);
-        players.retain(|_, player| {
-            if player.cells.is_empty() {
-                self.socket_addr_to_eth_address.remove(&player.addr);
-                self.notify_game_over(player);
-                return false
-            }
-            true
-
-        });
-        self.players = players;
+        self.mass_transfers.extend(transfers);
+        self.notify_cash_outs_cancelled(&cancelled);
+        self.remove_dead_players();
 
     }
 
     fn check_food_collisions(&mut self) {
+        let mut transfers = Vec::new();
         for player in self.players.values_mut() {
+            let player_id = &player.id;
             for cell in &mut player.cells {
                 self.food.retain(|f| {
                     if cell.is_collide(f) {
                         cell.update_mass(cell.mass + f.mass);
+                        if let Some(ejected_by) = &f.ejected_by {
+                            transfers.push(MassTransfer {
+                                from: ejected_by.clone(),
+                                to: player_id.clone(),
+                                mass: f.mass,
+                                at: unix_now(),
+                            });
+                        }
                         return false;
                     }
                     true
                 });
             }
         }
+        self.record_transfers(transfers);
+    }
+
+    /// Keeps the transfers between players of different teams, feeding a
+    /// teammate is part of the game.
+    fn record_transfers(&mut self, transfers: Vec<MassTransfer>) {
+        let team = |id: &String| self.players.get(id).and_then(|player| player.team);
+        let transfers: Vec<MassTransfer> = transfers.into_iter()
+            .filter(|t| t.from != t.to && (team(&t.from).is_none() || team(&t.from) != team(&t.to)))
+            .collect();
+        self.mass_transfers.extend(transfers);
+    }
+
+    pub fn take_mass_transfers(&mut self) -> Vec<MassTransfer> {
+        std::mem::take(&mut self.mass_transfers)
     }
 
     fn check_collisions(&mut self) {
         let cells = self.players.values().flat_map(|p| &p.cells).into_iter();
-        let player_cells_grid = Grid::new(GAME_WIDTH, 500, cells.into_iter());
-        let food_cells_grid = Grid::new(GAME_WIDTH, 500, self.food.iter());
+        let player_cells_grid = Grid::new(self.config.gameplay.width, 500, cells.into_iter());
+        let food_cells_grid = Grid::new(self.config.gameplay.width, 500, self.food.iter());
 
         let mut consumed_player_cells = Vec::new();
         let mut consumed_food = Vec::new();
@@ -802,6 +1243,7 @@ impl Game {
                 let player_cells = player_cells_grid.query(cell.pos, cell.radius as u32 * 2);
                 player_cells.for_each(|other_cell| {
                     if cell.player_id == other_cell.player_id {return}
+                    if cell.team.is_some() && cell.team == other_cell.team {return}
                     if !consumed_player_cells.iter().any(|&c| std::ptr::eq(other_cell, c)) {
                         if cell.mass > other_cell.mass * 1.1 {
                             if cell.is_collide(other_cell) {
@@ -824,7 +1266,7 @@ impl Game {
             cell.update_mass(cell.mass + mass_gained);
 
             // update player visible range
-            player.update_visible_range();
+            player.update_visible_range(self.config.gameplay.minimum_visible_range);
         }
 
 
@@ -834,12 +1276,23 @@ impl Game {
         });
 
         // remove consumed cells
+        let mut cancelled = Vec::new();
         for player in self.players.values_mut() {
+            let cells_before = player.cells.len();
             player.cells.retain(|cell| {
                 !consumed_player_cells.iter().any(|&c| std::ptr::eq(cell, c))
             });
+            if player.cells.len() < cells_before && player.cash_out_started.take().is_some() {
+                cancelled.push(player.id.clone());
+            }
         }
+        self.notify_cash_outs_cancelled(&cancelled);
+
+        self.remove_dead_players();
 
+    }
+
+    fn remove_dead_players(&mut self) {
         // remove players that have no more cells
         let mut players = std::mem::take(&mut self.players);
         players.retain(|_, player| {
@@ -852,11 +1305,124 @@ impl Game {
 
         });
         self.players = players;
+    }
+
+    /// The safe zone right now, while a round is on.
+    pub fn zone(&self) -> Option<Zone> {
+        let round = self.round.as_ref()?;
+        let config = &self.config.gameplay;
+        let progress = (round.started.elapsed().as_secs_f64() / config.win_time as f64).min(1.);
+        Some(Zone::arena(config).shrink_towards(&round.final_zone, progress))
+    }
 
+    /// Takes mass from the cells outside the safe zone, it goes back to the
+    /// food stack. Cells that run out of mass are gone.
+    fn apply_zone(&mut self) {
+        let zone = match self.zone() {
+            Some(zone) => zone,
+            None => return,
+        };
+        let config = &self.config.gameplay;
+        let damage = config.zone_damage / config.ticks_per_sec as f64;
+        let mut mass_lost = 0.;
+        let mut cancelled = Vec::new();
+        for player in self.players.values_mut() {
+            let mut damaged = false;
+            for cell in &mut player.cells {
+                if zone.contains(cell.pos) {
+                    continue;
+                }
+                let taken = if cell.mass - damage < 1. { cell.mass } else { damage };
+                cell.update_mass(cell.mass - taken);
+                mass_lost += taken;
+                damaged = true;
+            }
+            player.cells.retain(|cell| cell.mass > 0.);
+            if damaged && player.cash_out_started.take().is_some() {
+                cancelled.push(player.id.clone());
+            }
+        }
+        self.notify_cash_outs_cancelled(&cancelled);
+        self.add_mass_dust(mass_lost);
+        self.remove_dead_players();
+    }
+
+    fn start_round(&mut self) {
+        let config = &self.config.gameplay;
+        self.round = Some(Round {
+            started: Instant::now(),
+            final_zone: Zone::random_inside(config, config.zone_final_size),
+        });
+        self.win_counter = config.win_time;
+        println!("Battle royale round started. Players [{}]", self.players.len());
+    }
+
+    /// Counts the round down a second and returns who won when it is over.
+    fn round_tick(&mut self) -> Option<Vec<String>> {
+        if !self.config.gameplay.battle_royale {
+            let standings = self.standings();
+            return self.win_condition.tick(&standings).map(|side| side.members);
+        }
+
+        if self.round.is_none() {
+            if self.players.len() >= self.config.gameplay.round_min_players as usize {
+                self.start_round();
+            }
+            return None;
+        }
+        self.win_counter = self.win_counter.saturating_sub(1);
+        // the last one standing wins early, otherwise the heaviest at the end
+        let winners = match self.last_standing() {
+            Some(survivors) => survivors,
+            None if self.win_counter == 0 => {
+                self.standings().leader().map(|side| side.members.clone()).unwrap_or_default()
+            }
+            None => return None,
+        };
+        println!("Battle royale round over. Winners [{}]", winners.join(", "));
+        self.round = None;
+        self.win_counter = self.config.gameplay.win_time;
+        Some(winners)
+    }
+
+    /// Everyone left when a single player, or a single team, remains.
+    fn last_standing(&self) -> Option<Vec<String>> {
+        let mut teams: Vec<Option<u32>> = self.players.values().map(|p| p.team).collect();
+        teams.sort_unstable();
+        teams.dedup();
+        let decided = if self.config.gameplay.teams > 0 {
+            teams.len() <= 1
+        } else {
+            self.players.len() <= 1
+        };
+        match decided {
+            true => Some(self.players.keys().cloned().collect()),
+            false => None,
+        }
+    }
+
+    /// Round state for `notify_zone`, only sent in battle royale rooms.
+    fn zone_info(&self) -> Option<ZoneStatus> {
+        let config = &self.config.gameplay;
+        if !config.battle_royale {
+            return None;
+        }
+        Some(match (&self.round, self.zone()) {
+            (Some(round), Some(zone)) => ZoneStatus::Running {
+                zone,
+                final_zone: round.final_zone,
+                ends_in: self.win_counter,
+            },
+            _ => ZoneStatus::Waiting {
+                zone: Zone::arena(config),
+                final_zone: None,
+                players_needed: (config.round_min_players as usize).saturating_sub(self.players.len()),
+            },
+        })
     }
 
     fn notify_game_over(&self, player: &mut Player) {
-        self.notify_player(player, "notify_game_over", Value::Null);
+        self.notify_player(player, Notification::GameOver);
     }
 
     fn get_state(&self) -> State {
@@ -866,7 +1432,7 @@ impl Game {
         }
     }
 
-    fn get_scores(&self) -> Vec<(String, u32)> {
+    pub fn get_scores(&self) -> Vec<(String, u32)> {
         let mut scores: Vec<(String, u32)> = self.players.values()
             .map(|player| (player.id.clone(), player.mass()as u32))
             .collect();
@@ -874,23 +1440,89 @@ impl Game {
         scores
     }
 
-    fn get_winner(&self) -> Option<String> {
-        if let Some(player) = self.players.values().max_by(|&a, &b| a.mass().partial_cmp(&b.mass()).unwrap()) {
-            if player.mass() > WIN_MASS_THRESHOLD as f64 {
-                return Some(player.id.clone());
-            }
+    /// Mass of each team, heaviest first. Empty when teams are off.
+    pub fn get_team_scores(&self) -> Vec<TeamScore> {
+        let mut scores: Vec<TeamScore> = (0..self.config.gameplay.teams)
+            .map(|team| {
+                let members = self.players.values().filter(|p| p.team == Some(team));
+                let mut score = TeamScore { team, mass: 0, players: Vec::new() };
+                for player in members {
+                    score.mass += player.mass() as u32;
+                    score.players.push(player.id.clone());
+                }
+                score
+            })
+            .collect();
+        scores.sort_unstable_by(|a, b| b.mass.cmp(&a.mass));
+        scores
+    }
+
+    /// Players, or teams when teams are on, heaviest first, for the win
+    /// condition.
+    pub fn standings(&self) -> Standings {
+        let mut sides: Vec<Side> = if self.config.gameplay.teams > 0 {
+            (0..self.config.gameplay.teams)
+                .map(|team| {
+                    let members: Vec<&Player> = self.players.values().filter(|p| p.team == Some(team)).collect();
+                    Side {
+                        id: format!("team:{}", team),
+                        members: members.iter().map(|p| p.id.clone()).collect(),
+                        mass: members.iter().map(|p| p.mass()).sum(),
+                    }
+                })
+                .filter(|side| !side.members.is_empty())
+                .collect()
+        } else {
+            self.players.values()
+                .map(|p| Side { id: p.id.clone(), members: vec![p.id.clone()], mass: p.mass() })
+                .collect()
+        };
+        sides.sort_by(|a, b| b.mass.partial_cmp(&a.mass).unwrap());
+        let player_mass: f64 = self.players.values().map(|p| p.mass()).sum();
+        let food_mass: f64 = self.food.iter().map(|f| f.mass).sum();
+        Standings {
+            sides,
+            map_mass: player_mass + food_mass,
+        }
+    }
+
+    /// Seconds shown to players before the next win, if the room counts down.
+    fn win_counter(&self) -> Option<u64> {
+        if self.config.gameplay.battle_royale {
+            Some(self.win_counter)
+        } else {
+            self.win_condition.countdown()
         }
-        None
     }
 
-    fn get_available_rewards(&self) -> u64 {
-        (self.food_stack + self.food.len() as u64) * self.config.multiplier as u64
+    // masses are whole numbers apart from float noise from splitting and
+    // ejecting
+    fn food_mass(&self) -> u64 {
+        let mass: f64 = self.food.iter().map(|f| f.mass).sum();
+        mass.round() as u64
+    }
+
+    pub fn get_available_rewards(&self) -> u64 {
+        (self.food_stack + self.food_mass()) * self.config.economy.multiplier as u64
     }
 
-    pub fn add_rewards(&mut self, mut amount: u128) {
-        amount = amount / 1e9 as u128 / self.config.multiplier as u128;
-        println!("Adding food to food stack. Amount [{}]", amount);
-        self.food_stack += amount as u64;
+    /// Tokens backing the mass that is not held by any player.
+    pub fn reward_balance(&self) -> TokenAmount {
+        let mass = self.food_stack + self.food_mass();
+        self.conversion.mass_to_tokens(mass) + self.reward_dust
+    }
+
+    pub fn add_rewards(&mut self, amount: TokenAmount) {
+        // amounts that don't make up a whole unit of mass are kept until they do
+        let (mut mass, mut remainder) = self.conversion.tokens_to_mass(amount + self.reward_dust);
+        let room = u64::MAX - self.food_stack;
+        if mass > room {
+            remainder = remainder + self.conversion.mass_to_tokens(mass - room);
+            mass = room;
+        }
+        println!("Adding food to food stack. Amount [{}]", mass);
+        self.food_stack += mass;
+        self.reward_dust = remainder;
     }
 }
 
@@ -907,20 +1539,26 @@ async fn tick_loop(game: crate::Game) {
         let elapsed = now.elapsed()
             .unwrap_or_default().as_millis() as f64;
         // println!("game tick {}", elapsed);
-        sleep_time = (1000. / TICKS_PER_SEC as f64 - elapsed).max(1.);
+        sleep_time = (1000. / game.config.gameplay.ticks_per_sec as f64 - elapsed).max(1.);
     }
 }
 
 
 async fn move_loop(game: crate::Game) {
     loop {
-        time::sleep(Duration::from_millis(1000 / 60)).await;
+        let ticks_per_sec = game.lock().unwrap().config.gameplay.ticks_per_sec;
+        time::sleep(Duration::from_millis(1000 / ticks_per_sec)).await;
         tokio::spawn(apply_move(game.clone()));
     }
 }
 
 async fn apply_move(game: crate::Game) {
-    game.lock().unwrap().move_players();
+    let mut game = game.lock().unwrap();
+    game.apply_pending_config();
+    game.move_players();
+    game.stamp_inputs();
+    game.apply_zone();
+    game.tick = game.tick.wrapping_add(1);
 }
 
 async fn player_collision_loop(game: crate::Game) {
@@ -939,128 +1577,137 @@ async fn food_collision_loop(game: crate::Game) {
 
 async fn add_food_loop(game: crate::Game) {
     loop {
-        time::sleep(Duration::from_millis(FOOD_LOOP_TICK)).await;
+        let food_loop_tick = game.lock().unwrap().config.gameplay.food_loop_tick;
+        time::sleep(Duration::from_millis(food_loop_tick)).await;
         let mut game = game.lock().unwrap();
-        game.add_food(FOOD_TO_ADD_PER_TICK);
+        let amount = game.config.gameplay.food_to_add_per_tick;
+        game.add_food(amount);
     }
 }
 
 async fn update_loop(game: crate::Game) {
     loop {
-        time::sleep(Duration::from_millis(1000 / 60)).await;
+        let updates_per_sec = game.lock().unwrap().config.gameplay.updates_per_sec;
+        time::sleep(Duration::from_millis(1000 / updates_per_sec)).await;
         tokio::spawn(send_updates(game.clone()));
     }
 }
 
 async fn send_updates(game: crate::Game) {
-    let players = game.lock().unwrap().players.clone();
+    let (players, config, tick) = {
+        let game = game.lock().unwrap();
+        (game.players.clone(), game.config.gameplay.clone(), game.tick)
+    };
+    let quantizer = Quantizer { width: config.width as f32, height: config.height as f32 };
     let cells = players.values().flat_map(|p| &p.cells).into_iter();
-    let mut player_cells_grid = Grid::new(GAME_WIDTH, 250, cells);
+    let player_cells_grid = Grid::new(config.width, 250, cells);
     for player in players.values() {
-        let mut message = vec![0u8];
         let Position { x, y } = player.position();
-        message.extend((x as f32).to_le_bytes());
-        message.extend((y as f32).to_le_bytes());
-        message.extend((player.visible_range as f32).to_le_bytes());
-        message.extend(player_cells_grid.query_serialized(player.position(), player.visible_range as u32));
-        player.tx.send(Message::binary(message)).await;
+        let view = View { x: x as f32, y: y as f32, visible_range: player.visible_range as f32 };
+        let cells = player_cells_grid.query(player.position(), player.visible_range as u32)
+            .map(|cell| cell.entity(&quantizer))
+            .collect();
+        let stamp = Stamp { tick, input: player.last_input };
+        let message = player.snapshots.lock().unwrap()
+            .encode(Channel::Players, Some(view), stamp, quantizer, cells, &config);
+        player.tx.send_frame(Frame::Players, Message::binary(message));
     }
 }
 
 async fn food_update_loop(game: crate::Game) {
     loop {
         time::sleep(Duration::from_millis(50)).await;
-        let now = SystemTime::now();
-        let state = game.lock().unwrap().get_state();
-        let mut food_cells_grid = Grid::new(GAME_WIDTH, 250, state.food.iter());
+        let (state, config, tick) = {
+            let game = game.lock().unwrap();
+            (game.get_state(), game.config.gameplay.clone(), game.tick)
+        };
+        let quantizer = Quantizer { width: config.width as f32, height: config.height as f32 };
+        let food_cells_grid = Grid::new(config.width, 250, state.food.iter());
         for player in state.players.values() {
-            let mut message = vec![1u8];
-            message.extend(food_cells_grid.query_serialized(player.position(), player.visible_range as u32));
-            player.tx.send(Message::binary(message)).await;
+            let food = food_cells_grid.query(player.position(), player.visible_range as u32)
+                .map(|food| food.entity(&quantizer))
+                .collect();
+            let stamp = Stamp { tick, input: player.last_input };
+            let message = player.snapshots.lock().unwrap()
+                .encode(Channel::Food, None, stamp, quantizer, food, &config);
+            player.tx.send_frame(Frame::Food, Message::binary(message));
         }
-
-        let elapsed = now.elapsed()
-            .unwrap_or_default().as_millis() as f64;
-        // println!("food update {}", elapsed);
     }
 }
 
-async fn metadata_update_loop(
-    game: crate::Game,
-    client: Arc<SignerMiddleware<Provider<Ws>, Wallet<SigningKey>>>
-) {
+async fn metadata_update_loop(game: crate::Game) {
     loop {
         time::sleep(Duration::from_secs(1)).await;
         let scores;
+        let team_scores;
         let players;
         let win_counter;
+        let zone_info;
         {
             let game = game.lock().unwrap();
             scores = game.get_scores();
+            team_scores = game.get_team_scores();
             players = game.players.clone();
-            win_counter = game.win_counter;
+            win_counter = game.win_counter();
+            zone_info = game.zone_info();
         }
         let end_idx = if scores.len() >= 10 {10} else {scores.len()};
-        let scores = &scores[0..end_idx];
-        let message = json!({
-            "method": "notify_update_metadata",
-            "params": {
-                "scores": scores,
-                "win_counter": win_counter,
-            }
-        }).to_string();
+        let message = Notification::UpdateMetadata(Metadata {
+            scores: scores[0..end_idx].to_vec(),
+            teams: team_scores,
+            win_counter,
+        }).encode();
         for player in players.values() {
-            player.tx.send(Message::text(message.clone())).await;
+            player.tx.send_frame(Frame::Metadata, Message::text(message.clone()));
         }
-    }
-}
-
-async fn game_info_loop(game: crate::Game, eth_addr_peer_map: crate::EthAddrPeerMap) {
-    loop {
-        time::sleep(Duration::from_secs(1)).await;
-        let active_players =game.lock().unwrap().players.len();
-        let available_rewards = game.lock().unwrap().get_available_rewards();
-        let message = json!({
-            "method": "notify_game_info",
-            "params": {
-                "active_players": active_players,
-                "available_rewards": available_rewards,
+        if let Some(zone_info) = zone_info {
+            let message = Notification::Zone(zone_info).encode();
+            for player in players.values() {
+                player.tx.send_frame(Frame::Zone, Message::text(message.clone()));
             }
-        }).to_string();
-        let peer_map = eth_addr_peer_map.lock().unwrap().clone();
-        for tx in peer_map.values() {
-            tx.send(Message::text(message.clone())).await;
         }
     }
 }
 
-
-async fn win_loop(game: crate::Game, win_tx: UnboundedSender<Winner>, multiplier: u32) {
+async fn win_loop(game: crate::Game, win_tx: UnboundedSender<Winner>) {
     loop {
         time::sleep(Duration::from_secs(1)).await;
-        game.lock().unwrap().win_counter -= 1;
-        if game.lock().unwrap().win_counter == 0 {
-            game.lock().unwrap().win_counter = WIN_TIME;
-            let mut player: Option<Player> = None;
+        let (cash_outs, conversion) = {
+            let mut game = game.lock().unwrap();
+            (game.take_cash_outs(), game.conversion)
+        };
+        for (player, mass) in cash_outs {
+            let amount_cashed = conversion.mass_to_display(mass);
+            player.tx.send(Message::text(Notification::CashedOut(amount_cashed.clone()).encode_for(player.protocol_version)));
+            println!("Player cashed out. Player ID [{}] Amount [{}]", player.id, amount_cashed);
+            win_tx.send(Winner::new(
+                &player.id,
+                conversion.mass_to_tokens(mass),
+            ));
+        }
+
+        let winner_ids = game.lock().unwrap().round_tick();
+        if let Some(winner_ids) = winner_ids {
+            let conversion;
+            let win_percentage;
+            let winners: Vec<Player>;
+            let masses: Vec<u64>;
             {
                 let mut game = game.lock().unwrap();
-                if let Some(player_id) = game.get_winner() {
-                    player = game.remove_player(&player_id);
-                }
+                conversion = game.conversion;
+                win_percentage = game.config.economy.win_percentage;
+                winners = winner_ids.iter()
+                    .filter_map(|player_id| game.remove_player(player_id))
+                    .collect();
+                masses = winners.iter().map(|player| game.payable_mass(player)).collect();
             }
-            if let Some(player) = player {
-                let player_mass = player.mass();
-                let mass_won = (player_mass * WIN_PERCENTAGE).ceil();
-                let mass_remain = (player_mass - mass_won).floor() as u64;
-                game.lock().unwrap().food_stack += mass_remain;
-                let amount_won = mass_won * multiplier as f64;
-                player.tx.send(Message::text(json!({
-                    "method": "notify_won",
-                    "params": [amount_won],
-                }).to_string())).await.ok();
+            let (shares, mass_remain) = split_winnings(&masses, win_percentage);
+            game.lock().unwrap().food_stack += mass_remain;
+            for (player, mass_won) in winners.iter().zip(shares) {
+                let amount = conversion.mass_to_tokens(mass_won);
+                let amount_won = conversion.mass_to_display(mass_won);
+                player.tx.send(Message::text(Notification::Won(amount_won.clone()).encode_for(player.protocol_version)));
                 println!("Awarding player with {}", amount_won);
-                // apply 9 decimal places for erc20 contract
-                let amount = (amount_won * 1e9) as u128;
                 win_tx.send(Winner::new(
                     &player.id,
                     amount,
@@ -1070,22 +1717,39 @@ async fn win_loop(game: crate::Game, win_tx: UnboundedSender<Winner>, multiplier
     }
 }
 
+/// Splits `win_percentage` of the winners' total mass between them pro rata
+/// to their own mass. Returns each winner's share and the mass that goes
+/// back to the food stack.
+pub fn split_winnings(masses: &[u64], win_percentage: f64) -> (Vec<u64>, u64) {
+    let total: u64 = masses.iter().sum();
+    if total == 0 {
+        return (vec![0; masses.len()], 0);
+    }
+    let mass_won = ((total as f64 * win_percentage).ceil() as u64).min(total);
+    let mut shares: Vec<u64> = masses.iter()
+        .map(|&mass| (mass_won as u128 * mass as u128 / total as u128) as u64)
+        .collect();
+    // rounding leftovers go to the heaviest winner
+    let leftover = mass_won - shares.iter().sum::<u64>();
+    if let Some(heaviest) = (0..masses.len()).max_by_key(|&i| masses[i]) {
+        shares[heaviest] += leftover;
+    }
+    (shares, total - mass_won)
+}
 
-pub fn start_tasks(
-    game: crate::Game,
-    multiplier: u32,
-    eth_addr_peer_map: crate::EthAddrPeerMap,
-    client : Arc<SignerMiddleware<Provider<Ws>, Wallet<SigningKey>>>,
-    win_tx: UnboundedSender<Winner>,
-) {
-    // tokio::spawn(tick_loop(game.clone()));
-    tokio::spawn(move_loop(game.clone()));
-    tokio::spawn(player_collision_loop(game.clone()));
-    tokio::spawn(food_collision_loop(game.clone()));
-    tokio::spawn(add_food_loop(game.clone()));
-    tokio::spawn(update_loop(game.clone()));
-    tokio::spawn(food_update_loop(game.clone()));
-    tokio::spawn(metadata_update_loop(game.clone(), client.clone()));
-    tokio::spawn(game_info_loop(game.clone(), eth_addr_peer_map));
-    tokio::spawn(win_loop(game.clone(), win_tx, multiplier));
+
+/// Starts the loops that run one room, aborting the returned handles stops
+/// them.
+pub fn start_tasks(game: crate::Game, win_tx: UnboundedSender<Winner>) -> Vec<JoinHandle<()>> {
+    vec![
+        // tokio::spawn(tick_loop(game.clone())),
+        tokio::spawn(move_loop(game.clone())),
+        tokio::spawn(player_collision_loop(game.clone())),
+        tokio::spawn(food_collision_loop(game.clone())),
+        tokio::spawn(add_food_loop(game.clone())),
+        tokio::spawn(update_loop(game.clone())),
+        tokio::spawn(food_update_loop(game.clone())),
+        tokio::spawn(metadata_update_loop(game.clone())),
+        tokio::spawn(win_loop(game.clone(), win_tx)),
+    ]
 }
\ No newline at end of file
@@ -6,7 +6,10 @@ use std::{
     time::Duration,
 };
 use ethers::{abi::{ParamType, Token, decode}, prelude::*};
-use ethers::core::k256::ecdsa::SigningKey;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::chain::{ChainEvent, Client};
+use crate::token::TokenAmount;
 
 
 abigen!(
@@ -15,9 +18,18 @@ abigen!(
     event_derives(serde::Deserialize, serde::Serialize)
 );
 
+abigen!(
+    TokenContract,
+    "./data/abi/BobaToken.json",
+    event_derives(serde::Deserialize, serde::Serialize)
+);
+
+// the entry fee emits no event when it changes so it is polled
+const PARAMETERS_POLL_INTERVAL: u64 = 60; // seconds
+
 pub async fn game_pool_reward_added_listener(
     provider_http_url: String,
-    game: crate::Game,
+    events: UnboundedSender<ChainEvent>,
     game_pool_addr: String
 ) -> anyhow::Result<()> {
 
@@ -41,17 +53,28 @@ pub async fn game_pool_reward_added_listener(
 
     // get initial rewards available in server
     let amount = contract.game_pool_rewards().call().await.expect("Failed to get gamePoolRewards");
-    game.lock().unwrap().add_rewards(amount.as_u128());
+    events.send(ChainEvent::RewardsAdded(amount, None))?;
 
     // watch for rewards added events
     let filter = contract.rewards_added_filter().filter;
     let mut stream = provider.watch(&filter).await?.stream();
     while let Some(log) = stream.next().await {
+        // the event doesn't name the sender, sponsors are told apart by
+        // the transaction that emitted it
+        let sender = match log.transaction_hash {
+            Some(hash) => match provider.get_transaction(hash).await {
+                Ok(tx) => tx.map(|tx| format!("{:#x}", tx.from)),
+                Err(err) => {
+                    println!("Failed to get rewardsAdded transaction. Hash [{:#x}] Error [{}]", hash, err);
+                    None
+                }
+            },
+            None => None,
+        };
         let bytes = log.data;
         if let Ok(tokens) = decode(&[ParamType::Uint(256)], bytes.as_ref()) {
             if let Token::Uint(value) = &tokens[0] {
-                let amount = value.as_u128();
-                game.lock().unwrap().add_rewards(amount);
+                events.send(ChainEvent::RewardsAdded(*value, sender))?;
             }
         }
     }
@@ -61,41 +84,227 @@ pub async fn game_pool_reward_added_listener(
 
 
 
+/// Reads the token's decimals, retrying until the node answers. Balances
+/// are kept in mass converted with them, so this runs before anything that
+/// credits tokens.
+pub async fn token_decimals(provider_http_url: &str, token_addr: &str) -> u8 {
+    let token_addr = H160::from_str(token_addr).expect("Invalid token address format");
+    let provider = Provider::<Http>::try_from(provider_http_url).expect("Invalid http rpc endpoint");
+    let token = TokenContract::new(token_addr, Arc::new(provider));
+    loop {
+        match token.decimals().call().await {
+            Ok(decimals) => return decimals,
+            Err(err) => {
+                println!("Failed to get token decimals, will try again in 3 seconds. Error [{}]", err);
+                tokio::time::sleep(Duration::from_secs(3)).await;
+            }
+        }
+    }
+}
+
+pub async fn pool_parameters_listener(
+    provider_http_url: String,
+    events: UnboundedSender<ChainEvent>,
+    game_pool_addr: String,
+) -> anyhow::Result<()> {
+
+    let game_pool_addr = H160::from_str(&game_pool_addr).expect("Invalid game pool address format");
+
+    let provider = Provider::<Http>::try_from(provider_http_url)
+        .expect("Invalid http rpc endpoint")
+        .interval(Duration::from_secs(2u64));
+    let provider = Arc::new(provider);
+
+    let pool = GamePoolContract::new(game_pool_addr, provider.clone());
+
+    let mut entry_fee = None;
+    loop {
+        match pool.entry_fee().call().await {
+            Ok(fee) => {
+                if entry_fee != Some(fee) {
+                    entry_fee = Some(fee);
+                    events.send(ChainEvent::EntryFeeChanged(fee))?;
+                }
+            }
+            Err(err) => println!("Failed to get entryFee. Error [{}]", err),
+        }
+
+        tokio::time::sleep(Duration::from_secs(PARAMETERS_POLL_INTERVAL)).await;
+    }
+}
+
+
 pub struct Winner {
-   address: H160,
-   amount: U256,
+   pub address: H160,
+   pub amount: U256,
 }
 
 impl Winner {
-    pub fn new(address: &str, amount: u128) -> Self {
+    pub fn new(address: &str, amount: TokenAmount) -> Self {
         Winner {
             address: H160::from_str(address).unwrap(),
-            amount: U256::from(amount),
+            amount: amount.base_units(),
         }
     }
 }
 
 
+// failed and dropped awards are still owed, they are sent again after a
+// backoff doubling up to the maximum
+const AWARD_RETRY_DELAY: u64 = 5; // seconds
+const AWARD_RETRY_MAX_DELAY: u64 = 300; // seconds
+// an award not mined by then is looked at again on its next attempt, the
+// awards after it are sent meanwhile
+const AWARD_CONFIRM_TIMEOUT: u64 = 120; // seconds
+const RECEIPT_POLL_INTERVAL: u64 = 3; // seconds
+
+struct Award {
+    winner: Winner,
+    attempt: u32,
+    /// The last transaction sent for it.
+    sent: Option<Sent>,
+}
+
+#[derive(Clone, Copy)]
+struct Sent {
+    hash: TxHash,
+    nonce: U256,
+}
+
+enum TxStatus {
+    Succeeded,
+    Reverted,
+    Pending,
+    Dropped,
+}
+
+enum Progress {
+    Settled,
+    Waiting(TxHash),
+}
+
+async fn tx_status(client: &Client, hash: TxHash) -> Result<TxStatus, String> {
+    match client.get_transaction_receipt(hash).await.map_err(|err| err.to_string())? {
+        Some(receipt) if receipt.status == Some(U64::from(1)) => Ok(TxStatus::Succeeded),
+        Some(_) => Ok(TxStatus::Reverted),
+        None => match client.get_transaction(hash).await.map_err(|err| err.to_string())? {
+            Some(_) => Ok(TxStatus::Pending),
+            None => Ok(TxStatus::Dropped),
+        },
+    }
+}
+
+/// Sends `awardWinner`, unless the transaction sent for it before was mined
+/// or still may be. A dropped transaction is replaced with one of the same
+/// nonce, so at most one of them is ever mined.
+async fn send_award(contract: &GamePoolContract<Client>, client: &Client, award: &mut Award) -> Result<Progress, String> {
+    let mut nonce = None;
+    if let Some(sent) = award.sent {
+        match tx_status(client, sent.hash).await? {
+            TxStatus::Succeeded => return Ok(Progress::Settled),
+            TxStatus::Pending => return Ok(Progress::Waiting(sent.hash)),
+            // the nonce was used up by the reverted transaction
+            TxStatus::Reverted => {}
+            TxStatus::Dropped => {
+                let mined = client.get_transaction_count(client.address(), Some(BlockNumber::Latest.into())).await
+                    .map_err(|err| err.to_string())?;
+                if mined <= sent.nonce {
+                    nonce = Some(sent.nonce);
+                } else if let TxStatus::Succeeded = tx_status(client, sent.hash).await? {
+                    // mined since it was looked up
+                    return Ok(Progress::Settled);
+                }
+            }
+        }
+    }
+    let nonce = match nonce {
+        Some(nonce) => nonce,
+        None => client.get_transaction_count(client.address(), Some(BlockNumber::Pending.into())).await
+            .map_err(|err| err.to_string())?,
+    };
+
+    let call = contract.award_winner(award.winner.address, award.winner.amount).legacy().nonce(nonce);
+    let pending = call.send().await.map_err(|err| err.to_string())?;
+    award.sent = Some(Sent { hash: *pending, nonce });
+    Ok(Progress::Waiting(*pending))
+}
+
+/// Waits up to `AWARD_CONFIRM_TIMEOUT` for `hash` to be mined, `Ok` only once
+/// a receipt says it succeeded.
+async fn confirm_award(client: &Client, hash: TxHash) -> Result<(), String> {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(AWARD_CONFIRM_TIMEOUT);
+    loop {
+        match tx_status(client, hash).await? {
+            TxStatus::Succeeded => return Ok(()),
+            TxStatus::Reverted => return Err(String::from("Transaction reverted")),
+            TxStatus::Dropped => return Err(String::from("Transaction was dropped")),
+            TxStatus::Pending => {}
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(String::from("Transaction is not mined yet"));
+        }
+        tokio::time::sleep(Duration::from_secs(RECEIPT_POLL_INTERVAL)).await;
+    }
+}
+
+fn retry_award(retry_tx: &UnboundedSender<Award>, mut award: Award, err: String) {
+    let delay = (AWARD_RETRY_DELAY << award.attempt.min(16)).min(AWARD_RETRY_MAX_DELAY);
+    println!(
+        "Failed to award winner, will try again in {} seconds. Address [{:#x}] Amount [{}] Attempt [{}] Error [{}]",
+        delay, award.winner.address, award.winner.amount, award.attempt + 1, err
+    );
+    award.attempt += 1;
+    let retry_tx = retry_tx.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(delay)).await;
+        retry_tx.send(award).ok();
+    });
+}
+
 pub fn winner_listener(
     address: &str,
-    client: Arc<SignerMiddleware<Provider<Ws>, Wallet<SigningKey>>>,
+    client: Arc<Client>,
+    events: UnboundedSender<ChainEvent>,
 ) -> tokio::sync::mpsc::UnboundedSender<Winner> {
 
     let address = H160::from_str(address).expect("Game pool address is invalid");
-    let contract = GamePoolContract::new(address, client);
+    let contract = GamePoolContract::new(address, client.clone());
 
-    let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Winner>();
-    let mut stream = tokio_stream::wrappers::UnboundedReceiverStream::new(rx);
+    let (tx, mut winners) = tokio::sync::mpsc::unbounded_channel::<Winner>();
+    let (retry_tx, mut retries) = tokio::sync::mpsc::unbounded_channel::<Award>();
 
     tokio::spawn(async move {
-        while let Some(winner) = stream.next().await {
+        loop {
+            let mut award = tokio::select! {
+                Some(winner) = winners.recv() => Award { winner, attempt: 0, sent: None },
+                Some(award) = retries.recv() => award,
+                else => break,
+            };
 
-            contract.award_winner(
-                winner.address,
-                winner.amount
-            ).legacy().send().await.unwrap().await;
+            // awards are sent one at a time so each gets its own nonce, and
+            // confirmed side by side. They stay pending until settled, they
+            // are owed to the player
+            match send_award(&contract, &client, &mut award).await {
+                Ok(Progress::Settled) => {
+                    events.send(ChainEvent::PayoutSettled(TokenAmount::from(award.winner.amount))).ok();
+                }
+                Ok(Progress::Waiting(hash)) => {
+                    let client = client.clone();
+                    let events = events.clone();
+                    let retry_tx = retry_tx.clone();
+                    tokio::spawn(async move {
+                        match confirm_award(&client, hash).await {
+                            Ok(()) => {
+                                events.send(ChainEvent::PayoutSettled(TokenAmount::from(award.winner.amount))).ok();
+                            }
+                            Err(err) => retry_award(&retry_tx, award, err),
+                        }
+                    });
+                }
+                Err(err) => retry_award(&retry_tx, award, err),
+            }
         }
     });
 
     tx
-}
\ No newline at end of file
+}
@@ -6,21 +6,35 @@ use std::{
     net::SocketAddr,
 };
 
-use tokio::sync::mpsc::Sender;
-use tokio_tungstenite::tungstenite::Message;
-
 pub mod config;
 pub mod game;
 pub mod server;
 pub mod utils;
 pub mod grid;
+pub mod limits;
 pub mod crypto;
 pub mod game_pool;
 pub mod authenticate;
+pub mod bans;
+pub mod chain;
+pub mod collusion;
+pub mod token;
+pub mod admin;
+pub mod anticheat;
+pub mod monitor;
+pub mod outbox;
+pub mod protocol;
+pub mod reload;
+pub mod rooms;
+pub mod signer;
+pub mod snapshot;
+pub mod tournament;
+pub mod win;
 
 pub type Error = Box<dyn std::error::Error + Send + Sync>;
 pub type Result<T> = std::result::Result<T, Error>;
 
-pub type PeerMap = Arc<Mutex<HashMap<SocketAddr, Sender<Message>>>>;
-pub type EthAddrPeerMap = Arc<Mutex<HashMap<String, Sender<Message>>>>;
+pub type PeerMap = Arc<Mutex<HashMap<SocketAddr, outbox::Outbox>>>;
+pub type EthAddrPeerMap = Arc<Mutex<HashMap<String, outbox::Outbox>>>;
 pub type Game = Arc<Mutex<game::Game>>;
+pub type Rooms = Arc<Mutex<rooms::RoomManager>>;
@@ -0,0 +1,99 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use crate::config::ConnectionConfig;
+
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+/// Why a connection was turned away before the websocket handshake, or
+/// during it for the clients of a proxy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Refusal {
+    Banned,
+    TooManyConnections,
+    TooManyAttempts,
+    TooManyFailedAuths,
+}
+
+impl Refusal {
+    pub fn description(&self) -> &'static str {
+        match self {
+            Refusal::Banned => "ip is banned",
+            Refusal::TooManyConnections => "too many open connections",
+            Refusal::TooManyAttempts => "too many connection attempts",
+            Refusal::TooManyFailedAuths => "too many failed authentications",
+        }
+    }
+}
+
+/// Counts open connections, connection attempts and failed authentications
+/// per ip.
+#[derive(Debug, Default)]
+pub struct ConnectionLimiter {
+    open: HashMap<IpAddr, u32>,
+    // in the last minute
+    attempts: HashMap<IpAddr, VecDeque<Instant>>,
+    failed_auths: HashMap<IpAddr, VecDeque<Instant>>,
+}
+
+impl ConnectionLimiter {
+    /// Counts a connection from `ip`, unless it has to be refused. Every
+    /// accepted connection has to be `close`d.
+    pub fn open(&mut self, ip: IpAddr, now: Instant, config: &ConnectionConfig) -> Result<(), Refusal> {
+        let attempts = self.attempts.entry(ip).or_default();
+        forget_before(attempts, now);
+        attempts.push_back(now);
+        if attempts.len() > config.max_connection_attempts_per_min as usize {
+            return Err(Refusal::TooManyAttempts);
+        }
+        if let Some(failed) = self.failed_auths.get_mut(&ip) {
+            forget_before(failed, now);
+            if failed.len() >= config.max_failed_auths_per_min as usize {
+                return Err(Refusal::TooManyFailedAuths);
+            }
+        }
+        let open = self.open.entry(ip).or_default();
+        if *open >= config.max_connections_per_ip {
+            return Err(Refusal::TooManyConnections);
+        }
+        *open += 1;
+        Ok(())
+    }
+
+    pub fn close(&mut self, ip: IpAddr) {
+        if let Some(open) = self.open.get_mut(&ip) {
+            *open = open.saturating_sub(1);
+            if *open == 0 {
+                self.open.remove(&ip);
+            }
+        }
+    }
+
+    pub fn auth_failed(&mut self, ip: IpAddr, now: Instant) {
+        let failed = self.failed_auths.entry(ip).or_default();
+        forget_before(failed, now);
+        failed.push_back(now);
+    }
+
+    /// Drops the ips with nothing left in the last minute.
+    pub fn prune(&mut self, now: Instant) {
+        for times in self.attempts.values_mut().chain(self.failed_auths.values_mut()) {
+            forget_before(times, now);
+        }
+        self.attempts.retain(|_, times| !times.is_empty());
+        self.failed_auths.retain(|_, times| !times.is_empty());
+    }
+}
+
+fn forget_before(times: &mut VecDeque<Instant>, now: Instant) {
+    while let Some(first) = times.front() {
+        if now.duration_since(*first) < MINUTE {
+            break;
+        }
+        times.pop_front();
+    }
+}
@@ -1,26 +1,83 @@
+use clap::{Args, Parser, Subcommand};
 
-use dotenv;
-use tokio::net::TcpListener;
-
+use agario_rust::admin::{self, AdminArgs};
+use agario_rust::config::{Config, ConfigArgs};
 use agario_rust::server;
-use agario_rust::config;
 
-const DEV: bool = false;
 
-#[tokio::main]
-async fn main() -> agario_rust::Result<()> {
+#[derive(Debug, Parser)]
+#[clap(version, about = "Agario game server")]
+struct Cli {
+    #[clap(flatten)]
+    config: ConfigArgs,
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
 
-    if DEV {
-        dotenv::from_filename("dev.env").ok();
-    } else {
-        dotenv::dotenv().ok();
-    }
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run the game server (default)
+    Serve(ServeArgs),
+    /// Load and validate the config, then exit
+    CheckConfig(PrintArgs),
+    /// Inspect and manage the game pool contract
+    Admin(AdminArgs),
+}
 
-    let config = config::load_config();
-    let listener = TcpListener::bind(&format!("0.0.0.0:{}", config.ws_port)).await?;
-    server::run(config, listener).await;
-    Ok(())
+#[derive(Debug, Default, Args)]
+struct ServeArgs {
+    /// Address to listen on: `0.0.0.0:3000`, `[::]:3000` or `unix:/path/to.sock`
+    #[clap(long)]
+    bind: Option<String>,
+    #[clap(flatten)]
+    print: PrintArgs,
 }
 
+#[derive(Debug, Default, Args)]
+struct PrintArgs {
+    /// Print the config after all layers are applied, with secrets redacted
+    #[clap(long)]
+    print_effective_config: bool,
+}
 
+fn load_config(args: &ConfigArgs, print: &PrintArgs) -> Config {
+    let config = match args.load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprint!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    if print.print_effective_config {
+        print!("{}", config.to_redacted_toml());
+    }
+    config
+}
+
+
+#[tokio::main]
+async fn main() -> agario_rust::Result<()> {
+    let mut cli = Cli::parse();
 
+    match cli.command.take().unwrap_or_else(|| Command::Serve(ServeArgs::default())) {
+        Command::Serve(args) => {
+            if let Some(bind) = args.bind {
+                cli.config.overrides.push(format!("server.bind={}", bind));
+            }
+            let config = load_config(&cli.config, &args.print);
+            let address = config.server.bind_address()?;
+            let listener = server::bind(&address).await?;
+            println!("Listening on {}", address);
+            server::run(cli.config, config, listener).await?;
+        }
+        Command::CheckConfig(args) => {
+            load_config(&cli.config, &args);
+            println!("Configuration is valid");
+        }
+        Command::Admin(args) => {
+            let config = load_config(&cli.config, &PrintArgs::default());
+            admin::run(config, args).await?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use std::{
+    str::FromStr,
+    sync::Arc,
+};
+use ethers::prelude::*;
+use serde::Serialize;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::time::Duration;
+
+use crate::{
+    chain::{ChainEvent, Client},
+    game_pool::GamePoolContract,
+    token::TokenAmount,
+};
+
+
+const MONITOR_INTERVAL: u64 = 30; // seconds
+
+
+/// Funds the server relies on to pay winners.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct WalletStatus {
+    /// Native balance of the signing wallet, spent on gas for `awardWinner`.
+    pub signer_balance: U256,
+    /// `gamePoolRewards` of the game pool, where payouts are taken from.
+    pub pool_rewards: TokenAmount,
+}
+
+
+pub async fn wallet_monitor(
+    client: Arc<Client>,
+    game_pool_addr: String,
+    events: UnboundedSender<ChainEvent>,
+) -> anyhow::Result<()> {
+
+    let game_pool_addr = H160::from_str(&game_pool_addr).expect("Invalid game pool address format");
+    let contract = GamePoolContract::new(game_pool_addr, client.clone());
+    let signer = client.address();
+
+    loop {
+        let balance = client.get_balance(signer, None).await;
+        let rewards = contract.game_pool_rewards().call().await;
+        match (balance, rewards) {
+            (Ok(signer_balance), Ok(pool_rewards)) => {
+                events.send(ChainEvent::WalletStatus(WalletStatus {
+                    signer_balance,
+                    pool_rewards: TokenAmount::from(pool_rewards),
+                }))?;
+            }
+            (Err(err), _) => println!("Failed to get signer balance. Error [{}]", err),
+            (_, Err(err)) => println!("Failed to get gamePoolRewards. Error [{}]", err),
+        }
+
+        tokio::time::sleep(Duration::from_secs(MONITOR_INTERVAL)).await;
+    }
+}
@@ -0,0 +1,168 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Notify;
+use tokio_tungstenite::tungstenite::Message;
+
+
+/// Updates where only the newest one matters. A frame still waiting to be
+/// written is replaced by a newer frame of the same kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frame {
+    Players,
+    Food,
+    Metadata,
+    Zone,
+    GameInfo,
+}
+
+/// Depth of a client's queue and how many of its frames were replaced
+/// before they were written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutboxStats {
+    pub queued: usize,
+    pub dropped_frames: u64,
+}
+
+#[derive(Debug)]
+struct Queue {
+    // responses and notifications, written in order and never dropped
+    messages: VecDeque<Message>,
+    frames: VecDeque<(Frame, Message)>,
+    // since a frame was first replaced before being written
+    behind_since: Option<Instant>,
+    dropped_frames: u64,
+    lagging: bool,
+}
+
+#[derive(Debug)]
+struct Shared {
+    queue: Mutex<Queue>,
+    max_messages: usize,
+    max_behind: Duration,
+    ready: Notify,
+    lagged: Notify,
+}
+
+/// A client's outgoing messages.
+///
+/// Sending never waits on the client, so one slow connection cannot hold up
+/// the updates of everyone else. A client that lets `max_messages` pile up,
+/// or keeps missing frames for longer than `max_behind`, is marked as
+/// lagging and its connection is closed.
+#[derive(Debug, Clone)]
+pub struct Outbox {
+    shared: Arc<Shared>,
+}
+
+impl Outbox {
+    pub fn new(max_messages: usize, max_behind: Duration) -> Outbox {
+        Outbox {
+            shared: Arc::new(Shared {
+                queue: Mutex::new(Queue {
+                    messages: VecDeque::new(),
+                    frames: VecDeque::new(),
+                    behind_since: None,
+                    dropped_frames: 0,
+                    lagging: false,
+                }),
+                max_messages,
+                max_behind,
+                ready: Notify::new(),
+                lagged: Notify::new(),
+            }),
+        }
+    }
+
+    /// Queues a message that has to be delivered.
+    pub fn send(&self, message: Message) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        if queue.lagging {
+            return;
+        }
+        queue.messages.push_back(message);
+        if queue.messages.len() > self.shared.max_messages {
+            self.lag(&mut queue);
+            return;
+        }
+        self.shared.ready.notify_one();
+    }
+
+    /// Queues `message` as the newest `frame`, replacing an unwritten one.
+    pub fn send_frame(&self, frame: Frame, message: Message) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        if queue.lagging {
+            return;
+        }
+        match queue.frames.iter_mut().find(|(kind, _)| *kind == frame) {
+            Some((_, unsent)) => {
+                *unsent = message;
+                queue.dropped_frames += 1;
+                let now = Instant::now();
+                let behind_since = *queue.behind_since.get_or_insert(now);
+                if now.duration_since(behind_since) > self.shared.max_behind {
+                    self.lag(&mut queue);
+                    return;
+                }
+            }
+            None => queue.frames.push_back((frame, message)),
+        }
+        self.shared.ready.notify_one();
+    }
+
+    /// The next message to write, `None` once the client is lagging.
+    pub async fn next(&self) -> Option<Message> {
+        loop {
+            {
+                let mut queue = self.shared.queue.lock().unwrap();
+                if queue.lagging {
+                    return None;
+                }
+                if let Some(message) = queue.messages.pop_front() {
+                    return Some(message);
+                }
+                if let Some((_, message)) = queue.frames.pop_front() {
+                    if queue.frames.is_empty() {
+                        // caught up
+                        queue.behind_since = None;
+                    }
+                    return Some(message);
+                }
+            }
+            self.shared.ready.notified().await;
+        }
+    }
+
+    /// Resolves once the client is lagging, even while a write is stuck.
+    pub async fn lagged(&self) {
+        loop {
+            if self.is_lagging() {
+                return;
+            }
+            self.shared.lagged.notified().await;
+        }
+    }
+
+    pub fn is_lagging(&self) -> bool {
+        self.shared.queue.lock().unwrap().lagging
+    }
+
+    pub fn stats(&self) -> OutboxStats {
+        let queue = self.shared.queue.lock().unwrap();
+        OutboxStats {
+            queued: queue.messages.len() + queue.frames.len(),
+            dropped_frames: queue.dropped_frames,
+        }
+    }
+
+    fn lag(&self, queue: &mut Queue) {
+        queue.lagging = true;
+        queue.messages.clear();
+        queue.frames.clear();
+        self.shared.ready.notify_one();
+        self.shared.lagged.notify_one();
+    }
+}
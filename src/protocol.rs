@@ -0,0 +1,736 @@
+//! The game protocol, shared by the server, tests, bots and native clients.
+//!
+//! Clients talk JSON-RPC 2.0 in websocket text frames and get the world in
+//! binary frames. A connection starts with `authenticate`, which carries the
+//! `protocol_version` the client speaks. Versions the server doesn't
+//! support are refused, and so are clients that leave it out: they predate
+//! versioning and expect the unversioned `0u8`/`1u8` frames with f32
+//! positions, which no version encodes.
+//!
+//! Every message of a game session has a type here that encodes and decodes
+//! it: `ClientMessage` for what clients send, `Notification` for what the
+//! server pushes and `SnapshotFrame` for the binary world snapshots. Lobby
+//! and admin methods answer with plain JSON and are not covered.
+//!
+//! Version 2 tags `target` and `split` with a client `seq`, stamps snapshot
+//! frames with the server tick and the last input the server processed, and
+//! adds `ping` for round trips and clock sync.
+//!
+//! Version 3 sends the amount of `notify_won` as a decimal string, amounts
+//! past 2^53 base units don't survive a JSON number. Older versions still get
+//! a number.
+//!
+//! Snapshot frames, all numbers little endian:
+//!
+//! ```text
+//! frame     u8 channel (0 players, 1 food), u8 kind (0 keyframe, 1 delta), u32 seq
+//!           version 2: u32 tick, u32 seq of the last input processed
+//!           keyframe: f32 arena width, f32 arena height
+//!           delta:    u32 base seq
+//!           players channel: f32 x, f32 y, f32 visible range of the player
+//!           keyframe: u16 count, entities
+//!           delta:    u16 count, spawned or replaced entities
+//!                     u16 count, moves
+//!                     u16 count, u32 ids of despawned entities
+//! entity    u32 id, players channel: u32 owner, u16 x, u16 y, u16 radius, u16 hue
+//! move      u32 id, u16 x, u16 y, u16 radius
+//! ```
+//!
+//! Ids are kept for life, a split cell gets a new one. Owners are the
+//! player ids of `notify_owners` and `get_owners`. Positions are fractions of
+//! the arena, from 0 to 65535, radii are in eighths of a unit and hues are
+//! fractions of a full turn of the color wheel, from 0 to 65535.
+
+use std::{convert::TryInto, fmt};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::game::{TeamScore, Zone};
+
+
+/// The version this server speaks.
+pub const PROTOCOL_VERSION: u32 = 3;
+/// The oldest version this server still speaks.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+pub fn is_supported(version: u32) -> bool {
+    (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version)
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    Truncated,
+    TrailingBytes,
+    UnknownChannel(u8),
+    UnknownKind(u8),
+    InvalidJson(String),
+    UnknownMethod(String),
+    InvalidParams(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Truncated => write!(f, "frame is truncated"),
+            DecodeError::TrailingBytes => write!(f, "frame has bytes past its end"),
+            DecodeError::UnknownChannel(channel) => write!(f, "unknown channel {}", channel),
+            DecodeError::UnknownKind(kind) => write!(f, "unknown frame kind {}", kind),
+            DecodeError::InvalidJson(err) => write!(f, "invalid JSON: {}", err),
+            DecodeError::UnknownMethod(method) => write!(f, "unknown method {}", method),
+            DecodeError::InvalidParams(err) => write!(f, "invalid params: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+
+const KEYFRAME: u8 = 0;
+const DELTA: u8 = 1;
+const RADIUS_STEPS: f64 = 8.;
+const HUE_STEPS: f64 = 65536.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Players = 0,
+    Food = 1,
+}
+
+/// A cell or food as it goes on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entity {
+    pub id: u32,
+    /// Player the cell belongs to, 0 for food.
+    pub owner: u32,
+    pub x: u16,
+    pub y: u16,
+    pub radius: u16,
+    pub hue: u16,
+}
+
+/// An entity that moved or changed size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Move {
+    pub id: u32,
+    pub x: u16,
+    pub y: u16,
+    pub radius: u16,
+}
+
+/// Where the player is and how far they see, sent with player cells.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct View {
+    pub x: f32,
+    pub y: f32,
+    pub visible_range: f32,
+}
+
+/// Maps arena coordinates to what is sent and back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quantizer {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Quantizer {
+    /// `hue` is in degrees.
+    pub fn entity(&self, id: u32, owner: u32, x: f64, y: f64, radius: f64, hue: f64) -> Entity {
+        Entity {
+            id,
+            owner,
+            x: quantize(x / self.width as f64 * u16::MAX as f64),
+            y: quantize(y / self.height as f64 * u16::MAX as f64),
+            radius: quantize(radius * RADIUS_STEPS),
+            hue: (hue.rem_euclid(360.) / 360. * HUE_STEPS) as u32 as u16,
+        }
+    }
+
+    pub fn position(&self, entity: &Entity) -> (f32, f32) {
+        (
+            entity.x as f32 / u16::MAX as f32 * self.width,
+            entity.y as f32 / u16::MAX as f32 * self.height,
+        )
+    }
+
+    pub fn radius(&self, entity: &Entity) -> f32 {
+        (entity.radius as f64 / RADIUS_STEPS) as f32
+    }
+
+    /// In degrees.
+    pub fn hue(&self, entity: &Entity) -> f32 {
+        (entity.hue as f64 / HUE_STEPS * 360.) as f32
+    }
+}
+
+fn quantize(value: f64) -> u16 {
+    value.round().max(0.).min(u16::MAX as f64) as u16
+}
+
+/// Where the server was when it sent a frame, 0 before version 2.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stamp {
+    pub tick: u32,
+    /// The `seq` of the player's last input processed, 0 for none.
+    pub input: u32,
+}
+
+/// Every entity in view. Frames of the players channel always have a view.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyframe {
+    pub channel: Channel,
+    pub seq: u32,
+    pub stamp: Stamp,
+    pub arena: Quantizer,
+    pub view: Option<View>,
+    pub entities: Vec<Entity>,
+}
+
+/// What changed since frame `base`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delta {
+    pub channel: Channel,
+    pub seq: u32,
+    pub stamp: Stamp,
+    pub base: u32,
+    pub view: Option<View>,
+    /// New entities, and ones whose owner or hue changed.
+    pub spawned: Vec<Entity>,
+    pub moved: Vec<Move>,
+    pub despawned: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnapshotFrame {
+    Keyframe(Keyframe),
+    Delta(Delta),
+}
+
+impl SnapshotFrame {
+    pub fn channel(&self) -> Channel {
+        match self {
+            SnapshotFrame::Keyframe(keyframe) => keyframe.channel,
+            SnapshotFrame::Delta(delta) => delta.channel,
+        }
+    }
+
+    pub fn seq(&self) -> u32 {
+        match self {
+            SnapshotFrame::Keyframe(keyframe) => keyframe.seq,
+            SnapshotFrame::Delta(delta) => delta.seq,
+        }
+    }
+
+    pub fn stamp(&self) -> Stamp {
+        match self {
+            SnapshotFrame::Keyframe(keyframe) => keyframe.stamp,
+            SnapshotFrame::Delta(delta) => delta.stamp,
+        }
+    }
+
+    /// Lays the frame out as protocol `version` has it.
+    pub fn encode(&self, version: u32) -> Vec<u8> {
+        let mut data = vec![self.channel() as u8];
+        match self {
+            SnapshotFrame::Keyframe(keyframe) => {
+                data.push(KEYFRAME);
+                data.extend(keyframe.seq.to_le_bytes());
+                write_stamp(&mut data, version, keyframe.stamp);
+                data.extend(keyframe.arena.width.to_le_bytes());
+                data.extend(keyframe.arena.height.to_le_bytes());
+                write_view(&mut data, keyframe.channel, keyframe.view);
+                write_entities(&mut data, keyframe.channel, &keyframe.entities);
+            }
+            SnapshotFrame::Delta(delta) => {
+                data.push(DELTA);
+                data.extend(delta.seq.to_le_bytes());
+                write_stamp(&mut data, version, delta.stamp);
+                data.extend(delta.base.to_le_bytes());
+                write_view(&mut data, delta.channel, delta.view);
+                write_entities(&mut data, delta.channel, &delta.spawned);
+                data.extend((delta.moved.len() as u16).to_le_bytes());
+                for moved in &delta.moved {
+                    data.extend(moved.id.to_le_bytes());
+                    data.extend(moved.x.to_le_bytes());
+                    data.extend(moved.y.to_le_bytes());
+                    data.extend(moved.radius.to_le_bytes());
+                }
+                data.extend((delta.despawned.len() as u16).to_le_bytes());
+                for id in &delta.despawned {
+                    data.extend(id.to_le_bytes());
+                }
+            }
+        }
+        data
+    }
+
+    pub fn decode(data: &[u8], version: u32) -> Result<SnapshotFrame, DecodeError> {
+        let mut reader = Reader { data };
+        let channel = match reader.u8()? {
+            0 => Channel::Players,
+            1 => Channel::Food,
+            channel => return Err(DecodeError::UnknownChannel(channel)),
+        };
+        let kind = reader.u8()?;
+        let seq = reader.u32()?;
+        let stamp = if version >= 2 {
+            Stamp { tick: reader.u32()?, input: reader.u32()? }
+        } else {
+            Stamp::default()
+        };
+        let frame = match kind {
+            KEYFRAME => {
+                let arena = Quantizer { width: reader.f32()?, height: reader.f32()? };
+                SnapshotFrame::Keyframe(Keyframe {
+                    channel,
+                    seq,
+                    stamp,
+                    arena,
+                    view: read_view(&mut reader, channel)?,
+                    entities: read_entities(&mut reader, channel)?,
+                })
+            }
+            DELTA => {
+                let base = reader.u32()?;
+                let view = read_view(&mut reader, channel)?;
+                let spawned = read_entities(&mut reader, channel)?;
+                let moved = (0..reader.u16()?)
+                    .map(|_| Ok(Move {
+                        id: reader.u32()?,
+                        x: reader.u16()?,
+                        y: reader.u16()?,
+                        radius: reader.u16()?,
+                    }))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let despawned = (0..reader.u16()?).map(|_| reader.u32()).collect::<Result<Vec<_>, _>>()?;
+                SnapshotFrame::Delta(Delta { channel, seq, stamp, base, view, spawned, moved, despawned })
+            }
+            kind => return Err(DecodeError::UnknownKind(kind)),
+        };
+        if !reader.data.is_empty() {
+            return Err(DecodeError::TrailingBytes);
+        }
+        Ok(frame)
+    }
+}
+
+fn write_stamp(data: &mut Vec<u8>, version: u32, stamp: Stamp) {
+    if version >= 2 {
+        data.extend(stamp.tick.to_le_bytes());
+        data.extend(stamp.input.to_le_bytes());
+    }
+}
+
+fn write_view(data: &mut Vec<u8>, channel: Channel, view: Option<View>) {
+    if channel == Channel::Players {
+        let view = view.unwrap_or_default();
+        data.extend(view.x.to_le_bytes());
+        data.extend(view.y.to_le_bytes());
+        data.extend(view.visible_range.to_le_bytes());
+    }
+}
+
+fn write_entities(data: &mut Vec<u8>, channel: Channel, entities: &[Entity]) {
+    data.extend((entities.len() as u16).to_le_bytes());
+    for entity in entities {
+        data.extend(entity.id.to_le_bytes());
+        if channel == Channel::Players {
+            data.extend(entity.owner.to_le_bytes());
+        }
+        data.extend(entity.x.to_le_bytes());
+        data.extend(entity.y.to_le_bytes());
+        data.extend(entity.radius.to_le_bytes());
+        data.extend(entity.hue.to_le_bytes());
+    }
+}
+
+fn read_view(reader: &mut Reader, channel: Channel) -> Result<Option<View>, DecodeError> {
+    match channel {
+        Channel::Players => Ok(Some(View {
+            x: reader.f32()?,
+            y: reader.f32()?,
+            visible_range: reader.f32()?,
+        })),
+        Channel::Food => Ok(None),
+    }
+}
+
+fn read_entities(reader: &mut Reader, channel: Channel) -> Result<Vec<Entity>, DecodeError> {
+    (0..reader.u16()?)
+        .map(|_| Ok(Entity {
+            id: reader.u32()?,
+            owner: match channel {
+                Channel::Players => reader.u32()?,
+                Channel::Food => 0,
+            },
+            x: reader.u16()?,
+            y: reader.u16()?,
+            radius: reader.u16()?,
+            hue: reader.u16()?,
+        }))
+        .collect()
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.data.len() < len {
+            return Err(DecodeError::Truncated);
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, DecodeError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32, DecodeError> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuthParams {
+    pub address: String,
+    /// Of the message "let's play".
+    pub signature: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol_version: Option<u32>,
+}
+
+/// Where to head, relative to the player's position.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TargetParams {
+    pub x: f64,
+    pub y: f64,
+    /// Client sequence number, echoed in the stamp of later snapshots.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PingParams {
+    /// Any clock of the client's, sent back as is.
+    pub client_time: f64,
+}
+
+/// The answer to `ping`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pong {
+    pub client_time: f64,
+    /// Unix time in milliseconds.
+    pub server_time: u64,
+    /// The tick of the player's room, when they are in one.
+    pub tick: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AckSnapshotParams {
+    pub seq: u32,
+}
+
+/// What a client sends during a game session.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientMessage {
+    Authenticate(AuthParams),
+    EnterGame,
+    Target(TargetParams),
+    Split(InputParams),
+    Eject,
+    CashOut,
+    AckSnapshot(AckSnapshotParams),
+    RequestKeyframe,
+    GetOwners,
+    Ping(PingParams),
+}
+
+impl ClientMessage {
+    pub fn method(&self) -> &'static str {
+        match self {
+            ClientMessage::Authenticate(_) => "authenticate",
+            ClientMessage::EnterGame => "enter_game",
+            ClientMessage::Target(_) => "target",
+            ClientMessage::Split(_) => "split",
+            ClientMessage::Eject => "eject",
+            ClientMessage::CashOut => "cash_out",
+            ClientMessage::AckSnapshot(_) => "ack_snapshot",
+            ClientMessage::RequestKeyframe => "request_keyframe",
+            ClientMessage::GetOwners => "get_owners",
+            ClientMessage::Ping(_) => "ping",
+        }
+    }
+
+    fn params(&self) -> Value {
+        match self {
+            ClientMessage::Authenticate(params) => json!(params),
+            ClientMessage::Target(params) => json!(params),
+            ClientMessage::Split(params) => json!(params),
+            ClientMessage::AckSnapshot(params) => json!(params),
+            ClientMessage::Ping(params) => json!(params),
+            _ => json!([]),
+        }
+    }
+
+    /// A JSON-RPC request with `id`, or a notification without one.
+    pub fn encode(&self, id: Option<i64>) -> String {
+        let mut message = json!({
+            "jsonrpc": "2.0",
+            "method": self.method(),
+            "params": self.params(),
+        });
+        if let Some(id) = id {
+            message["id"] = json!(id);
+        }
+        message.to_string()
+    }
+
+    /// The message and its request id, if it has one.
+    pub fn decode(text: &str) -> Result<(Option<Value>, ClientMessage), DecodeError> {
+        let raw: RawMessage = serde_json::from_str(text)
+            .map_err(|err| DecodeError::InvalidJson(err.to_string()))?;
+        let message = match raw.method.as_str() {
+            "authenticate" => ClientMessage::Authenticate(params(raw.params)?),
+            "enter_game" => ClientMessage::EnterGame,
+            "target" => ClientMessage::Target(params(raw.params)?),
+            // sent without params before version 2
+            "split" => ClientMessage::Split(match raw.params {
+                Value::Null => InputParams::default(),
+                raw => params(raw)?,
+            }),
+            "eject" => ClientMessage::Eject,
+            "cash_out" => ClientMessage::CashOut,
+            "ack_snapshot" => ClientMessage::AckSnapshot(params(raw.params)?),
+            "request_keyframe" => ClientMessage::RequestKeyframe,
+            "get_owners" => ClientMessage::GetOwners,
+            "ping" => ClientMessage::Ping(params(raw.params)?),
+            method => return Err(DecodeError::UnknownMethod(method.to_string())),
+        };
+        Ok((raw.id, message))
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomJoined {
+    pub room_id: u32,
+    pub team: Option<u32>,
+}
+
+/// A player in the room, owner of the cells with its `id`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Owner {
+    pub id: u32,
+    pub address: String,
+    pub team: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Owners {
+    pub added: Vec<Owner>,
+    pub removed: Vec<u32>,
+    /// The player's own id, sent when they join.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub own: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    /// The top ten addresses and their mass.
+    pub scores: Vec<(String, u32)>,
+    pub teams: Vec<TeamScore>,
+    /// Seconds left until the next winner, when the win condition counts
+    /// down.
+    pub win_counter: Option<u64>,
+}
+
+/// The battle royale round, only sent in battle royale rooms.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "round", rename_all = "snake_case")]
+pub enum ZoneStatus {
+    Running {
+        zone: Zone,
+        final_zone: Zone,
+        ends_in: u64,
+    },
+    Waiting {
+        zone: Zone,
+        /// Always `None`, the round has not picked one yet.
+        final_zone: Option<Zone>,
+        players_needed: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameInfo {
+    pub active_players: usize,
+    pub available_rewards: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeldPayoutId {
+    pub payout_id: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TournamentHeat {
+    pub tournament_id: u32,
+    pub round: usize,
+    #[serde(rename = "final")]
+    pub is_final: bool,
+    pub room_id: u32,
+    /// Unix time.
+    pub ends_at: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeatOver {
+    pub tournament_id: u32,
+    pub round: usize,
+    pub mass: u64,
+    pub advanced: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Banned {
+    pub reason: String,
+    /// Unix time, banned for good when unset.
+    pub until: Option<u64>,
+}
+
+/// What the server pushes to clients, as JSON-RPC notifications.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Notification {
+    TicketsUpdate(i32),
+    RoomJoined(RoomJoined),
+    Owners(Owners),
+    UpdateMetadata(Metadata),
+    Zone(ZoneStatus),
+    GameInfo(GameInfo),
+    GameOver,
+    /// The amount won, formatted.
+    Won(String),
+    /// The amount paid out, formatted.
+    CashedOut(String),
+    CashOutCancelled,
+    PayoutHeld(HeldPayoutId),
+    PayoutRejected(HeldPayoutId),
+    TournamentHeat(TournamentHeat),
+    HeatOver(HeatOver),
+    Banned(Banned),
+}
+
+impl Notification {
+    pub fn method(&self) -> &'static str {
+        match self {
+            Notification::TicketsUpdate(_) => "notify_tickets_update",
+            Notification::RoomJoined(_) => "notify_room_joined",
+            Notification::Owners(_) => "notify_owners",
+            Notification::UpdateMetadata(_) => "notify_update_metadata",
+            Notification::Zone(_) => "notify_zone",
+            Notification::GameInfo(_) => "notify_game_info",
+            Notification::GameOver => "notify_game_over",
+            Notification::Won(_) => "notify_won",
+            Notification::CashedOut(_) => "notify_cashed_out",
+            Notification::CashOutCancelled => "notify_cash_out_cancelled",
+            Notification::PayoutHeld(_) => "notify_payout_held",
+            Notification::PayoutRejected(_) => "notify_payout_rejected",
+            Notification::TournamentHeat(_) => "notify_tournament_heat",
+            Notification::HeatOver(_) => "notify_heat_over",
+            Notification::Banned(_) => "notify_banned",
+        }
+    }
+
+    fn params(&self) -> Value {
+        match self {
+            Notification::TicketsUpdate(tickets) => json!([tickets]),
+            Notification::RoomJoined(params) => json!(params),
+            Notification::Owners(params) => json!(params),
+            Notification::UpdateMetadata(params) => json!(params),
+            Notification::Zone(params) => json!(params),
+            Notification::GameInfo(params) => json!(params),
+            Notification::GameOver | Notification::CashOutCancelled => Value::Null,
+            Notification::Won(amount) | Notification::CashedOut(amount) => json!([amount]),
+            Notification::PayoutHeld(params) | Notification::PayoutRejected(params) => json!(params),
+            Notification::TournamentHeat(params) => json!(params),
+            Notification::HeatOver(params) => json!(params),
+            Notification::Banned(params) => json!(params),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        self.encode_for(PROTOCOL_VERSION)
+    }
+
+    /// Encodes the notification for a client speaking `version`.
+    pub fn encode_for(&self, version: u32) -> String {
+        let params = match self {
+            Notification::Won(amount) if version < 3 => json!([amount.parse::<f64>().unwrap_or_default()]),
+            _ => self.params(),
+        };
+        json!({
+            "method": self.method(),
+            "params": params,
+        }).to_string()
+    }
+
+    pub fn decode(text: &str) -> Result<Notification, DecodeError> {
+        let raw: RawMessage = serde_json::from_str(text)
+            .map_err(|err| DecodeError::InvalidJson(err.to_string()))?;
+        Ok(match raw.method.as_str() {
+            "notify_tickets_update" => Notification::TicketsUpdate(params::<(i32,)>(raw.params)?.0),
+            "notify_room_joined" => Notification::RoomJoined(params(raw.params)?),
+            "notify_owners" => Notification::Owners(params(raw.params)?),
+            "notify_update_metadata" => Notification::UpdateMetadata(params(raw.params)?),
+            "notify_zone" => Notification::Zone(params(raw.params)?),
+            "notify_game_info" => Notification::GameInfo(params(raw.params)?),
+            "notify_game_over" => Notification::GameOver,
+            "notify_won" => Notification::Won(match params::<(Value,)>(raw.params)?.0 {
+                Value::String(amount) => amount,
+                Value::Number(amount) => amount.to_string(),
+                other => return Err(DecodeError::InvalidParams(format!("amount won is {}", other))),
+            }),
+            "notify_cashed_out" => Notification::CashedOut(params::<(String,)>(raw.params)?.0),
+            "notify_cash_out_cancelled" => Notification::CashOutCancelled,
+            "notify_payout_held" => Notification::PayoutHeld(params(raw.params)?),
+            "notify_payout_rejected" => Notification::PayoutRejected(params(raw.params)?),
+            "notify_tournament_heat" => Notification::TournamentHeat(params(raw.params)?),
+            "notify_heat_over" => Notification::HeatOver(params(raw.params)?),
+            "notify_banned" => Notification::Banned(params(raw.params)?),
+            method => return Err(DecodeError::UnknownMethod(method.to_string())),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct RawMessage {
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+fn params<T: DeserializeOwned>(params: Value) -> Result<T, DecodeError> {
+    serde_json::from_value(params).map_err(|err| DecodeError::InvalidParams(err.to_string()))
+}
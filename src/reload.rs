@@ -0,0 +1,88 @@
+use std::sync::Mutex;
+
+use crate::{
+    config::{Config, ConfigArgs},
+    game::GameConfig,
+};
+
+
+/// Reloads the config from the same sources the server was started with.
+///
+/// The gameplay, economy, tournament, collusion, anti_cheat and connections
+/// sections (and the admin list) take effect live, a reload that changes
+/// anything else, or a field `Config::restart_required_changes` lists, is
+/// rejected as a whole.
+/// Overrides given to an accepted reload stay applied to the ones after it.
+#[derive(Debug)]
+pub struct ConfigReloader {
+    args: ConfigArgs,
+    running: Mutex<Running>,
+}
+
+#[derive(Debug)]
+struct Running {
+    config: Config,
+    /// From accepted reloads, applied after the startup `--set` flags.
+    overrides: Vec<String>,
+}
+
+impl ConfigReloader {
+    pub fn new(args: ConfigArgs, config: Config) -> ConfigReloader {
+        ConfigReloader {
+            args,
+            running: Mutex::new(Running { config, overrides: Vec::new() }),
+        }
+    }
+
+    /// Loads the config again, with `overrides` applied after the startup
+    /// `--set` flags and those of earlier reloads, and returns the game
+    /// config to queue.
+    pub fn reload(&self, overrides: &[String]) -> Result<GameConfig, Vec<String>> {
+        let mut running = self.running.lock().unwrap();
+        let mut args = self.args.clone();
+        args.overrides.extend(running.overrides.iter().cloned());
+        args.overrides.extend(overrides.iter().cloned());
+        let config = args.load().map_err(|err| err.0)?;
+
+        let errors = running.config.restart_required_changes(&config);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        running.config = config;
+        running.overrides.extend(overrides.iter().cloned());
+        let config = &running.config;
+        Ok(GameConfig {
+            gameplay: config.gameplay.clone(),
+            economy: config.economy.clone(),
+            tournament: config.tournament.clone(),
+            collusion: config.collusion.clone(),
+            anti_cheat: config.anti_cheat.clone(),
+            connections: config.connections.clone(),
+        })
+    }
+
+    pub fn is_admin(&self, eth_address: &str) -> bool {
+        self.running.lock().unwrap().config.is_admin(eth_address)
+    }
+}
+
+
+/// Reloads the config whenever the process receives SIGHUP.
+#[cfg(unix)]
+pub async fn sighup_loop(rooms: crate::Rooms, reloader: std::sync::Arc<ConfigReloader>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(err) => {
+            println!("Failed to listen for SIGHUP, config reload is only available over RPC. Error [{}]", err);
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        match reloader.reload(&[]) {
+            Ok(config) => rooms.lock().unwrap().queue_config(config),
+            Err(errors) => println!("Config reload rejected. Errors [{}]", errors.join("; ")),
+        }
+    }
+}
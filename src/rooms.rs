@@ -0,0 +1,1482 @@
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+use ethers::prelude::{H160, U256};
+use jsonrpc_core::Value;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use tokio::time::{self, Duration};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::{
+    anticheat::InputMonitor,
+    bans::{Ban, BanList, BanTarget},
+    collusion::{CollusionDetector, HeldPayout, HeldPayouts, Join},
+    config::{AntiCheatConfig, GameplayConfig},
+    game::{self, Game, GameConfig, GameError},
+    game_pool::Winner,
+    limits::{ConnectionLimiter, Refusal},
+    monitor::WalletStatus,
+    outbox::{Frame, Outbox, OutboxStats},
+    protocol::{self, Banned, GameInfo, HeatOver, HeldPayoutId, Notification, Pong, RoomJoined, TournamentHeat},
+    token::{MassConversion, TokenAmount},
+    tournament::{self, Heat, Stage, Tournament, TournamentId},
+    win::WinConditionKind,
+};
+
+
+pub type RoomId = u32;
+
+#[derive(Debug)]
+struct Room {
+    id: RoomId,
+    game: crate::Game,
+    // set when the room was opened for a match with these tags
+    tags: MatchTags,
+    // only joinable with the invite code when set
+    private: Option<PrivateRoom>,
+    // hosts tournament heats, only the tournament lets players in
+    tournament: bool,
+    // the room's loops, aborted when it closes
+    tasks: Vec<JoinHandle<()>>,
+    // since when nobody has played or held a slot in the room
+    empty_since: Option<Instant>,
+}
+
+impl Room {
+    fn is_public(&self) -> bool {
+        self.private.is_none() && !self.tournament
+    }
+}
+
+/// Gameplay values the creator of a private room can change. Unset values
+/// follow the server config.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoomRules {
+    pub max_players: Option<u32>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub win_time: Option<u64>,
+    pub max_split_num: Option<usize>,
+    pub food_to_add_per_tick: Option<u64>,
+    pub max_food_in_game: Option<usize>,
+    pub teams: Option<u32>,
+    pub battle_royale: Option<bool>,
+    pub win_condition: Option<WinConditionKind>,
+    pub win_mass_target: Option<u64>,
+    pub hold_secs: Option<u64>,
+    pub win_map_share: Option<f64>,
+}
+
+impl RoomRules {
+    fn apply(&self, gameplay: &mut GameplayConfig) {
+        gameplay.max_players = self.max_players.unwrap_or(gameplay.max_players);
+        gameplay.width = self.width.unwrap_or(gameplay.width);
+        gameplay.height = self.height.unwrap_or(gameplay.height);
+        gameplay.win_time = self.win_time.unwrap_or(gameplay.win_time);
+        gameplay.max_split_num = self.max_split_num.unwrap_or(gameplay.max_split_num);
+        gameplay.food_to_add_per_tick = self.food_to_add_per_tick.unwrap_or(gameplay.food_to_add_per_tick);
+        gameplay.max_food_in_game = self.max_food_in_game.unwrap_or(gameplay.max_food_in_game);
+        gameplay.teams = self.teams.unwrap_or(gameplay.teams);
+        gameplay.battle_royale = self.battle_royale.unwrap_or(gameplay.battle_royale);
+        gameplay.win_condition = self.win_condition.unwrap_or(gameplay.win_condition);
+        gameplay.win_mass_target = self.win_mass_target.unwrap_or(gameplay.win_mass_target);
+        gameplay.hold_secs = self.hold_secs.unwrap_or(gameplay.hold_secs);
+        gameplay.win_map_share = self.win_map_share.unwrap_or(gameplay.win_map_share);
+    }
+}
+
+/// What `create_private_room` takes.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrivateRoomRequest {
+    pub rules: RoomRules,
+    /// Eth addresses allowed in besides the creator, anyone with the code
+    /// when empty.
+    pub members: Vec<String>,
+    /// Play without tickets. The room is only funded by rewards the creator
+    /// sends to the game pool.
+    pub free_entry: bool,
+}
+
+#[derive(Debug, Clone)]
+struct PrivateRoom {
+    invite_code: String,
+    creator: String,
+    members: HashSet<String>,
+    rules: RoomRules,
+    free_entry: bool,
+}
+
+impl PrivateRoom {
+    fn allows(&self, eth_address: &str) -> bool {
+        let eth_address = eth_address.to_lowercase();
+        self.members.is_empty() || self.creator == eth_address || self.members.contains(&eth_address)
+    }
+}
+
+fn generate_invite_code() -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(8)
+        .map(char::from)
+        .collect::<String>()
+        .to_uppercase()
+}
+
+/// Optional preferences a player matches on. An untagged room takes anyone.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct MatchTags {
+    pub region: Option<String>,
+    pub skill: Option<String>,
+}
+
+impl MatchTags {
+    fn accepts(&self, wanted: &MatchTags) -> bool {
+        fn tag_accepts(room: &Option<String>, wanted: &Option<String>) -> bool {
+            match (room, wanted) {
+                (Some(room), Some(wanted)) => room == wanted,
+                _ => true,
+            }
+        }
+        tag_accepts(&self.region, &wanted.region) && tag_accepts(&self.skill, &wanted.skill)
+    }
+
+    /// Number of tags both sides set to the same value.
+    fn matching(&self, wanted: &MatchTags) -> usize {
+        let region = self.region.is_some() && self.region == wanted.region;
+        let skill = self.skill.is_some() && self.skill == wanted.skill;
+        region as usize + skill as usize
+    }
+}
+
+/// A slot held in a room for a player between `find_match` and entering.
+#[derive(Debug, Clone, Copy)]
+struct Reservation {
+    room_id: RoomId,
+    expires: Instant,
+}
+
+/// Hosts the rooms of the server, each an independent `Game` with its own
+/// loops, food stack and win timer, and the state they share: tickets,
+/// chain parameters and payouts.
+///
+/// Every room pays out of the one game pool. Reward deposits are split
+/// evenly between the public rooms, and a new public room takes half the
+/// food stack of the best funded one, so each token is backed by exactly one
+/// room. Private rooms are only funded by their players' tickets, whose mass
+/// moves over from the public rooms when they are used there, and by
+/// deposits their creator makes. Whatever is left when a private room closes
+/// goes back to the public rooms.
+#[derive(Debug)]
+pub struct RoomManager {
+    rooms: Vec<Room>,
+    next_room_id: RoomId,
+    config: GameConfig,
+    peer_map: crate::PeerMap,
+    eth_addr_peer_map: crate::EthAddrPeerMap,
+    // room each connection last joined, the player may have died since
+    player_rooms: HashMap<SocketAddr, RoomId>,
+    address_tickets_map: HashMap<String, i32>,
+    // keyed by eth address
+    reservations: HashMap<String, Reservation>,
+    conversion: MassConversion,
+    // unknown until read from the game pool contract
+    entry_fee: Option<TokenAmount>,
+    // latest reading of the payout wallets, unknown until the monitor reports
+    wallet_status: Option<WalletStatus>,
+    // decimals the token reports, while they differ from the ones held
+    // balances were converted with
+    mismatched_decimals: Option<u8>,
+    // awarded to winners but not confirmed on chain yet
+    pending_payouts: TokenAmount,
+    // rooms report winners here, they are queued before going to the chain
+    room_win_tx: UnboundedSender<Winner>,
+    tournaments: Vec<Tournament>,
+    next_tournament_id: TournamentId,
+    collusion: CollusionDetector,
+    // payouts of flagged players, waiting for an admin
+    held_payouts: HeldPayouts,
+    next_held_payout_id: u32,
+    // approved held payouts go straight to the chain
+    win_tx: UnboundedSender<Winner>,
+    input_monitors: HashMap<SocketAddr, InputMonitor>,
+    // protocol version each authenticated connection speaks
+    protocol_versions: HashMap<SocketAddr, u32>,
+    // ip of each authenticated client, a proxy's clients aren't its socket's
+    client_ips: HashMap<SocketAddr, IpAddr>,
+    ban_list: BanList,
+    limiter: ConnectionLimiter,
+    // clients disconnected for falling behind on updates
+    lagging_disconnects: u64,
+}
+
+
+impl RoomManager {
+    /// Opens the first room and starts the tasks shared by all rooms.
+    pub fn start(
+        peer_map: crate::PeerMap,
+        eth_addr_peer_map: crate::EthAddrPeerMap,
+        config: GameConfig,
+        win_tx: UnboundedSender<Winner>,
+        ban_list: BanList,
+        held_payouts: HeldPayouts,
+    ) -> crate::Rooms {
+        let conversion = MassConversion::new(config.economy.token_decimals, config.economy.multiplier)
+            .expect("Invalid multiplier or token decimals");
+        let (room_win_tx, room_win_rx) = unbounded_channel();
+        let collusion = CollusionDetector::new(config.collusion.clone());
+        let mut manager = RoomManager {
+            rooms: Vec::new(),
+            next_room_id: 1,
+            config,
+            peer_map,
+            eth_addr_peer_map: eth_addr_peer_map.clone(),
+            player_rooms: HashMap::new(),
+            address_tickets_map: HashMap::new(),
+            reservations: HashMap::new(),
+            conversion,
+            entry_fee: None,
+            wallet_status: None,
+            mismatched_decimals: None,
+            // payouts held before a restart are still owed
+            pending_payouts: held_payouts.total(),
+            room_win_tx,
+            tournaments: Vec::new(),
+            next_tournament_id: 1,
+            collusion,
+            next_held_payout_id: held_payouts.next_id(),
+            held_payouts,
+            win_tx: win_tx.clone(),
+            input_monitors: HashMap::new(),
+            protocol_versions: HashMap::new(),
+            client_ips: HashMap::new(),
+            ban_list,
+            limiter: ConnectionLimiter::default(),
+            lagging_disconnects: 0,
+        };
+        manager.open_room(MatchTags::default(), None);
+        manager.schedule_tournaments();
+
+        let rooms = Arc::new(Mutex::new(manager));
+        tokio::spawn(payout_loop(rooms.clone(), room_win_rx, win_tx));
+        tokio::spawn(game_info_loop(rooms.clone(), eth_addr_peer_map));
+        tokio::spawn(tournament_loop(rooms.clone()));
+        tokio::spawn(collusion_loop(rooms.clone()));
+        tokio::spawn(limits_loop(rooms.clone()));
+        tokio::spawn(close_rooms_loop(rooms.clone()));
+        rooms
+    }
+
+    fn room_config(&self, private: Option<&PrivateRoom>, tournament: bool) -> GameConfig {
+        let mut config = self.config.clone();
+        if let Some(private) = private {
+            private.rules.apply(&mut config.gameplay);
+            config.economy.no_entry_fee |= private.free_entry;
+        }
+        if tournament {
+            // heats are scored by the tournament, never by the win timer
+            config.gameplay.win_condition = WinConditionKind::Timed;
+            config.gameplay.win_mass_threshold = u64::MAX;
+            config.gameplay.battle_royale = false;
+            // heat mass belongs to the prize pool
+            config.gameplay.cash_out = false;
+            config.economy.no_entry_fee = true;
+            config.economy.free_entry_min_food = 0;
+        }
+        config
+    }
+
+    fn open_room(&mut self, tags: MatchTags, private: Option<PrivateRoom>) -> RoomId {
+        let id = self.next_room_id;
+        self.next_room_id += 1;
+
+        let config = self.room_config(private.as_ref(), false);
+        let mut game = Game::new(self.peer_map.clone(), self.eth_addr_peer_map.clone(), config);
+        game.set_token_decimals(self.conversion.decimals());
+        if private.is_none() {
+            let richest = self.rooms.iter()
+                .filter(|room| room.is_public())
+                .max_by_key(|room| room.game.lock().unwrap().food_stack());
+            if let Some(richest) = richest {
+                let mut richest = richest.game.lock().unwrap();
+                let mass = richest.take_food_stack(richest.food_stack() / 2);
+                game.add_food_stack(mass);
+            }
+        }
+
+        let game = Arc::new(Mutex::new(game));
+        let tasks = game::start_tasks(game.clone(), self.room_win_tx.clone());
+        self.rooms.push(Room { id, game, tags, private, tournament: false, tasks, empty_since: None });
+        println!("Opened room. Room ID [{}] Rooms [{}]", id, self.rooms.len());
+        id
+    }
+
+    /// A room for a tournament heat, reusing one no heat is played in.
+    fn tournament_room(&mut self) -> RoomId {
+        let busy: HashSet<RoomId> = self.tournaments.iter()
+            .filter(|t| t.is_playing())
+            .flat_map(|t| t.rounds.last().into_iter().flatten().map(|heat| heat.room_id))
+            .collect();
+        let idle = self.rooms.iter()
+            .find(|room| room.tournament && !busy.contains(&room.id) && room.game.lock().unwrap().player_count() == 0);
+        if let Some(room) = idle {
+            return room.id;
+        }
+
+        let id = self.next_room_id;
+        self.next_room_id += 1;
+        let mut game = Game::new(self.peer_map.clone(), self.eth_addr_peer_map.clone(), self.room_config(None, true));
+        game.set_token_decimals(self.conversion.decimals());
+        let game = Arc::new(Mutex::new(game));
+        let tasks = game::start_tasks(game.clone(), self.room_win_tx.clone());
+        self.rooms.push(Room {
+            id,
+            game,
+            tags: MatchTags::default(),
+            private: None,
+            tournament: true,
+            tasks,
+            empty_since: None,
+        });
+        println!("Opened tournament room. Room ID [{}] Rooms [{}]", id, self.rooms.len());
+        id
+    }
+
+    /// Opens a room only joinable with the returned invite code.
+    pub fn create_private_room(&mut self, eth_address: &str, request: PrivateRoomRequest) -> Result<(RoomId, String), Vec<String>> {
+        let creator = eth_address.to_lowercase();
+        let mut errors = Vec::new();
+        if self.rooms.iter().any(|room| room.private.as_ref().map(|p| p.creator == creator).unwrap_or(false)) {
+            errors.push(String::from("You already have a private room"));
+        }
+        if !self.can_open_room() {
+            errors.push(String::from("The server cannot open more rooms"));
+        }
+
+        // rules can make a room smaller than the public ones, never larger
+        let mut gameplay = self.config.gameplay.clone();
+        request.rules.apply(&mut gameplay);
+        let limits = &self.config.gameplay;
+        if gameplay.max_players > limits.max_players {
+            errors.push(format!("rules.max_players must be at most {}", limits.max_players));
+        }
+        if gameplay.width > limits.width || gameplay.height > limits.height {
+            errors.push(format!("rules.width and rules.height must be at most {}x{}", limits.width, limits.height));
+        }
+        if gameplay.max_food_in_game > limits.max_food_in_game {
+            errors.push(format!("rules.max_food_in_game must be at most {}", limits.max_food_in_game));
+        }
+        errors.extend(gameplay.validate().into_iter().map(|e| e.replacen("gameplay.", "rules.", 1)));
+        let members: HashSet<String> = request.members.iter().map(|m| m.to_lowercase()).collect();
+        if members.iter().any(|m| H160::from_str(m).is_err()) {
+            errors.push(String::from("members must all be addresses"));
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let invite_code = loop {
+            let code = generate_invite_code();
+            if self.private_room_by_code(&code).is_none() {
+                break code;
+            }
+        };
+        let id = self.open_room(MatchTags::default(), Some(PrivateRoom {
+            invite_code: invite_code.clone(),
+            creator: creator.clone(),
+            members,
+            rules: request.rules,
+            free_entry: request.free_entry,
+        }));
+        println!("Opened private room. Room ID [{}] Creator [{}]", id, creator);
+        Ok((id, invite_code))
+    }
+
+    fn private_room_by_code(&self, invite_code: &str) -> Option<&Room> {
+        self.rooms.iter().find(|room| {
+            room.private.as_ref().map(|p| p.invite_code == invite_code).unwrap_or(false)
+        })
+    }
+
+    /// Joins the private room with `invite_code`, if the player is invited.
+    pub fn join_private_room(&mut self, addr: SocketAddr, eth_address: String, invite_code: &str) -> Result<RoomId, GameError> {
+        let room = self.private_room_by_code(&invite_code.to_uppercase()).ok_or(GameError::RoomNotFound)?;
+        if !room.private.as_ref().unwrap().allows(&eth_address) {
+            return Err(GameError::NotInvited);
+        }
+        let room_id = room.id;
+        self.enter_room(addr, eth_address, room_id)
+    }
+
+    fn room(&self, id: RoomId) -> Option<&crate::Game> {
+        self.rooms.iter().find(|room| room.id == id).map(|room| &room.game)
+    }
+
+    /// The room `addr` is playing in, if it is still alive.
+    fn room_of(&self, addr: SocketAddr) -> Option<&crate::Game> {
+        let game = self.room(*self.player_rooms.get(&addr)?)?;
+        if game.lock().unwrap().has_player(addr) {
+            Some(game)
+        } else {
+            None
+        }
+    }
+
+    /// Closes the rooms nobody has played in for `empty_room_secs`, keeping
+    /// one public room open. Their food goes to a public room that stays and
+    /// the creator of a closed private room can open another.
+    fn close_empty_rooms(&mut self) {
+        let now = Instant::now();
+        let timeout = std::time::Duration::from_secs(self.config.gameplay.empty_room_secs);
+        let reserved: HashSet<RoomId> = self.reservations.values().map(|r| r.room_id).collect();
+        for room in self.rooms.iter_mut() {
+            let empty = room.game.lock().unwrap().player_count() == 0 && !reserved.contains(&room.id);
+            room.empty_since = if empty { room.empty_since.or(Some(now)) } else { None };
+        }
+
+        let idle = |room: &Room| room.empty_since.map(|since| now.duration_since(since) >= timeout).unwrap_or(false);
+        loop {
+            let public = self.rooms.iter().filter(|room| room.is_public()).count();
+            let index = self.rooms.iter().position(|room| {
+                idle(room) && (room.private.is_some() || (room.is_public() && public > 1))
+            });
+            match index {
+                Some(index) => self.close_room(index),
+                None => break,
+            }
+        }
+    }
+
+    fn close_room(&mut self, index: usize) {
+        let room = self.rooms.remove(index);
+        for task in room.tasks.iter() {
+            task.abort();
+        }
+        let (mass, dust) = {
+            let mut game = room.game.lock().unwrap();
+            let (_, mass) = game.empty();
+            (mass, game.take_reward_dust())
+        };
+        self.return_to_public(mass);
+        if !dust.is_zero() {
+            if let Some(public) = self.rooms.iter().find(|room| room.is_public()) {
+                public.game.lock().unwrap().add_rewards(dust);
+            }
+        }
+        println!("Closed room. Room ID [{}] Rooms [{}]", room.id, self.rooms.len());
+    }
+
+    // tournament rooms come and go with the schedule and don't count
+    fn can_open_room(&self) -> bool {
+        self.rooms.iter().filter(|room| !room.tournament).count() < self.config.gameplay.max_rooms as usize
+    }
+
+    fn drop_expired_reservations(&mut self) {
+        let now = Instant::now();
+        self.reservations.retain(|_, reservation| reservation.expires > now);
+    }
+
+    /// Slots of the room held for players other than `eth_address`.
+    fn reserved_for_others(&self, room_id: RoomId, eth_address: &str) -> usize {
+        self.reservations.iter()
+            .filter(|(address, reservation)| reservation.room_id == room_id && address.as_str() != eth_address)
+            .count()
+    }
+
+    /// Like `Game::can_enter`, also counting slots reserved for others as taken.
+    fn can_enter(&self, room: &Room, eth_address: &str) -> Result<(), GameError> {
+        let game = room.game.lock().unwrap();
+        game.can_enter(eth_address)?;
+        let taken = game.player_count() + self.reserved_for_others(room.id, eth_address);
+        if taken >= game.max_players() {
+            return Err(GameError::ServerFull);
+        }
+        Ok(())
+    }
+
+    /// Picks the best room for the player and holds a slot in it for
+    /// `match_reservation_secs`.
+    ///
+    /// Rooms with matching tags come first, then the most populated rooms so
+    /// games fill up, then the ones with the most rewards left. A room is
+    /// opened for the player when none can take them.
+    pub fn find_match(&mut self, addr: SocketAddr, eth_address: &str, tags: &MatchTags) -> Result<(RoomId, u64), GameError> {
+        self.drop_expired_reservations();
+        if self.room_of(addr).is_some() {
+            return Err(GameError::PlayerAlreadyInGame);
+        }
+        // a paid ticket plays anywhere, a free entry needs a room with rewards to give
+        if !self.config.economy.no_entry_fee && self.get_available_tickets(eth_address) <= 0 {
+            return Err(GameError::NoTicketsAvailable);
+        }
+
+        let mut best = None;
+        let mut first_error = None;
+        for room in self.rooms.iter().filter(|room| room.is_public() && room.tags.accepts(tags)) {
+            match self.can_enter(room, eth_address) {
+                Ok(()) => {
+                    let game = room.game.lock().unwrap();
+                    let score = (room.tags.matching(tags), game.player_count(), game.get_available_rewards());
+                    if best.map(|(best_score, _)| score > best_score).unwrap_or(true) {
+                        best = Some((score, room.id));
+                    }
+                }
+                // a room playing a round takes players again once it's over
+                Err(GameError::ServerFull) | Err(GameError::RoundInProgress) => (),
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+        let room_id = match (best, first_error) {
+            (Some((_, room_id)), _) => room_id,
+            (None, _) if self.can_open_room() => self.open_room(tags.clone(), None),
+            (None, Some(err)) => return Err(err),
+            (None, None) => return Err(GameError::ServerFull),
+        };
+
+        let seconds = self.config.gameplay.match_reservation_secs;
+        self.reservations.insert(eth_address.to_string(), Reservation {
+            room_id,
+            expires: Instant::now() + std::time::Duration::from_secs(seconds),
+        });
+        Ok((room_id, seconds))
+    }
+
+    /// Puts the player in the room reserved for them, or else the first room
+    /// with space, opening a new room when they are all full.
+    pub fn enter_game(&mut self, addr: SocketAddr, eth_address: String) -> Result<RoomId, GameError> {
+        self.drop_expired_reservations();
+        if self.room_of(addr).is_some() {
+            return Err(GameError::PlayerAlreadyInGame);
+        }
+        let heat = self.tournaments.iter_mut()
+            .find_map(|t| t.heat_to_enter(&eth_address))
+            .map(|heat| heat.room_id);
+        if let Some(room_id) = heat {
+            return self.enter_heat(addr, eth_address, room_id);
+        }
+        if let Some(reservation) = self.reservations.get(&eth_address) {
+            let room_id = reservation.room_id;
+            return self.enter_room(addr, eth_address, room_id);
+        }
+        let mut open_room = None;
+        let mut first_error = None;
+        for room in self.rooms.iter().filter(|room| room.is_public()) {
+            let entry = self.can_enter(room, &eth_address);
+            match entry {
+                Ok(()) => {
+                    open_room = Some(room.id);
+                    break;
+                }
+                Err(GameError::ServerFull) | Err(GameError::RoundInProgress) => (),
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+        match (open_room, first_error) {
+            (Some(id), _) => self.enter_room(addr, eth_address, id),
+            (None, Some(err)) => Err(err),
+            (None, None) if self.can_open_room() => {
+                let id = self.open_room(MatchTags::default(), None);
+                self.enter_room(addr, eth_address, id)
+            }
+            (None, None) => Err(GameError::ServerFull),
+        }
+    }
+
+    /// Joins a public room by id.
+    pub fn join_room(&mut self, addr: SocketAddr, eth_address: String, room_id: RoomId) -> Result<RoomId, GameError> {
+        // private rooms are not found by id, only by invite code
+        if !self.rooms.iter().any(|room| room.id == room_id && room.is_public()) {
+            return Err(GameError::RoomNotFound);
+        }
+        self.enter_room(addr, eth_address, room_id)
+    }
+
+    fn enter_room(&mut self, addr: SocketAddr, eth_address: String, room_id: RoomId) -> Result<RoomId, GameError> {
+        self.drop_expired_reservations();
+        let room = self.rooms.iter().find(|room| room.id == room_id).ok_or(GameError::RoomNotFound)?;
+        let game = room.game.clone();
+        let tournament_room = room.tournament;
+        let private_room = room.private.is_some();
+        let no_entry_fee = self.config.economy.no_entry_fee
+            || room.tournament
+            || room.private.as_ref().map(|p| p.free_entry).unwrap_or(false);
+        if self.room_of(addr).is_some() {
+            return Err(GameError::PlayerAlreadyInGame);
+        }
+        self.can_enter(room, &eth_address)?;
+        // locks every room, so before locking this one
+        if !no_entry_fee && !self.can_honor_payouts() {
+            return Err(GameError::PayoutsPaused);
+        }
+        // tickets are credited to a public room when bought, the ticket's
+        // mass follows it into a private room
+        let ticket_mass = if !no_entry_fee && private_room && self.get_available_tickets(&eth_address) > 0 {
+            self.take_public_mass(self.config.economy.new_player_food_to_add)
+        } else {
+            0
+        };
+        let version = self.protocol_versions.get(&addr).copied().unwrap_or(protocol::MIN_PROTOCOL_VERSION);
+        let mut game = game.lock().unwrap();
+
+        let team = if no_entry_fee {
+            game.add_player(addr, eth_address.clone(), version)?
+        } else {
+            if self.get_available_tickets(&eth_address) <= 0 {
+                return Err(GameError::NoTicketsAvailable);
+            }
+            let team = match game.add_player(addr, eth_address.clone(), version) {
+                Ok(team) => team,
+                Err(err) => {
+                    // a refused player keeps their ticket, its mass goes back
+                    drop(game);
+                    self.return_to_public(ticket_mass);
+                    return Err(err);
+                }
+            };
+            game.add_food_stack(ticket_mass);
+            let remaining_tickets = self.use_ticket(&eth_address)?;
+            self.notify_player_by_id(&eth_address, Notification::TicketsUpdate(remaining_tickets));
+            team
+        };
+        self.player_rooms.insert(addr, room_id);
+        self.reservations.remove(&eth_address);
+        // heats start everyone together, that says nothing about them
+        if !tournament_room {
+            self.collusion.record_join(Join {
+                address: eth_address.clone(),
+                ip: self.client_ip(addr),
+                room_id,
+                at: tournament::unix_now(),
+            });
+        }
+        self.notify_player_by_id(&eth_address, Notification::RoomJoined(RoomJoined { room_id, team }));
+        Ok(room_id)
+    }
+
+    pub fn list_rooms(&self) -> Value {
+        let rooms: Vec<Value> = self.rooms.iter()
+            .filter(|room| room.is_public())
+            .map(|room| {
+                let game = room.game.lock().unwrap();
+                json!({
+                    "id": room.id,
+                    "players": game.player_count(),
+                    "reserved": self.reserved_for_others(room.id, ""),
+                    "region": room.tags.region,
+                    "skill": room.tags.skill,
+                    "max_players": game.max_players(),
+                    "available_rewards": game.get_available_rewards(),
+                })
+            })
+            .collect();
+        json!(rooms)
+    }
+
+    /// Inputs tagged with a `seq` count as processed whether they are
+    /// applied or dropped.
+    pub fn set_target(&mut self, addr: SocketAddr, eth_address: &str, x: f64, y: f64, seq: Option<u32>) {
+        let allowed = self.check_input(addr, eth_address, |monitor, now, config| monitor.target(x, y, now, config));
+        if let Some(game) = self.room_of(addr) {
+            let mut game = game.lock().unwrap();
+            if allowed {
+                game.set_target(addr, x, y);
+            }
+            if let Some(seq) = seq {
+                game.input_processed(addr, seq);
+            }
+        }
+    }
+
+    pub fn split(&mut self, addr: SocketAddr, eth_address: &str, seq: Option<u32>) {
+        let allowed = self.check_input(addr, eth_address, |monitor, now, config| monitor.split(now, config));
+        if let Some(game) = self.room_of(addr) {
+            let mut game = game.lock().unwrap();
+            if allowed {
+                game.split(addr);
+            }
+            if let Some(seq) = seq {
+                game.input_processed(addr, seq);
+            }
+        }
+    }
+
+    /// Runs `check` on the connection's input monitor and bans the player
+    /// once their score is over the limit. Returns whether the input can be
+    /// applied.
+    fn check_input(
+        &mut self,
+        addr: SocketAddr,
+        eth_address: &str,
+        check: impl FnOnce(&mut InputMonitor, Instant, &AntiCheatConfig) -> bool,
+    ) -> bool {
+        let now = Instant::now();
+        let config = &self.config.anti_cheat;
+        let monitor = self.input_monitors.entry(addr).or_insert_with(|| InputMonitor::new(now));
+        let allowed = check(monitor, now, config);
+        if monitor.should_ban(config) {
+            let reason = format!("Anomalous input, score {:.1}", monitor.score());
+            self.ban(addr, eth_address, &reason);
+            return false;
+        }
+        allowed
+    }
+
+    /// Takes the player out of their room, tells them why and closes their
+    /// connection. They can't log in again until the ban ends.
+    fn ban(&mut self, addr: SocketAddr, eth_address: &str, reason: &str) {
+        let until = tournament::unix_now() + self.config.anti_cheat.ban_secs;
+        println!("Banning player. Address [{}] Reason [{}] Until [{}]", eth_address, reason, until);
+        let ban = Ban {
+            target: BanTarget::Address(eth_address.to_lowercase()),
+            reason: reason.to_string(),
+            until: Some(until),
+        };
+        self.player_lost_connection(addr);
+        if let Some(tx) = self.eth_addr_peer_map.lock().unwrap().get(eth_address) {
+            kick(tx, &ban);
+        }
+        self.ban_list.add(ban);
+    }
+
+    pub fn is_banned(&self, eth_address: &str) -> bool {
+        self.ban_list.is_address_banned(eth_address, tournament::unix_now())
+    }
+
+    pub fn list_bans(&mut self) -> Value {
+        json!(self.ban_list.active(tournament::unix_now()))
+    }
+
+    /// Bans an eth address or ip range for `secs`, or for good, and closes
+    /// the connections it covers.
+    pub fn add_ban(&mut self, target: &str, reason: String, secs: Option<u64>) -> Result<(), String> {
+        let target: BanTarget = target.parse()?;
+        let ban = Ban {
+            target,
+            reason,
+            until: secs.map(|secs| tournament::unix_now() + secs),
+        };
+        println!("Ban added. Target [{}] Reason [{}] Until [{:?}]", ban.target, ban.reason, ban.until);
+        match &ban.target {
+            BanTarget::Address(address) => {
+                let peers = self.eth_addr_peer_map.lock().unwrap();
+                let tx = peers.iter().find(|(peer, _)| peer.to_lowercase() == *address).map(|(_, tx)| tx);
+                if let Some(tx) = tx {
+                    kick(tx, &ban);
+                }
+            }
+            BanTarget::IpRange(range) => {
+                for (addr, tx) in self.peer_map.lock().unwrap().iter() {
+                    if range.contains(self.client_ip(*addr)) {
+                        kick(tx, &ban);
+                    }
+                }
+            }
+        }
+        self.ban_list.add(ban);
+        Ok(())
+    }
+
+    /// Whether there was a ban to lift.
+    pub fn remove_ban(&mut self, target: &str) -> Result<bool, String> {
+        let target: BanTarget = target.parse()?;
+        let removed = self.ban_list.remove(&target);
+        if removed {
+            println!("Ban lifted. Target [{}]", target);
+        }
+        Ok(removed)
+    }
+
+    /// Counts a new connection, or says why it is refused.
+    pub fn connection_opened(&mut self, ip: IpAddr) -> Result<(), Refusal> {
+        if self.ban_list.is_ip_banned(ip, tournament::unix_now()) {
+            return Err(Refusal::Banned);
+        }
+        self.limiter.open(ip, Instant::now(), &self.config.connections)
+    }
+
+    pub fn connection_closed(&mut self, ip: IpAddr) {
+        self.limiter.close(ip);
+    }
+
+    pub fn authenticated(&mut self, addr: SocketAddr, ip: IpAddr, protocol_version: u32) {
+        self.protocol_versions.insert(addr, protocol_version);
+        self.client_ips.insert(addr, ip);
+    }
+
+    fn client_ip(&self, addr: SocketAddr) -> IpAddr {
+        self.client_ips.get(&addr).copied().unwrap_or_else(|| addr.ip())
+    }
+
+    /// Header to read the client's ip from, when `addr` is a proxy trusted
+    /// to set it.
+    pub fn real_ip_header(&self, addr: SocketAddr, unix: bool) -> Option<String> {
+        let connections = &self.config.connections;
+        let trusted = unix || connections.trusted_proxies.contains(&addr.ip());
+        connections.real_ip_header.clone().filter(|_| trusted)
+    }
+
+    pub fn auth_failed(&mut self, ip: IpAddr) {
+        self.limiter.auth_failed(ip, Instant::now());
+    }
+
+    pub fn auth_timeout(&self) -> Duration {
+        Duration::from_secs(self.config.connections.auth_timeout_secs)
+    }
+
+    /// Queue for a new connection's outgoing messages.
+    pub fn outbox(&self) -> Outbox {
+        Outbox::new(
+            self.config.connections.max_queued_messages,
+            Duration::from_secs(self.config.connections.max_behind_secs),
+        )
+    }
+
+    pub fn client_lagged(&mut self) {
+        self.lagging_disconnects += 1;
+    }
+
+    /// Queue depths of the connected clients.
+    fn outbound_stats(&self) -> Value {
+        let stats: Vec<OutboxStats> = self.peer_map.lock().unwrap().values().map(Outbox::stats).collect();
+        json!({
+            "connections": stats.len(),
+            "queued": stats.iter().map(|s| s.queued).sum::<usize>(),
+            "max_queued": stats.iter().map(|s| s.queued).max().unwrap_or(0),
+            "dropped_frames": stats.iter().map(|s| s.dropped_frames).sum::<u64>(),
+            "lagging_disconnects": self.lagging_disconnects,
+        })
+    }
+
+    pub fn eject(&self, addr: SocketAddr) {
+        if let Some(game) = self.room_of(addr) {
+            game.lock().unwrap().eject(addr);
+        }
+    }
+
+    /// Owner ids of the room the connection last joined.
+    pub fn owners(&self, addr: SocketAddr) -> Result<Value, GameError> {
+        let game = self.player_rooms.get(&addr)
+            .and_then(|room_id| self.room(*room_id))
+            .ok_or(GameError::NotInGame)?;
+        let owners = game.lock().unwrap().owners();
+        Ok(json!(owners))
+    }
+
+    pub fn ack_snapshot(&self, addr: SocketAddr, seq: u32) {
+        if let Some(game) = self.room_of(addr) {
+            game.lock().unwrap().ack_snapshot(addr, seq);
+        }
+    }
+
+    pub fn request_keyframe(&self, addr: SocketAddr) {
+        if let Some(game) = self.room_of(addr) {
+            game.lock().unwrap().request_keyframe(addr);
+        }
+    }
+
+    /// Answers a `ping`, with the tick of the connection's room.
+    pub fn pong(&self, addr: SocketAddr, client_time: f64) -> Pong {
+        Pong {
+            client_time,
+            server_time: tournament::unix_now_millis(),
+            tick: self.room_of(addr).map(|game| game.lock().unwrap().tick()),
+        }
+    }
+
+    pub fn cash_out(&self, addr: SocketAddr) -> Result<u64, GameError> {
+        let game = self.room_of(addr).ok_or(GameError::NotInGame)?;
+        game.lock().unwrap().start_cash_out(addr)
+    }
+
+    pub fn player_lost_connection(&mut self, addr: SocketAddr) {
+        self.input_monitors.remove(&addr);
+        self.protocol_versions.remove(&addr);
+        self.client_ips.remove(&addr);
+        if let Some(room_id) = self.player_rooms.remove(&addr) {
+            if let Some(game) = self.room(room_id) {
+                game.lock().unwrap().player_lost_connection(addr);
+            }
+        }
+    }
+
+    pub fn ticket_bought(&mut self, eth_address: String) {
+        // the entry fee goes to the public room the player is most likely to
+        // play in, until the ticket is used in a private room
+        let room = self.rooms.iter()
+            .find(|room| room.is_public() && !room.game.lock().unwrap().is_full())
+            .or_else(|| self.rooms.iter().find(|room| room.is_public()))
+            .expect("One public room is always open");
+        room.game.lock().unwrap().ticket_bought();
+
+        *self.address_tickets_map.entry(eth_address.clone()).or_default() += 1;
+        let tickets = *self.address_tickets_map.get(&eth_address).unwrap();
+        self.notify_player_by_id(&eth_address, Notification::TicketsUpdate(tickets));
+    }
+
+    fn use_ticket(&mut self, user: &str) -> Result<i32, GameError> {
+        let tickets = self.address_tickets_map.get_mut(user);
+        match tickets {
+            Some(tickets) => {
+                if *tickets == 0 {
+                    return Err(GameError::NoTicketsAvailable);
+                }
+                *tickets -= 1;
+                Ok(*tickets)
+            }
+            None => Err(GameError::NoTicketsAvailable)
+        }
+    }
+
+    pub fn get_available_tickets(&self, eth_address: &str) -> i32 {
+        *self.address_tickets_map.get(eth_address).unwrap_or(&0)
+    }
+
+    pub fn get_server_info(&self) -> Value {
+        let decimals = self.conversion.decimals();
+        json!({
+            "max_players": self.config.gameplay.max_players,
+            "rooms": self.rooms.len(),
+            "max_rooms": self.config.gameplay.max_rooms,
+            "entry_fee": self.entry_fee.map(|fee| fee.format(decimals)),
+            "token_decimals": decimals,
+            "reported_token_decimals": self.mismatched_decimals,
+            "signer_balance": self.wallet_status.map(|s| s.signer_balance.to_string()),
+            "pool_rewards": self.wallet_status.map(|s| s.pool_rewards),
+            "obligations": self.obligations(),
+            "pending_payouts": self.pending_payouts,
+            "accepting_entries": self.can_honor_payouts(),
+            "outbound": self.outbound_stats(),
+        })
+    }
+
+    pub fn set_wallet_status(&mut self, status: WalletStatus) {
+        let was_paying = self.can_honor_payouts();
+        self.wallet_status = Some(status);
+        let paying = self.can_honor_payouts();
+        if was_paying && !paying {
+            println!(
+                "Pausing paid entries. Signer balance [{}] Pool rewards [{}] Obligations [{}]",
+                status.signer_balance, status.pool_rewards, self.obligations()
+            );
+        } else if !was_paying && paying {
+            println!("Resuming paid entries");
+        }
+    }
+
+    fn payout_queued(&mut self, amount: TokenAmount) {
+        self.pending_payouts = self.pending_payouts + amount;
+    }
+
+    pub fn payout_settled(&mut self, amount: TokenAmount) {
+        self.pending_payouts = self.pending_payouts.saturating_sub(amount);
+    }
+
+    /// Hands the mass transfers of every room to the collusion detector.
+    fn collect_mass_transfers(&mut self) {
+        for room in self.rooms.iter() {
+            for transfer in room.game.lock().unwrap().take_mass_transfers() {
+                self.collusion.record_transfer(transfer);
+            }
+        }
+        self.collusion.forget_old(tournament::unix_now());
+    }
+
+    /// Holds the payout for review when the winner is flagged, otherwise
+    /// gives it back to be sent. A held payout stays in the obligations.
+    fn hold_if_flagged(&mut self, winner: Winner) -> Option<Winner> {
+        // transfers from the last second may be what flags the winner
+        self.collect_mass_transfers();
+        let address = format!("{:#x}", winner.address);
+        let flags = self.collusion.flags(&address);
+        if flags.is_empty() {
+            return Some(winner);
+        }
+        let id = self.next_held_payout_id;
+        self.next_held_payout_id += 1;
+        println!(
+            "Holding payout for review. Payout ID [{}] Address [{}] Amount [{}] Flags [{:?}]",
+            id, address, winner.amount, flags
+        );
+        self.notify_player_by_id(&address, Notification::PayoutHeld(HeldPayoutId { payout_id: id }));
+        self.held_payouts.add(HeldPayout {
+            id,
+            address,
+            amount: TokenAmount::from(winner.amount),
+            flags,
+            held_at: tournament::unix_now(),
+        });
+        None
+    }
+
+    pub fn list_held_payouts(&self) -> Value {
+        json!(self.held_payouts.all())
+    }
+
+    /// Sends an approved payout to the chain. A rejected payout's mass goes
+    /// back to the public rooms.
+    pub fn review_payout(&mut self, id: u32, approve: bool) -> Result<(), GameError> {
+        let payout = self.held_payouts.remove(id).ok_or(GameError::PayoutNotFound)?;
+        if approve {
+            println!("Held payout approved. Payout ID [{}] Address [{}]", id, payout.address);
+            if self.win_tx.send(Winner::new(&payout.address, payout.amount)).is_err() {
+                println!("Payout channel closed, winner was not sent to the chain");
+            }
+        } else {
+            println!("Held payout rejected. Payout ID [{}] Address [{}]", id, payout.address);
+            self.payout_settled(payout.amount);
+            let (mass, _) = self.conversion.tokens_to_mass(payout.amount);
+            self.return_to_public(mass);
+            self.notify_player_by_id(&payout.address, Notification::PayoutRejected(HeldPayoutId { payout_id: id }));
+        }
+        Ok(())
+    }
+
+    /// Tokens the pool has to cover: everything every room can pay out,
+    /// the prize pools not in play yet, plus payouts that are still in
+    /// flight.
+    pub fn obligations(&self) -> TokenAmount {
+        let prize_pools: u64 = self.tournaments.iter()
+            .filter(|t| t.stage == Stage::Registration)
+            .map(|t| t.prize_pool)
+            .sum();
+        self.rooms.iter()
+            .map(|room| room.game.lock().unwrap().obligations())
+            .fold(self.pending_payouts + self.conversion.mass_to_tokens(prize_pools), |total, room| total + room)
+    }
+
+    /// Whether the last known wallet status can cover gas for awards and all
+    /// obligations, and tokens are still converted with the decimals the
+    /// token reports. Assumed true until the monitor first reports.
+    pub fn can_honor_payouts(&self) -> bool {
+        if self.mismatched_decimals.is_some() {
+            return false;
+        }
+        match self.wallet_status {
+            Some(status) => {
+                status.signer_balance >= U256::from(self.config.economy.min_signer_balance)
+                    && status.pool_rewards >= self.obligations()
+            }
+            None => true,
+        }
+    }
+
+    pub fn set_entry_fee(&mut self, fee: TokenAmount) {
+        println!("Entry fee set. Amount [{}]", fee);
+        self.entry_fee = Some(fee);
+    }
+
+    /// Only takes effect while no tokens are held, balances converted with
+    /// the old decimals would change value. Otherwise paid entries pause
+    /// until the decimals are back or the server restarts with them.
+    pub fn set_token_decimals(&mut self, decimals: u8) {
+        if decimals == self.conversion.decimals() {
+            if self.mismatched_decimals.take().is_some() {
+                println!("Token decimals are back, resuming paid entries. Decimals [{}]", decimals);
+            }
+            return;
+        }
+        if !self.obligations().is_zero() {
+            println!(
+                "Pausing paid entries, token decimals changed while balances are held. Decimals [{}] Held with [{}]",
+                decimals, self.conversion.decimals()
+            );
+            self.mismatched_decimals = Some(decimals);
+            return;
+        }
+        match MassConversion::new(decimals, self.config.economy.multiplier) {
+            Some(conversion) => {
+                self.conversion = conversion;
+                for room in self.rooms.iter() {
+                    room.game.lock().unwrap().set_token_decimals(decimals);
+                }
+            }
+            None => println!("Ignoring unusable token decimals. Decimals [{}]", decimals),
+        }
+    }
+
+    /// Adds a reward deposit to the private room of its sender, or else
+    /// splits it evenly between the public rooms, the first room gets what
+    /// doesn't divide.
+    pub fn add_rewards(&mut self, amount: TokenAmount, sender: Option<&str>) {
+        let sponsored = sender.and_then(|sender| self.rooms.iter().find(|room| {
+            room.private.as_ref().map(|p| p.creator == sender.to_lowercase()).unwrap_or(false)
+        }));
+        if let Some(room) = sponsored {
+            println!("Sponsor rewards added. Room ID [{}] Amount [{}]", room.id, amount);
+            room.game.lock().unwrap().add_rewards(amount);
+            return;
+        }
+
+        let public: Vec<&Room> = self.rooms.iter().filter(|room| room.is_public()).collect();
+        let rooms = U256::from(public.len());
+        let share = TokenAmount::from_base_units(amount.base_units() / rooms);
+        let first_share = amount - TokenAmount::from_base_units(share.base_units() * (rooms - 1));
+        for (i, room) in public.iter().enumerate() {
+            let share = if i == 0 { first_share } else { share };
+            room.game.lock().unwrap().add_rewards(share);
+        }
+    }
+
+    /// Tokens backing the mass that is not held by any player, in all rooms.
+    pub fn reward_balance(&self) -> TokenAmount {
+        self.rooms.iter()
+            .map(|room| room.game.lock().unwrap().reward_balance())
+            .fold(TokenAmount::zero(), |total, room| total + room)
+    }
+
+    /// Queues `config` for every room and for the rooms opened from now on.
+    pub fn queue_config(&mut self, config: GameConfig) {
+        self.collusion.update_config(config.collusion.clone());
+        self.config = config;
+        self.schedule_tournaments();
+        for room in self.rooms.iter() {
+            // private rooms keep their rules on top of the new config
+            let config = self.room_config(room.private.as_ref(), room.tournament);
+            room.game.lock().unwrap().queue_config(config);
+        }
+    }
+
+    /// Adds the tournaments of the config that are still to come.
+    fn schedule_tournaments(&mut self) {
+        let now = tournament::unix_now();
+        let mut start_times = self.config.tournament.start_times.clone();
+        start_times.sort_unstable();
+        for starts_at in start_times {
+            if starts_at <= now || self.tournaments.iter().any(|t| t.starts_at == starts_at) {
+                continue;
+            }
+            let id = self.next_tournament_id;
+            self.next_tournament_id += 1;
+            self.tournaments.push(Tournament::new(id, starts_at, &self.config.tournament));
+            println!("Tournament scheduled. Tournament ID [{}] Starts at [{}]", id, starts_at);
+        }
+    }
+
+    /// Registers the player with one of their tickets, its mass goes to the
+    /// prize pool.
+    pub fn register_tournament(&mut self, eth_address: String, id: TournamentId) -> Result<(), GameError> {
+        let tournament = self.tournaments.iter().find(|t| t.id == id).ok_or(GameError::TournamentNotFound)?;
+        if !tournament.registration_open(tournament::unix_now()) {
+            return Err(GameError::RegistrationClosed);
+        }
+        if tournament.registered.contains(&eth_address) {
+            return Err(GameError::AlreadyRegistered);
+        }
+        let remaining_tickets = self.use_ticket(&eth_address)?;
+        let mass = self.take_public_mass(self.config.economy.new_player_food_to_add);
+
+        let tournament = self.tournaments.iter_mut().find(|t| t.id == id).unwrap();
+        tournament.registered.push(eth_address.clone());
+        tournament.prize_pool += mass;
+        println!("Registered for tournament. Tournament ID [{}] Player [{}]", id, eth_address);
+        self.notify_player_by_id(&eth_address, Notification::TicketsUpdate(remaining_tickets));
+        Ok(())
+    }
+
+    /// Moves up to `mass` out of the food stacks of the public rooms, the
+    /// richest first.
+    fn take_public_mass(&self, mass: u64) -> u64 {
+        let mut public: Vec<&Room> = self.rooms.iter().filter(|room| room.is_public()).collect();
+        public.sort_by_key(|room| std::cmp::Reverse(room.game.lock().unwrap().food_stack()));
+        let mut taken = 0;
+        for room in public {
+            taken += room.game.lock().unwrap().take_food_stack(mass - taken);
+        }
+        taken
+    }
+
+    fn return_to_public(&self, mass: u64) {
+        if let Some(room) = self.rooms.iter().find(|room| room.is_public()) {
+            room.game.lock().unwrap().add_food_stack(mass);
+        }
+    }
+
+    fn enter_heat(&mut self, addr: SocketAddr, eth_address: String, room_id: RoomId) -> Result<RoomId, GameError> {
+        // the mass players spawn with comes out of the prize pool
+        let room_id = self.enter_room(addr, eth_address.clone(), room_id)?;
+        if let Some(heat) = self.tournaments.iter_mut().find_map(|t| t.heat_to_enter(&eth_address)) {
+            heat.entered.insert(eth_address);
+        }
+        Ok(room_id)
+    }
+
+    fn tournament_tick(&mut self) {
+        let now = tournament::unix_now();
+        for i in 0..self.tournaments.len() {
+            let tournament = &mut self.tournaments[i];
+            match tournament.stage {
+                Stage::Scheduled if now >= tournament.registration_opens_at => {
+                    tournament.stage = Stage::Registration;
+                    println!("Tournament registration open. Tournament ID [{}]", tournament.id);
+                }
+                Stage::Registration if now >= tournament.starts_at => self.start_tournament(i),
+                Stage::Heats | Stage::Final if tournament.round_ends_at.map(|end| now >= end).unwrap_or(false) => {
+                    self.end_round(i);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn start_tournament(&mut self, i: usize) {
+        let tournament = &mut self.tournaments[i];
+        if tournament.registered.len() >= 2 {
+            let players = tournament.registered.clone();
+            self.start_round(i, players);
+            return;
+        }
+
+        println!("Tournament cancelled, not enough players. Tournament ID [{}]", tournament.id);
+        tournament.stage = Stage::Cancelled;
+        let players = tournament.registered.clone();
+        let pool = std::mem::take(&mut tournament.prize_pool);
+        for player in players {
+            *self.address_tickets_map.entry(player.clone()).or_default() += 1;
+            let tickets = self.get_available_tickets(&player);
+            self.notify_player_by_id(&player, Notification::TicketsUpdate(tickets));
+        }
+        self.return_to_public(pool);
+    }
+
+    /// Seeds `players` into heats and hands each heat room its share of the
+    /// prize pool.
+    fn start_round(&mut self, i: usize, players: Vec<String>) {
+        let seeded = tournament::seed_heats(&players, self.config.tournament.heat_size);
+        let heat_count = seeded.len() as u64;
+        let ends_at = tournament::unix_now() + self.config.tournament.heat_secs;
+        let tournament = &mut self.tournaments[i];
+        tournament.stage = if heat_count == 1 { Stage::Final } else { Stage::Heats };
+        tournament.rounds.push(Vec::new());
+        tournament.round_ends_at = Some(ends_at);
+        let (id, pool, round) = (tournament.id, tournament.prize_pool, tournament.rounds.len());
+        println!("Tournament round started. Tournament ID [{}] Round [{}] Players [{}]", id, round, players.len());
+
+        for (n, heat_players) in seeded.into_iter().enumerate() {
+            let room_id = self.tournament_room();
+            let share = pool / heat_count + if n == 0 { pool % heat_count } else { 0 };
+            self.room(room_id).unwrap().lock().unwrap().add_food_stack(share);
+            for player in heat_players.iter() {
+                self.notify_player_by_id(player, Notification::TournamentHeat(TournamentHeat {
+                    tournament_id: id,
+                    round,
+                    is_final: heat_count == 1,
+                    room_id,
+                    ends_at,
+                }));
+            }
+            self.tournaments[i].rounds.last_mut().unwrap().push(Heat {
+                room_id,
+                players: heat_players,
+                entered: HashSet::new(),
+                results: Vec::new(),
+            });
+        }
+    }
+
+    /// Scores the heats of the round by the mass players hold at the end and
+    /// plays the next round, or pays the final.
+    fn end_round(&mut self, i: usize) {
+        let heats: Vec<(RoomId, Vec<String>)> = self.tournaments[i].rounds.last().unwrap().iter()
+            .map(|heat| (heat.room_id, heat.players.clone()))
+            .collect();
+        let mut pool = 0;
+        let mut round_results = Vec::new();
+        for (room_id, players) in heats {
+            let (masses, total) = self.room(room_id).unwrap().lock().unwrap().empty();
+            pool += total;
+            let mut results: Vec<(String, u64)> = players.iter()
+                .map(|player| {
+                    let mass = masses.iter().find(|(id, _)| id == player).map(|(_, mass)| *mass).unwrap_or(0);
+                    (player.clone(), mass)
+                })
+                .collect();
+            // ties keep the seeding order
+            results.sort_by(|a, b| b.1.cmp(&a.1));
+            round_results.push(results);
+        }
+
+        let advance_per_heat = self.config.tournament.advance_per_heat;
+        let tournament = &mut self.tournaments[i];
+        tournament.prize_pool = pool;
+        let mut next_round = Vec::new();
+        for (heat, results) in tournament.rounds.last_mut().unwrap().iter_mut().zip(round_results) {
+            next_round.extend(tournament::advancing(&results, advance_per_heat));
+            heat.results = results;
+        }
+        let (id, round, is_final) = (tournament.id, tournament.rounds.len(), tournament.stage == Stage::Final);
+        for heat in tournament.rounds.last().unwrap().clone() {
+            for (player, mass) in heat.results {
+                let advanced = !is_final && next_round.contains(&player);
+                self.notify_player_by_id(&player, Notification::HeatOver(HeatOver {
+                    tournament_id: id,
+                    round,
+                    mass,
+                    advanced,
+                }));
+            }
+        }
+
+        if is_final {
+            self.pay_prizes(i);
+        } else {
+            self.start_round(i, next_round);
+        }
+    }
+
+    /// Pays the places of the final through the same payout path as room
+    /// winners, the rest of the pool goes back to the public rooms.
+    fn pay_prizes(&mut self, i: usize) {
+        let shares = tournament::prize_shares(self.tournaments[i].prize_pool, &self.config.tournament.prize_percentages);
+        let tournament = &mut self.tournaments[i];
+        let results = tournament.rounds.last().and_then(|round| round.first()).map(|heat| heat.results.clone()).unwrap_or_default();
+        // only players who played the final place
+        tournament.prizes = results.into_iter()
+            .filter(|(_, mass)| *mass > 0)
+            .zip(shares)
+            .map(|((player, _), prize)| (player, prize))
+            .collect();
+        tournament.stage = Stage::Finished;
+        tournament.round_ends_at = None;
+        let paid: u64 = tournament.prizes.iter().map(|(_, prize)| prize).sum();
+        let remain = tournament.prize_pool - paid;
+        println!("Tournament finished. Tournament ID [{}] Prize pool [{}] Paid [{}]", tournament.id, tournament.prize_pool, paid);
+
+        for (player, prize) in tournament.prizes.clone() {
+            if prize == 0 {
+                continue;
+            }
+            let amount_won = self.conversion.mass_to_display(prize);
+            self.notify_player_by_id(&player, Notification::Won(amount_won.clone()));
+            println!("Awarding tournament prize. Player [{}] Amount [{}]", player, amount_won);
+            if self.room_win_tx.send(Winner::new(&player, self.conversion.mass_to_tokens(prize))).is_err() {
+                println!("Payout channel closed, tournament prize was not paid. Player [{}]", player);
+            }
+        }
+        self.return_to_public(remain);
+    }
+
+    fn tournament_summary(&self, tournament: &Tournament) -> Value {
+        json!({
+            "id": tournament.id,
+            "starts_at": tournament.starts_at,
+            "registration_opens_at": tournament.registration_opens_at,
+            "stage": tournament.stage,
+            "players": tournament.registered.len(),
+            "prize_pool": self.conversion.mass_to_display(tournament.prize_pool),
+        })
+    }
+
+    /// Tournaments that are not over yet, soonest first.
+    pub fn list_tournaments(&self) -> Value {
+        let mut upcoming: Vec<&Tournament> = self.tournaments.iter().filter(|t| !t.is_over()).collect();
+        upcoming.sort_by_key(|t| t.starts_at);
+        json!(upcoming.into_iter().map(|t| self.tournament_summary(t)).collect::<Vec<Value>>())
+    }
+
+    /// The bracket of a tournament: every heat played so far with its
+    /// results, and the prizes once it is over.
+    pub fn get_tournament(&self, id: TournamentId) -> Result<Value, GameError> {
+        let tournament = self.tournaments.iter().find(|t| t.id == id).ok_or(GameError::TournamentNotFound)?;
+        let mut bracket = self.tournament_summary(tournament);
+        bracket["registered"] = json!(tournament.registered);
+        bracket["round_ends_at"] = json!(tournament.round_ends_at);
+        bracket["rounds"] = json!(tournament.rounds);
+        bracket["prizes"] = json!(tournament.prizes.iter()
+            .map(|(player, prize)| json!([player, self.conversion.mass_to_display(*prize)]))
+            .collect::<Vec<Value>>());
+        Ok(bracket)
+    }
+
+    fn notify_player_by_id(&self, id: &str, notification: Notification) {
+        if let Some(tx) = self.eth_addr_peer_map.lock().unwrap().get(id) {
+            tx.send(Message::text(notification.encode()));
+        }
+    }
+}
+
+
+/// Tells the connection about its ban and closes it.
+fn kick(tx: &Outbox, ban: &Ban) {
+    let message = Notification::Banned(Banned { reason: ban.reason.clone(), until: ban.until });
+    tx.send(Message::text(message.encode()));
+    tx.send(Message::Close(None));
+}
+
+
+async fn payout_loop(
+    rooms: crate::Rooms,
+    mut winners: UnboundedReceiver<Winner>,
+    win_tx: UnboundedSender<Winner>,
+) {
+    while let Some(winner) = winners.recv().await {
+        let winner = {
+            let mut rooms = rooms.lock().unwrap();
+            rooms.payout_queued(TokenAmount::from(winner.amount));
+            rooms.hold_if_flagged(winner)
+        };
+        if let Some(winner) = winner {
+            if win_tx.send(winner).is_err() {
+                println!("Payout channel closed, winner was not sent to the chain");
+            }
+        }
+    }
+}
+
+async fn close_rooms_loop(rooms: crate::Rooms) {
+    loop {
+        time::sleep(Duration::from_secs(1)).await;
+        rooms.lock().unwrap().close_empty_rooms();
+    }
+}
+
+async fn limits_loop(rooms: crate::Rooms) {
+    loop {
+        time::sleep(Duration::from_secs(60)).await;
+        rooms.lock().unwrap().limiter.prune(Instant::now());
+    }
+}
+
+async fn collusion_loop(rooms: crate::Rooms) {
+    loop {
+        time::sleep(Duration::from_secs(1)).await;
+        rooms.lock().unwrap().collect_mass_transfers();
+    }
+}
+
+async fn tournament_loop(rooms: crate::Rooms) {
+    loop {
+        time::sleep(Duration::from_secs(1)).await;
+        rooms.lock().unwrap().tournament_tick();
+    }
+}
+
+async fn game_info_loop(rooms: crate::Rooms, eth_addr_peer_map: crate::EthAddrPeerMap) {
+    loop {
+        time::sleep(Duration::from_secs(1)).await;
+        let (active_players, available_rewards) = {
+            let rooms = rooms.lock().unwrap();
+            rooms.rooms.iter().fold((0, 0), |(players, rewards), room| {
+                let game = room.game.lock().unwrap();
+                (players + game.player_count(), rewards + game.get_available_rewards())
+            })
+        };
+        let message = Notification::GameInfo(GameInfo { active_players, available_rewards }).encode();
+        let peer_map = eth_addr_peer_map.lock().unwrap().clone();
+        for tx in peer_map.values() {
+            tx.send_frame(Frame::GameInfo, Message::text(message.clone()));
+        }
+    }
+}
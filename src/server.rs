@@ -1,67 +1,282 @@
 use std::{
     env,
     collections::HashMap,
-    net::SocketAddr,
+    io,
+    net::{IpAddr, Ipv6Addr, SocketAddr},
     sync::{Arc, Mutex},
     str::FromStr,
 };
 use serde_json::json;
 use tokio::{net::{TcpListener, TcpStream}, time::timeout};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::time::{self, Duration};
-use tokio::sync::mpsc::channel;
+use tokio::sync::mpsc::UnboundedReceiver;
 
 use futures_util::{FutureExt, SinkExt, StreamExt, future, pin_mut, stream::{
         TryStreamExt,
     }};
 use jsonrpc_core::{MetaIoHandler, Metadata, Params};
-use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::{
+    Message,
+    handshake::server::{ErrorResponse, Request, Response},
+    http::StatusCode,
+};
 use ethers::prelude::*;
 
 use crate::{
-    config::Config,
+    config::{BindAddress, Config, ConfigArgs},
     game,
     authenticate,
-    crypto::entry_fee_paid_event_listener,
-    game_pool::game_pool_reward_added_listener,
+    limits::Refusal,
+    protocol::{self, AckSnapshotParams, AuthParams, InputParams, PingParams, TargetParams},
+    bans::BanList,
+    collusion::HeldPayouts,
+    chain::{self, ChainBackend, ChainEvent},
+    reload::ConfigReloader,
+    rooms::{MatchTags, PrivateRoomRequest, RoomId, RoomManager},
+    token::TokenAmount,
+    tournament::TournamentId,
 };
 
-use tokio_stream::wrappers::ReceiverStream;
+/// A bound socket the server accepts websocket connections on.
+#[derive(Debug)]
+pub enum ServerListener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl From<TcpListener> for ServerListener {
+    fn from(listener: TcpListener) -> Self {
+        ServerListener::Tcp(listener)
+    }
+}
+
+pub async fn bind(address: &BindAddress) -> io::Result<ServerListener> {
+    match address {
+        BindAddress::Tcp(addr) => Ok(ServerListener::Tcp(TcpListener::bind(addr).await?)),
+        #[cfg(unix)]
+        BindAddress::Unix(path) => {
+            use std::os::unix::fs::FileTypeExt;
+            // a socket file left behind by a previous run makes bind fail,
+            // a socket a running server still listens on and anything else
+            // at the path are left alone
+            match std::fs::symlink_metadata(path) {
+                Ok(metadata) if metadata.file_type().is_socket() => match std::os::unix::net::UnixStream::connect(path) {
+                    Ok(_) => return Err(io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        format!("{} is in use by another server", path.display()),
+                    )),
+                    Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => std::fs::remove_file(path)?,
+                    Err(err) => return Err(err),
+                },
+                Ok(_) => return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", path.display()),
+                )),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+            Ok(ServerListener::Unix(UnixListener::bind(path)?))
+        }
+        #[cfg(not(unix))]
+        BindAddress::Unix(_) => Err(io::Error::new(io::ErrorKind::Other, "Unix sockets are not supported on this platform")),
+    }
+}
+
+enum Connection {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Connection {
+    fn is_unix(&self) -> bool {
+        match self {
+            Connection::Tcp(_) => false,
+            #[cfg(unix)]
+            Connection::Unix(_) => true,
+        }
+    }
+}
+
+/// A connection counted against its ip's limits, released when dropped
+/// however the connection ends.
+struct ConnectionSlot {
+    rooms: crate::Rooms,
+    ip: IpAddr,
+}
+
+impl ConnectionSlot {
+    fn open(rooms: &crate::Rooms, ip: IpAddr) -> Result<ConnectionSlot, Refusal> {
+        rooms.lock().unwrap().connection_opened(ip)?;
+        Ok(ConnectionSlot { rooms: rooms.clone(), ip })
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        // a poisoned lock means the server is going down anyway
+        if let Ok(mut rooms) = self.rooms.lock() {
+            rooms.connection_closed(self.ip);
+        }
+    }
+}
+
+/// An authenticated client in the peer maps, taken out of them and its
+/// room when dropped however the connection ends.
+struct ConnectedPlayer {
+    rooms: crate::Rooms,
+    peer_map: crate::PeerMap,
+    eth_addr_peer_map: crate::EthAddrPeerMap,
+    addr: SocketAddr,
+    eth_address: String,
+}
+
+impl Drop for ConnectedPlayer {
+    fn drop(&mut self) {
+        println!("Lost connection with client. Socket Address [{}]", self.addr);
+        if let Ok(mut rooms) = self.rooms.lock() {
+            rooms.player_lost_connection(self.addr);
+        }
+        if let Ok(mut peer_map) = self.peer_map.lock() {
+            peer_map.remove(&self.addr);
+        }
+        if let Ok(mut eth_addr_peer_map) = self.eth_addr_peer_map.lock() {
+            eth_addr_peer_map.remove(&self.eth_address);
+        }
+    }
+}
+
+/// The client's ip as a proxy put it in `header`, the last one when it
+/// lists several as that is the one the proxy saw.
+fn forwarded_ip(request: &Request, header: &str) -> Option<IpAddr> {
+    let value = request.headers().get(header)?.to_str().ok()?;
+    value.rsplit(',').next()?.trim().parse().ok()
+}
+
+fn refused(refusal: Refusal) -> ErrorResponse {
+    let mut response = ErrorResponse::new(Some(refusal.description().to_string()));
+    *response.status_mut() = match refusal {
+        Refusal::Banned => StatusCode::FORBIDDEN,
+        _ => StatusCode::TOO_MANY_REQUESTS,
+    };
+    response
+}
 
 #[derive(Debug)]
 struct Listener {
-    listener: TcpListener,
+    listener: ServerListener,
+    unix_connections: u64,
     handler: Arc<MetaIoHandler<Meta>>,
     peer_map: crate::PeerMap,
     eth_addr_peer_map: crate::EthAddrPeerMap,
-    game: crate::Game,
+    rooms: crate::Rooms,
 }
 
-async fn handle_connection(
-    game: crate::Game,
+async fn handle_connection<S>(
+    rooms: crate::Rooms,
     handler: Arc<MetaIoHandler<Meta>>,
     peer_map: crate::PeerMap,
     eth_addr_peer_map: crate::EthAddrPeerMap,
-    stream: TcpStream,
-    addr: SocketAddr
-) {
-    let ws_stream = tokio_tungstenite::accept_async(stream)
-        .await
-        .expect("failed to establish websocket connection");
+    stream: S,
+    addr: SocketAddr,
+    real_ip_header: Option<String>,
+) where S: AsyncRead + AsyncWrite + Unpin {
+    // refused connections are dropped before the handshake, a proxy's
+    // clients once the request says who they are
+    let mut slot = None;
+    if real_ip_header.is_none() {
+        match ConnectionSlot::open(&rooms, addr.ip()) {
+            Ok(opened) => slot = Some(opened),
+            Err(refusal) => {
+                println!("Refusing connection. Addr [{}] Reason [{}]", addr, refusal.description());
+                return;
+            }
+        }
+    }
+    let auth_timeout = rooms.lock().unwrap().auth_timeout();
+    let callback = |request: &Request, response: Response| {
+        if slot.is_some() {
+            return Ok(response);
+        }
+        let ip = real_ip_header.as_deref().and_then(|header| forwarded_ip(request, header)).unwrap_or_else(|| addr.ip());
+        match ConnectionSlot::open(&rooms, ip) {
+            Ok(opened) => {
+                slot = Some(opened);
+                Ok(response)
+            }
+            Err(refusal) => {
+                println!("Refusing connection. Addr [{}] IP [{}] Reason [{}]", addr, ip, refusal.description());
+                Err(refused(refusal))
+            }
+        }
+    };
+    let handshake = timeout(auth_timeout, tokio_tungstenite::accept_hdr_async(stream, callback)).await;
+    let ws_stream = match handshake {
+        Ok(Ok(ws_stream)) => ws_stream,
+        Ok(Err(err)) => {
+            println!("Websocket handshake failed. Addr [{}] Error [{}]", addr, err);
+            if let Some(slot) = &slot {
+                rooms.lock().unwrap().auth_failed(slot.ip);
+            }
+            return;
+        }
+        Err(_) => {
+            println!("Websocket handshake timed out. Addr [{}]", addr);
+            if let Some(slot) = &slot {
+                rooms.lock().unwrap().auth_failed(slot.ip);
+            }
+            return;
+        }
+    };
+    let ip = match &slot {
+        Some(slot) => slot.ip,
+        None => return,
+    };
 
     let mut eth_address = String::new();
+    let mut protocol_version = protocol::MIN_PROTOCOL_VERSION;
     let (mut outgoing, mut incoming) = ws_stream.split();
-    let (tx, rx) = channel(128);
-    let rx = ReceiverStream::new(rx);
+    let tx = rooms.lock().unwrap().outbox();
 
     let mut authenticated = false;
-    let res = timeout(Duration::from_secs(3), incoming.next()).await;
+    let res = timeout(auth_timeout, incoming.next()).await;
     match res {
         Ok(msg) => {
-            if let Some(msg) = msg {
-                if let Ok(msg) = msg.unwrap().into_text() {
+            if let Some(Ok(msg)) = msg {
+                if let Ok(msg) = msg.into_text() {
                     if let Ok(request) = serde_json::from_str::<AuthRequest>(&msg) {
                         let response;
-                        if eth_addr_peer_map.lock().unwrap().contains_key(&request.params.address) {
+                        // clients from before versioning expect frames no
+                        // version encodes any more, they are refused too
+                        let version = request.params.protocol_version.filter(|&version| protocol::is_supported(version));
+                        if version.is_none() {
+                            response = json!({
+                                "id": request.id,
+                                "jsonrpc": "2.0",
+                                "error": jsonrpc_core::Error {
+                                    code: jsonrpc_core::ErrorCode::ServerError(1000),
+                                    message: String::from("Unsupported protocol version"),
+                                    data: Some(json!({
+                                        "min": protocol::MIN_PROTOCOL_VERSION,
+                                        "max": protocol::PROTOCOL_VERSION,
+                                    })),
+                                },
+                            });
+                        } else if rooms.lock().unwrap().is_banned(&request.params.address) {
+                            response = json!({
+                                "id": request.id,
+                                "jsonrpc": "2.0",
+                                "error": jsonrpc_core::Error {
+                                    code: jsonrpc_core::ErrorCode::ServerError(1000),
+                                    message: String::from("This address is banned"),
+                                    data: None,
+                                },
+                            });
+                        } else if eth_addr_peer_map.lock().unwrap().contains_key(&request.params.address) {
                             // check if address is already logged in
                             response = json!({
                                 "id": request.id,
@@ -77,10 +292,11 @@ async fn handle_connection(
                             authenticated = authenticate::authenticate(&request.params.address, &request.params.signature);
                             if authenticated {
                                 eth_address = request.params.address;
+                                protocol_version = version.unwrap_or(protocol::MIN_PROTOCOL_VERSION);
                                 response = json!({
                                     "id": request.id,
                                     "jsonrpc": "2.0",
-                                    "result": serde_json::Value::Null,
+                                    "result": { "protocol_version": protocol::PROTOCOL_VERSION },
                                 });
                             } else {
                                 response = json!({
@@ -101,109 +317,153 @@ async fn handle_connection(
         }
         Err(_) => {
             println!("Connection authentication timed out. Addr [{}]", addr);
+            rooms.lock().unwrap().auth_failed(ip);
             return;
         }
     }
 
     if authenticated {
+        rooms.lock().unwrap().authenticated(addr, ip, protocol_version);
         peer_map.lock().unwrap().insert(addr, tx.clone());
         eth_addr_peer_map.lock().unwrap().insert(eth_address.clone(), tx.clone());
+        let _player = ConnectedPlayer {
+            rooms: rooms.clone(),
+            peer_map: peer_map.clone(),
+            eth_addr_peer_map: eth_addr_peer_map.clone(),
+            addr,
+            eth_address: eth_address.clone(),
+        };
 
         let incoming_future = incoming.try_for_each(|msg| async {
-            let msg = msg.into_text().unwrap(); // TODO: handle when message is not text
+            let msg = match msg {
+                // tungstenite answers pings itself
+                Message::Ping(_) | Message::Pong(_) | Message::Close(_) => return Ok(()),
+                msg => match msg.into_text() {
+                    Ok(msg) => msg,
+                    Err(err) => {
+                        println!("Ignoring message that is not text. Addr [{}] Error [{}]", addr, err);
+                        return Ok(());
+                    }
+                },
+            };
+            // the connection is closing, nothing more gets in
+            if rooms.lock().unwrap().is_banned(&eth_address) {
+                return Ok(());
+            }
             // println!("Received message: {}", msg);
             let response = handler.handle_request(&msg, Meta(Some(addr), eth_address.clone())).await;
             if let Some(result) = response {
                 // println!("Sending response {}", result);
-                tx.send(Message::text(result)).await;
+                tx.send(Message::text(result));
             }
             Ok(())
         });
 
-        let outgoing_future = rx.map(Ok).forward(outgoing);
-        pin_mut!(incoming_future, outgoing_future);
-        future::select(outgoing_future, incoming_future).await;
-
-        println!("Lost connection with client. Socket Address [{}]", addr);
-        game.lock().unwrap().player_lost_connection(addr);
-        peer_map.lock().unwrap().remove(&addr);
-        eth_addr_peer_map.lock().unwrap().remove(&eth_address);
+        let outgoing_future = async {
+            while let Some(message) = tx.next().await {
+                if outgoing.send(message).await.is_err() {
+                    break;
+                }
+            }
+        };
+        // a write to a stalled client can block for good
+        let lagged_future = tx.lagged();
+        pin_mut!(incoming_future, outgoing_future, lagged_future);
+        future::select(future::select(outgoing_future, incoming_future), lagged_future).await;
+
+        if tx.is_lagging() {
+            println!("Disconnecting slow client. Socket Address [{}]", addr);
+            rooms.lock().unwrap().client_lagged();
+        }
     } else {
         println!("Authentication failed for client. Socket Address [{}]", addr);
+        rooms.lock().unwrap().auth_failed(ip);
     }
 
 }
 
 
-pub async fn run(config: Config, listener: TcpListener) -> crate::Result<()> {
-    let ws = loop {
-        if let Ok(ws_) = Ws::connect(&config.provider_ws_url).await {
-            println!("Connected to provider");
-            break ws_;
-        } else {
-            println!("Failed to connect provider. Will attemp again in 3 seconds");
-            tokio::time::sleep(Duration::from_secs(3)).await;
-        }
+/// Runs the server with `config`, loaded from `args` so it can be reloaded.
+pub async fn run(args: ConfigArgs, config: Config, listener: ServerListener) -> crate::Result<()> {
+    let backend = chain::connect(&config).await;
+    let game_config = game::GameConfig {
+        gameplay: config.gameplay.clone(),
+        economy: config.economy.clone(),
+        tournament: config.tournament.clone(),
+        collusion: config.collusion.clone(),
+        anti_cheat: config.anti_cheat.clone(),
+        connections: config.connections.clone(),
     };
-    let provider = Provider::new(ws).interval(Duration::from_millis(2000));
-
-    let wallet = Wallet::from_str(&config.secret_key).expect("SECRET KEY is not valid").with_chain_id(config.chain_id);
-    let client = SignerMiddleware::new(provider, wallet);
-    let client = Arc::new(client);
+    let reloader = Arc::new(ConfigReloader::new(args, config));
+    serve(game_config, listener, backend, Some(reloader)).await
+}
 
 
+/// Runs the game server on `listener` against the given chain backend.
+///
+/// Without a reloader the config is fixed and `reload_config` is refused.
+pub async fn serve(
+    game_config: game::GameConfig,
+    listener: impl Into<ServerListener>,
+    backend: ChainBackend,
+    reloader: Option<Arc<ConfigReloader>>,
+) -> crate::Result<()> {
+    let ban_list = BanList::load(game_config.connections.ban_list_path.clone())?;
+    let held_payouts = HeldPayouts::load(game_config.collusion.held_payouts_path.clone())?;
     let peer_map = Arc::new(Mutex::new(HashMap::new()));
     let eth_addr_peer_map = Arc::new(Mutex::new(HashMap::new()));
-    let game = Arc::new(Mutex::new(game::Game::new(
+    let rooms = RoomManager::start(
         peer_map.clone(),
         eth_addr_peer_map.clone(),
-        config.no_entry_fee,
-        config.multiplier as u32,
-    )));
-
-  if !config.no_entry_fee {
-    tokio::spawn(
-        entry_fee_paid_event_listener(
-            config.fee_manager_address.clone(),
-            config.game_pool_address.clone(),
-            client.clone(),
-            game.clone(),
-        )
-    );
-  }
-
-    tokio::spawn(
-        game_pool_reward_added_listener(
-            config.provider_http_url.clone(),
-            game.clone(),
-            config.game_pool_address.clone(),
-        )
-    );
-
-    let win_tx = crate::game_pool::winner_listener(
-        &config.game_pool_address,
-        client.clone(),
+        game_config,
+        backend.win_tx,
+        ban_list,
+        held_payouts,
     );
 
-    game::start_tasks(
-        game.clone(),
-        config.multiplier,
-        eth_addr_peer_map.clone(),
-        client.clone(),
-        win_tx,
-    );
+    tokio::spawn(chain_event_loop(rooms.clone(), backend.events));
+    #[cfg(unix)]
+    if let Some(reloader) = &reloader {
+        tokio::spawn(crate::reload::sighup_loop(rooms.clone(), reloader.clone()));
+    }
 
     let mut server = Listener {
-        listener,
-        handler: Arc::new(create_handler(game.clone())),
+        listener: listener.into(),
+        unix_connections: 0,
+        handler: Arc::new(create_handler(rooms.clone(), reloader)),
         peer_map,
         eth_addr_peer_map,
-        game,
+        rooms,
     };
 
-    server.run().await;
+    server.run().await
+}
+
 
-    Ok(())
+async fn chain_event_loop(rooms: crate::Rooms, mut events: UnboundedReceiver<ChainEvent>) {
+    while let Some(event) = events.recv().await {
+        match event {
+            ChainEvent::EntryFeePaid(eth_address) => {
+                rooms.lock().unwrap().ticket_bought(eth_address);
+            }
+            ChainEvent::RewardsAdded(amount, sender) => {
+                rooms.lock().unwrap().add_rewards(TokenAmount::from(amount), sender.as_deref());
+            }
+            ChainEvent::EntryFeeChanged(fee) => {
+                rooms.lock().unwrap().set_entry_fee(TokenAmount::from(fee));
+            }
+            ChainEvent::TokenDecimalsChanged(decimals) => {
+                rooms.lock().unwrap().set_token_decimals(decimals);
+            }
+            ChainEvent::WalletStatus(status) => {
+                rooms.lock().unwrap().set_wallet_status(status);
+            }
+            ChainEvent::PayoutSettled(amount) => {
+                rooms.lock().unwrap().payout_settled(amount);
+            }
+        }
+    }
+    println!("Chain event stream closed");
 }
 
 
@@ -211,25 +471,49 @@ impl Listener {
     async fn run(&mut self) -> crate::Result<()> {
 
         loop {
-            let (stream, addr) = self.accept().await?;
-            tokio::spawn(
-                handle_connection(
-                    self.game.clone(),
-                    self.handler.clone(),
-                    self.peer_map.clone(),
-                    self.eth_addr_peer_map.clone(),
-                    stream,
-                    addr,
-                )
-            );
+            let (connection, addr) = self.accept().await?;
+            let real_ip_header = self.rooms.lock().unwrap().real_ip_header(addr, connection.is_unix());
+            let rooms = self.rooms.clone();
+            let handler = self.handler.clone();
+            let peer_map = self.peer_map.clone();
+            let eth_addr_peer_map = self.eth_addr_peer_map.clone();
+            tokio::spawn(async move {
+                match connection {
+                    Connection::Tcp(stream) => {
+                        handle_connection(rooms, handler, peer_map, eth_addr_peer_map, stream, addr, real_ip_header).await
+                    }
+                    #[cfg(unix)]
+                    Connection::Unix(stream) => {
+                        handle_connection(rooms, handler, peer_map, eth_addr_peer_map, stream, addr, real_ip_header).await
+                    }
+                }
+            });
         }
     }
 
-    async fn accept(&mut self) -> crate::Result<(TcpStream, SocketAddr)> {
+    // Unix socket peers have no address. Give each connection a unique one
+    // from the fd00::/8 unique local range so it can key the peer maps.
+    fn next_unix_peer_addr(&mut self) -> SocketAddr {
+        self.unix_connections += 1;
+        let ip = Ipv6Addr::from((0xfd00u128 << 112) | self.unix_connections as u128);
+        SocketAddr::from((ip, 0))
+    }
+
+    async fn accept(&mut self) -> crate::Result<(Connection, SocketAddr)> {
         let mut backoff = 1;
         loop {
-            match self.listener.accept().await {
-                Ok((stream, addr)) => return Ok((stream, addr)),
+            let accepted = match &self.listener {
+                ServerListener::Tcp(listener) => listener.accept().await
+                    .map(|(stream, addr)| (Connection::Tcp(stream), Some(addr))),
+                #[cfg(unix)]
+                ServerListener::Unix(listener) => listener.accept().await
+                    .map(|(stream, _)| (Connection::Unix(stream), None)),
+            };
+            match accepted {
+                Ok((connection, addr)) => {
+                    let addr = addr.unwrap_or_else(|| self.next_unix_peer_addr());
+                    return Ok((connection, addr));
+                }
                 Err(err) => {
                     if backoff > 64 {
                         return Err(err.into());
@@ -246,10 +530,45 @@ impl Listener {
 use serde::{Deserialize};
 
 
+
+/// A public room by id, or a private one by invite code.
+#[derive(Deserialize)]
+struct JoinRoomParams {
+    room_id: Option<RoomId>,
+    invite_code: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TournamentParams {
+    tournament_id: TournamentId,
+}
+
+
+#[derive(Deserialize)]
+struct ReloadConfigParams {
+    #[serde(default)]
+    set: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct AddBanParams {
+    /// Eth address, ip or ip range.
+    target: String,
+    #[serde(default)]
+    reason: String,
+    /// For good when unset.
+    secs: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct RemoveBanParams {
+    target: String,
+}
+
 #[derive(Deserialize)]
-struct SetTargetParams {
-    x: f64,
-    y: f64,
+struct ReviewPayoutParams {
+    payout_id: u32,
+    approve: bool,
 }
 
 
@@ -258,59 +577,316 @@ struct Meta(Option<SocketAddr>, String);
 impl Metadata for Meta {}
 
 
-fn create_handler(game: crate::Game) -> MetaIoHandler<Meta> {
+fn is_admin(reloader: &Option<Arc<ConfigReloader>>, eth_address: &str) -> bool {
+    reloader.as_ref().map(|reloader| reloader.is_admin(eth_address)).unwrap_or(false)
+}
+
+fn not_allowed(message: &str) -> jsonrpc_core::Error {
+    jsonrpc_core::Error {
+        code: jsonrpc_core::ErrorCode::ServerError(1001),
+        message: message.to_string(),
+        data: None,
+    }
+}
+
+fn room_error(err: game::GameError) -> jsonrpc_core::Error {
+    jsonrpc_core::Error {
+        code: jsonrpc_core::ErrorCode::ServerError(1000),
+        message: err.description(),
+        data: None,
+    }
+}
+
+
+fn create_handler(rooms: crate::Rooms, reloader: Option<Arc<ConfigReloader>>) -> MetaIoHandler<Meta> {
     let mut io = MetaIoHandler::default();
 
-    let local_game = game.clone();
+    let local_rooms = rooms.clone();
     io.add_method_with_meta("enter_game", move |_params: Params, meta: Meta| {
-        let mut local_game = local_game.lock().unwrap();
-        let res = local_game.enter_game(meta.0.unwrap(), meta.1);
+        let mut local_rooms = local_rooms.lock().unwrap();
+        let res = local_rooms.enter_game(meta.0.unwrap(), meta.1);
+        match res {
+            Ok(_) => future::ok(jsonrpc_core::Value::Null),
+            Err(err) => future::err(room_error(err)),
+        }
+    });
+
+    let local_rooms = rooms.clone();
+    io.add_method_with_meta("list_rooms", move |_params: Params, _meta: Meta| {
+        let local_rooms = local_rooms.lock().unwrap();
+        future::ok(local_rooms.list_rooms())
+    });
+
+    let local_rooms = rooms.clone();
+    io.add_method_with_meta("list_tournaments", move |_params: Params, _meta: Meta| {
+        let local_rooms = local_rooms.lock().unwrap();
+        future::ok(local_rooms.list_tournaments())
+    });
+
+    let local_rooms = rooms.clone();
+    io.add_method_with_meta("get_tournament", move |params: Params, _meta: Meta| {
+        let parsed = match params.parse::<TournamentParams>() {
+            Ok(parsed) => parsed,
+            Err(err) => return future::err(err),
+        };
+        let local_rooms = local_rooms.lock().unwrap();
+        match local_rooms.get_tournament(parsed.tournament_id) {
+            Ok(bracket) => future::ok(bracket),
+            Err(err) => future::err(room_error(err)),
+        }
+    });
+
+    let local_rooms = rooms.clone();
+    io.add_method_with_meta("register_tournament", move |params: Params, meta: Meta| {
+        let parsed = match params.parse::<TournamentParams>() {
+            Ok(parsed) => parsed,
+            Err(err) => return future::err(err),
+        };
+        let mut local_rooms = local_rooms.lock().unwrap();
+        match local_rooms.register_tournament(meta.1, parsed.tournament_id) {
+            Ok(()) => future::ok(jsonrpc_core::Value::Null),
+            Err(err) => future::err(room_error(err)),
+        }
+    });
+
+    let local_rooms = rooms.clone();
+    io.add_method_with_meta("find_match", move |params: Params, meta: Meta| {
+        let tags = match params {
+            Params::None => MatchTags::default(),
+            Params::Array(values) if values.is_empty() => MatchTags::default(),
+            params => match params.parse::<MatchTags>() {
+                Ok(tags) => tags,
+                Err(err) => return future::err(err),
+            },
+        };
+        let mut local_rooms = local_rooms.lock().unwrap();
+        match local_rooms.find_match(meta.0.unwrap(), &meta.1, &tags) {
+            Ok((room_id, expires_in)) => future::ok(json!({
+                "room_id": room_id,
+                "expires_in": expires_in,
+            })),
+            Err(err) => future::err(room_error(err)),
+        }
+    });
+
+    let local_rooms = rooms.clone();
+    io.add_method_with_meta("join_room", move |params: Params, meta: Meta| {
+        let parsed = match params.parse::<JoinRoomParams>() {
+            Ok(parsed) => parsed,
+            Err(err) => return future::err(err),
+        };
+        let mut local_rooms = local_rooms.lock().unwrap();
+        let res = match (parsed.room_id, parsed.invite_code) {
+            (Some(room_id), None) => local_rooms.join_room(meta.0.unwrap(), meta.1, room_id),
+            (None, Some(invite_code)) => local_rooms.join_private_room(meta.0.unwrap(), meta.1, &invite_code),
+            _ => return future::err(jsonrpc_core::Error::invalid_params("expected one of room_id and invite_code")),
+        };
         match res {
             Ok(_) => future::ok(jsonrpc_core::Value::Null),
-            Err(err) => future::err(jsonrpc_core::Error {
-                code: jsonrpc_core::ErrorCode::ServerError(1000),
-                message: err.description(),
-                data: None,
-            })
+            Err(err) => future::err(room_error(err)),
+        }
+    });
+
+    let local_rooms = rooms.clone();
+    io.add_method_with_meta("create_private_room", move |params: Params, meta: Meta| {
+        let request = match params {
+            Params::None => PrivateRoomRequest::default(),
+            Params::Array(values) if values.is_empty() => PrivateRoomRequest::default(),
+            params => match params.parse::<PrivateRoomRequest>() {
+                Ok(request) => request,
+                Err(err) => return future::err(err),
+            },
+        };
+        let mut local_rooms = local_rooms.lock().unwrap();
+        match local_rooms.create_private_room(&meta.1, request) {
+            Ok((room_id, invite_code)) => future::ok(json!({
+                "room_id": room_id,
+                "invite_code": invite_code,
+            })),
+            Err(errors) => future::err(jsonrpc_core::Error {
+                code: jsonrpc_core::ErrorCode::InvalidParams,
+                message: String::from("Cannot create the private room"),
+                data: Some(json!(errors)),
+            }),
         }
     });
 
-    let local_game = game.clone();
+    let local_rooms = rooms.clone();
     io.add_method_with_meta("get_available_tickets", move |_params: Params, meta: Meta| {
-        let local_game = local_game.lock().unwrap();
-        let res = local_game.get_available_tickets(&meta.1);
+        let local_rooms = local_rooms.lock().unwrap();
+        let res = local_rooms.get_available_tickets(&meta.1);
         future::ok(json!(res))
     });
 
-    let local_game = game.clone();
-    io.add_method_with_meta("get_server_info", move |_params: Params, meta: Meta| {
-        let local_game = local_game.lock().unwrap();
-        let res = local_game.get_server_info();
-        future::ok(json!(res))
+    let local_rooms = rooms.clone();
+    io.add_method_with_meta("get_server_info", move |_params: Params, _meta: Meta| {
+        let local_rooms = local_rooms.lock().unwrap();
+        future::ok(local_rooms.get_server_info())
     });
 
-    let local_game = game.clone();
+    let local_rooms = rooms.clone();
     io.add_notification_with_meta("target", move |params: Params, meta: Meta| {
-        if let Ok(parsed) = params.parse::<SetTargetParams>() {
-            let mut local_game = local_game.lock().unwrap();
-            local_game.set_target(meta.0.unwrap(), parsed.x, parsed.y);
+        if let Ok(parsed) = params.parse::<TargetParams>() {
+            let mut local_rooms = local_rooms.lock().unwrap();
+            local_rooms.set_target(meta.0.unwrap(), &meta.1, parsed.x, parsed.y, parsed.seq);
         }
 
     });
 
-    let local_game = game.clone();
-    io.add_notification_with_meta("split", move |_params: Params, meta: Meta| {
-        let mut local_game = local_game.lock().unwrap();
-        local_game.split(meta.0.unwrap());
+    let local_rooms = rooms.clone();
+    io.add_notification_with_meta("split", move |params: Params, meta: Meta| {
+        // no params before protocol version 2
+        let seq = params.parse::<InputParams>().ok().and_then(|parsed| parsed.seq);
+        let mut local_rooms = local_rooms.lock().unwrap();
+        local_rooms.split(meta.0.unwrap(), &meta.1, seq);
     });
 
-    io
-}
+    let local_rooms = rooms.clone();
+    io.add_notification_with_meta("eject", move |_params: Params, meta: Meta| {
+        let local_rooms = local_rooms.lock().unwrap();
+        local_rooms.eject(meta.0.unwrap());
+    });
 
-#[derive(Debug, Deserialize)]
-struct AuthRequestParams {
-    signature: String,
-    address: String,
+    let local_rooms = rooms.clone();
+    io.add_method_with_meta("get_owners", move |_params: Params, meta: Meta| {
+        let local_rooms = local_rooms.lock().unwrap();
+        match local_rooms.owners(meta.0.unwrap()) {
+            Ok(owners) => future::ok(owners),
+            Err(err) => future::err(room_error(err)),
+        }
+    });
+
+    let local_rooms = rooms.clone();
+    io.add_method_with_meta("ping", move |params: Params, meta: Meta| {
+        let parsed = match params.parse::<PingParams>() {
+            Ok(parsed) => parsed,
+            Err(err) => return future::err(err),
+        };
+        let local_rooms = local_rooms.lock().unwrap();
+        future::ok(json!(local_rooms.pong(meta.0.unwrap(), parsed.client_time)))
+    });
+
+    let local_rooms = rooms.clone();
+    io.add_notification_with_meta("ack_snapshot", move |params: Params, meta: Meta| {
+        if let Ok(parsed) = params.parse::<AckSnapshotParams>() {
+            let local_rooms = local_rooms.lock().unwrap();
+            local_rooms.ack_snapshot(meta.0.unwrap(), parsed.seq);
+        }
+    });
+
+    let local_rooms = rooms.clone();
+    io.add_notification_with_meta("request_keyframe", move |_params: Params, meta: Meta| {
+        let local_rooms = local_rooms.lock().unwrap();
+        local_rooms.request_keyframe(meta.0.unwrap());
+    });
+
+    let local_rooms = rooms.clone();
+    io.add_method_with_meta("cash_out", move |_params: Params, meta: Meta| {
+        let local_rooms = local_rooms.lock().unwrap();
+        match local_rooms.cash_out(meta.0.unwrap()) {
+            Ok(ready_in) => future::ok(json!({ "ready_in": ready_in })),
+            Err(err) => future::err(room_error(err)),
+        }
+    });
+
+    let local_rooms = rooms.clone();
+    let local_reloader = reloader.clone();
+    io.add_method_with_meta("list_held_payouts", move |_params: Params, meta: Meta| {
+        if !is_admin(&local_reloader, &meta.1) {
+            return future::err(not_allowed("Not allowed to review payouts"));
+        }
+        future::ok(local_rooms.lock().unwrap().list_held_payouts())
+    });
+
+    let local_rooms = rooms.clone();
+    let local_reloader = reloader.clone();
+    io.add_method_with_meta("review_payout", move |params: Params, meta: Meta| {
+        if !is_admin(&local_reloader, &meta.1) {
+            return future::err(not_allowed("Not allowed to review payouts"));
+        }
+        let parsed = match params.parse::<ReviewPayoutParams>() {
+            Ok(parsed) => parsed,
+            Err(err) => return future::err(err),
+        };
+        let mut local_rooms = local_rooms.lock().unwrap();
+        match local_rooms.review_payout(parsed.payout_id, parsed.approve) {
+            Ok(()) => future::ok(jsonrpc_core::Value::Null),
+            Err(err) => future::err(room_error(err)),
+        }
+    });
+
+    let local_rooms = rooms.clone();
+    let local_reloader = reloader.clone();
+    io.add_method_with_meta("list_bans", move |_params: Params, meta: Meta| {
+        if !is_admin(&local_reloader, &meta.1) {
+            return future::err(not_allowed("Not allowed to manage bans"));
+        }
+        future::ok(local_rooms.lock().unwrap().list_bans())
+    });
+
+    let local_rooms = rooms.clone();
+    let local_reloader = reloader.clone();
+    io.add_method_with_meta("add_ban", move |params: Params, meta: Meta| {
+        if !is_admin(&local_reloader, &meta.1) {
+            return future::err(not_allowed("Not allowed to manage bans"));
+        }
+        let parsed = match params.parse::<AddBanParams>() {
+            Ok(parsed) => parsed,
+            Err(err) => return future::err(err),
+        };
+        let mut local_rooms = local_rooms.lock().unwrap();
+        match local_rooms.add_ban(&parsed.target, parsed.reason, parsed.secs) {
+            Ok(()) => future::ok(jsonrpc_core::Value::Null),
+            Err(message) => future::err(jsonrpc_core::Error::invalid_params(message)),
+        }
+    });
+
+    let local_rooms = rooms.clone();
+    let local_reloader = reloader.clone();
+    io.add_method_with_meta("remove_ban", move |params: Params, meta: Meta| {
+        if !is_admin(&local_reloader, &meta.1) {
+            return future::err(not_allowed("Not allowed to manage bans"));
+        }
+        let parsed = match params.parse::<RemoveBanParams>() {
+            Ok(parsed) => parsed,
+            Err(err) => return future::err(err),
+        };
+        let mut local_rooms = local_rooms.lock().unwrap();
+        match local_rooms.remove_ban(&parsed.target) {
+            Ok(removed) => future::ok(json!({ "removed": removed })),
+            Err(message) => future::err(jsonrpc_core::Error::invalid_params(message)),
+        }
+    });
+
+    let local_rooms = rooms.clone();
+    io.add_method_with_meta("reload_config", move |params: Params, meta: Meta| {
+        let reloader = match &reloader {
+            Some(reloader) if reloader.is_admin(&meta.1) => reloader,
+            _ => return future::err(not_allowed("Not allowed to reload the config")),
+        };
+        let overrides = match params {
+            Params::None => Vec::new(),
+            Params::Array(values) if values.is_empty() => Vec::new(),
+            params => match params.parse::<ReloadConfigParams>() {
+                Ok(parsed) => parsed.set,
+                Err(err) => return future::err(err),
+            },
+        };
+        match reloader.reload(&overrides) {
+            Ok(config) => {
+                println!("Config reload requested. Address [{}]", meta.1);
+                local_rooms.lock().unwrap().queue_config(config);
+                future::ok(jsonrpc_core::Value::Null)
+            }
+            Err(errors) => future::err(jsonrpc_core::Error {
+                code: jsonrpc_core::ErrorCode::ServerError(1002),
+                message: String::from("Config reload rejected"),
+                data: Some(json!(errors)),
+            }),
+        }
+    });
+
+    io
 }
 
 #[derive(Debug, Deserialize)]
@@ -318,7 +894,7 @@ struct AuthRequest {
     id: i32,
     jsonrpc: String,
     method: String,
-    params: AuthRequestParams
+    params: AuthParams
 }
 
 
@@ -0,0 +1,264 @@
+use std::{
+    fmt,
+    fs,
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use ethers::prelude::*;
+use ethers::types::transaction::{eip2718::TypedTransaction, eip712::Eip712};
+use futures_util::{SinkExt, StreamExt};
+use jsonrpc_core::{IoHandler, Params};
+use serde_json::json;
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::config::ChainConfig;
+
+
+/// The wallet the server signs its transactions with.
+///
+/// Neither variant holds the key in a form `Debug` would print.
+#[derive(Debug, Clone)]
+pub enum ServerSigner {
+    Local(LocalWallet),
+    Remote(RemoteSigner),
+}
+
+#[derive(Debug)]
+pub enum SignerError {
+    Local(WalletError),
+    Remote(ProviderError),
+    InvalidSignature(SignatureError),
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for SignerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignerError::Local(err) => write!(f, "{}", err),
+            SignerError::Remote(err) => write!(f, "remote signer: {}", err),
+            SignerError::InvalidSignature(err) => write!(f, "remote signer returned an invalid signature: {}", err),
+            SignerError::Unsupported(what) => write!(f, "remote signer does not support {}", what),
+        }
+    }
+}
+
+impl std::error::Error for SignerError {}
+
+/// Loads the signer picked in `config`: the remote signer, the keystore or
+/// the raw secret key, in that order.
+pub async fn load(config: &ChainConfig) -> anyhow::Result<ServerSigner> {
+    let chain_id = config.chain_id;
+    if let Some(url) = &config.remote_signer_url {
+        let signer = RemoteSigner::connect(url).await?;
+        return Ok(ServerSigner::Remote(signer.with_chain_id(chain_id)));
+    }
+    let wallet = match &config.keystore_path {
+        Some(path) => {
+            let password = read_password(config.keystore_password_file.as_deref())?;
+            LocalWallet::decrypt_keystore(path, password)
+                .map_err(|err| anyhow!("Failed to decrypt keystore {}: {}", path.display(), err))?
+        }
+        // the parse error is not shown, it could echo part of the key
+        None => LocalWallet::from_str(config.secret_key.expose())
+            .map_err(|_| anyhow!("chain.secret_key is not a valid private key"))?,
+    };
+    Ok(ServerSigner::Local(wallet.with_chain_id(chain_id)))
+}
+
+/// Reads a keystore password from `file`, or prompts for it when there is none.
+pub fn read_password(file: Option<&Path>) -> anyhow::Result<String> {
+    match file {
+        Some(path) => {
+            let password = fs::read_to_string(path)
+                .map_err(|err| anyhow!("Failed to read {}: {}", path.display(), err))?;
+            Ok(password.trim_end_matches(&['\r', '\n'][..]).to_string())
+        }
+        None => Ok(rpassword::prompt_password_stderr("Keystore password: ")?),
+    }
+}
+
+#[async_trait]
+impl Signer for ServerSigner {
+    type Error = SignerError;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(&self, message: S) -> Result<Signature, SignerError> {
+        match self {
+            ServerSigner::Local(wallet) => wallet.sign_message(message).await.map_err(SignerError::Local),
+            ServerSigner::Remote(signer) => signer.sign_message(message.as_ref()).await,
+        }
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, SignerError> {
+        match self {
+            ServerSigner::Local(wallet) => wallet.sign_transaction(tx).await.map_err(SignerError::Local),
+            ServerSigner::Remote(signer) => signer.sign_transaction(tx).await,
+        }
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(&self, payload: &T) -> Result<Signature, SignerError> {
+        match self {
+            ServerSigner::Local(wallet) => wallet.sign_typed_data(payload).await.map_err(SignerError::Local),
+            ServerSigner::Remote(_) => Err(SignerError::Unsupported("typed data")),
+        }
+    }
+
+    fn address(&self) -> Address {
+        match self {
+            ServerSigner::Local(wallet) => wallet.address(),
+            ServerSigner::Remote(signer) => signer.address,
+        }
+    }
+
+    fn chain_id(&self) -> u64 {
+        match self {
+            ServerSigner::Local(wallet) => wallet.chain_id(),
+            ServerSigner::Remote(signer) => signer.chain_id,
+        }
+    }
+
+    fn with_chain_id<T: Into<u64>>(self, chain_id: T) -> Self {
+        match self {
+            ServerSigner::Local(wallet) => ServerSigner::Local(wallet.with_chain_id(chain_id)),
+            ServerSigner::Remote(signer) => ServerSigner::Remote(signer.with_chain_id(chain_id)),
+        }
+    }
+}
+
+
+/// Signs through another process over JSON-RPC on a websocket, so the key
+/// never has to be in the game server's memory. The other side implements:
+///
+/// * `signer_address()` -> the signing address.
+/// * `signer_signTransaction(tx)` -> hex signature of a typed transaction,
+///   for the chain id set on `tx`.
+/// * `signer_signMessage(data)` -> hex signature of the hex `data`, with the
+///   usual `personal_sign` prefix.
+///
+/// `serve` is a stand-in implementation backed by a local wallet.
+#[derive(Debug, Clone)]
+pub struct RemoteSigner {
+    provider: Provider<Ws>,
+    address: Address,
+    chain_id: u64,
+}
+
+impl RemoteSigner {
+    pub async fn connect(url: &str) -> anyhow::Result<RemoteSigner> {
+        let ws = Ws::connect(url).await
+            .map_err(|err| anyhow!("Failed to connect to remote signer {}: {}", url, err))?;
+        let provider = Provider::new(ws);
+        let address = provider.request("signer_address", ()).await
+            .map_err(|err| anyhow!("Failed to get the remote signer address: {}", err))?;
+        println!("Connected to remote signer. Address [{:#x}]", address);
+        Ok(RemoteSigner {
+            provider,
+            address,
+            chain_id: 1,
+        })
+    }
+
+    pub fn with_chain_id<T: Into<u64>>(mut self, chain_id: T) -> RemoteSigner {
+        self.chain_id = chain_id.into();
+        self
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        let params = [Bytes::from(message.to_vec())];
+        self.request("signer_signMessage", params).await
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, SignerError> {
+        let mut tx = tx.clone();
+        if tx.chain_id().is_none() {
+            tx.set_chain_id(self.chain_id);
+        }
+        self.request("signer_signTransaction", [tx]).await
+    }
+
+    async fn request<T>(&self, method: &str, params: T) -> Result<Signature, SignerError>
+    where T: fmt::Debug + serde::Serialize + Send + Sync {
+        let signature: String = self.provider.request(method, params).await
+            .map_err(SignerError::Remote)?;
+        Signature::from_str(&signature).map_err(SignerError::InvalidSignature)
+    }
+}
+
+
+/// Serves `wallet` as a remote signer on `listener`.
+///
+/// A stand-in for a real external signer, it signs whatever it is sent, so
+/// only bind it where the game server alone can reach it.
+pub async fn serve(wallet: LocalWallet, listener: TcpListener) -> crate::Result<()> {
+    let handler = Arc::new(create_handler(wallet));
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            let ws_stream = match tokio_tungstenite::accept_async(stream).await {
+                Ok(ws_stream) => ws_stream,
+                Err(err) => {
+                    println!("Failed to accept signer connection. Addr [{}] Error [{}]", addr, err);
+                    return;
+                }
+            };
+            println!("Signer client connected. Addr [{}]", addr);
+            let (mut outgoing, mut incoming) = ws_stream.split();
+            while let Some(Ok(msg)) = incoming.next().await {
+                let msg = match msg.into_text() {
+                    Ok(msg) => msg,
+                    Err(_) => continue,
+                };
+                if let Some(response) = handler.handle_request(&msg).await {
+                    if outgoing.send(Message::text(response)).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            println!("Signer client disconnected. Addr [{}]", addr);
+        });
+    }
+}
+
+fn signing_error(err: WalletError) -> jsonrpc_core::Error {
+    jsonrpc_core::Error {
+        code: jsonrpc_core::ErrorCode::InternalError,
+        message: err.to_string(),
+        data: None,
+    }
+}
+
+fn create_handler(wallet: LocalWallet) -> IoHandler {
+    let mut io = IoHandler::new();
+
+    let address = wallet.address();
+    io.add_sync_method("signer_address", move |_params: Params| {
+        Ok(json!(address))
+    });
+
+    let local_wallet = wallet.clone();
+    io.add_method("signer_signTransaction", move |params: Params| {
+        let wallet = local_wallet.clone();
+        async move {
+            let (tx,): (TypedTransaction,) = params.parse()?;
+            println!("Signing transaction. To [{:?}]", tx.to());
+            let signature = wallet.sign_transaction(&tx).await.map_err(signing_error)?;
+            Ok(json!(signature.to_string()))
+        }
+    });
+
+    let local_wallet = wallet;
+    io.add_method("signer_signMessage", move |params: Params| {
+        let wallet = local_wallet.clone();
+        async move {
+            let (message,): (Bytes,) = params.parse()?;
+            let signature = wallet.sign_message(message.to_vec()).await.map_err(signing_error)?;
+            Ok(json!(signature.to_string()))
+        }
+    });
+
+    io
+}
@@ -0,0 +1,325 @@
+//! World snapshots, sent to players as binary frames.
+//!
+//! Player cells and food are sent on separate channels. Each frame is either
+//! a keyframe with every entity in view, or a delta against an earlier frame
+//! the client acknowledged with `ack_snapshot`. Clients keep the frames they
+//! applied until they are acknowledged, and call `request_keyframe` when a
+//! delta doesn't apply.
+//!
+//! The frames themselves are laid out in `protocol`.
+
+use std::{collections::VecDeque, fmt};
+
+use crate::config::GameplayConfig;
+use crate::protocol::{
+    self, Channel, DecodeError, Delta, Entity, Keyframe, Move, Quantizer, SnapshotFrame, Stamp, View,
+};
+
+
+// frames sent on one channel, oldest first, entities sorted by id
+#[derive(Debug, Default)]
+struct History {
+    sent: VecDeque<(u32, Vec<Entity>)>,
+    acked: Option<u32>,
+    since_keyframe: u32,
+}
+
+impl History {
+    fn position(&self, seq: u32) -> Option<usize> {
+        self.sent.iter().position(|(sent, _)| *sent == seq)
+    }
+
+    fn ack(&mut self, seq: u32) {
+        let position = match self.position(seq) {
+            Some(position) => position,
+            None => return,
+        };
+        let newer = self.acked
+            .and_then(|acked| self.position(acked))
+            .map(|acked| position > acked)
+            .unwrap_or(true);
+        if newer {
+            self.acked = Some(seq);
+        }
+    }
+}
+
+/// Snapshots sent to one client.
+#[derive(Debug)]
+pub struct SnapshotEncoder {
+    version: u32,
+    next_seq: u32,
+    players: History,
+    food: History,
+}
+
+impl Default for SnapshotEncoder {
+    fn default() -> SnapshotEncoder {
+        SnapshotEncoder::new(protocol::PROTOCOL_VERSION)
+    }
+}
+
+impl SnapshotEncoder {
+    /// Encodes for a client speaking protocol `version`.
+    pub fn new(version: u32) -> SnapshotEncoder {
+        SnapshotEncoder {
+            version,
+            next_seq: 0,
+            players: History::default(),
+            food: History::default(),
+        }
+    }
+
+    /// Encodes what the client sees on `channel` as a delta against the last
+    /// frame it acknowledged, or as a keyframe when there is none or one is
+    /// due.
+    pub fn encode(
+        &mut self,
+        channel: Channel,
+        view: Option<View>,
+        stamp: Stamp,
+        quantizer: Quantizer,
+        mut entities: Vec<Entity>,
+        config: &GameplayConfig,
+    ) -> Vec<u8> {
+        entities.sort_unstable_by_key(|entity| entity.id);
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        let history = match channel {
+            Channel::Players => &mut self.players,
+            Channel::Food => &mut self.food,
+        };
+
+        let sent = &history.sent;
+        let keyframe_due = history.since_keyframe >= config.keyframe_interval;
+        let base = history.acked
+            .filter(|_| !keyframe_due)
+            .and_then(|acked| sent.iter().find(|(seq, _)| *seq == acked));
+        let frame = match base {
+            Some((base_seq, base)) => {
+                history.since_keyframe += 1;
+                SnapshotFrame::Delta(delta(channel, seq, stamp, *base_seq, view, base, &entities))
+            }
+            None => {
+                history.since_keyframe = 0;
+                SnapshotFrame::Keyframe(Keyframe {
+                    channel,
+                    seq,
+                    stamp,
+                    arena: quantizer,
+                    view,
+                    entities: entities.clone(),
+                })
+            }
+        };
+
+        history.sent.push_back((seq, entities));
+        while history.sent.len() > config.snapshot_history {
+            history.sent.pop_front();
+        }
+        frame.encode(self.version)
+    }
+
+    /// The client applied frame `seq`, later deltas can build on it.
+    pub fn ack(&mut self, seq: u32) {
+        self.players.ack(seq);
+        self.food.ack(seq);
+    }
+
+    /// Forgets everything sent, the next frames are keyframes.
+    pub fn resync(&mut self) {
+        self.players = History::default();
+        self.food = History::default();
+    }
+}
+
+fn delta(
+    channel: Channel,
+    seq: u32,
+    stamp: Stamp,
+    base_seq: u32,
+    view: Option<View>,
+    base: &[Entity],
+    entities: &[Entity],
+) -> Delta {
+    let mut delta = Delta {
+        channel,
+        seq,
+        stamp,
+        base: base_seq,
+        view,
+        spawned: Vec::new(),
+        moved: Vec::new(),
+        despawned: Vec::new(),
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < base.len() || j < entities.len() {
+        match (base.get(i), entities.get(j)) {
+            (Some(old), Some(new)) if old.id == new.id => {
+                if old.hue != new.hue || old.owner != new.owner {
+                    delta.spawned.push(*new);
+                } else if old != new {
+                    delta.moved.push(Move { id: new.id, x: new.x, y: new.y, radius: new.radius });
+                }
+                i += 1;
+                j += 1;
+            }
+            (Some(old), Some(new)) if old.id < new.id => {
+                delta.despawned.push(old.id);
+                i += 1;
+            }
+            (Some(old), None) => {
+                delta.despawned.push(old.id);
+                i += 1;
+            }
+            (_, Some(new)) => {
+                delta.spawned.push(*new);
+                j += 1;
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+    delta
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotError {
+    Decode(DecodeError),
+    /// A delta came before any keyframe.
+    NoKeyframe,
+    /// The delta's base frame is gone, a keyframe is needed.
+    MissingBase(u32),
+    /// The delta moves an entity the base frame doesn't have.
+    UnknownEntity(u32),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Decode(err) => write!(f, "{}", err),
+            SnapshotError::NoKeyframe => write!(f, "delta before any keyframe"),
+            SnapshotError::MissingBase(seq) => write!(f, "base frame {} is gone", seq),
+            SnapshotError::UnknownEntity(id) => write!(f, "entity {} is not in the base frame", id),
+        }
+    }
+}
+
+impl From<DecodeError> for SnapshotError {
+    fn from(err: DecodeError) -> SnapshotError {
+        SnapshotError::Decode(err)
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+/// A decoded frame, with every entity in view.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub seq: u32,
+    pub stamp: Stamp,
+    pub channel: Channel,
+    pub quantizer: Quantizer,
+    pub view: Option<View>,
+    /// Sorted by id.
+    pub entities: Vec<Entity>,
+}
+
+/// The client side, rebuilds snapshots from keyframes and deltas.
+#[derive(Debug)]
+pub struct SnapshotReceiver {
+    version: u32,
+    history: usize,
+    quantizer: Option<Quantizer>,
+    players: VecDeque<(u32, Vec<Entity>)>,
+    food: VecDeque<(u32, Vec<Entity>)>,
+}
+
+impl SnapshotReceiver {
+    /// Keeps the last `history` frames of each channel to apply deltas to,
+    /// at least the server's `snapshot_history`.
+    pub fn new(history: usize) -> SnapshotReceiver {
+        SnapshotReceiver::for_version(protocol::PROTOCOL_VERSION, history)
+    }
+
+    /// For a client that authenticated with protocol `version`.
+    pub fn for_version(version: u32, history: usize) -> SnapshotReceiver {
+        SnapshotReceiver {
+            version,
+            history,
+            quantizer: None,
+            players: VecDeque::new(),
+            food: VecDeque::new(),
+        }
+    }
+
+    pub fn apply(&mut self, data: &[u8]) -> Result<Snapshot, SnapshotError> {
+        let frame = SnapshotFrame::decode(data, self.version)?;
+        let (channel, seq, stamp) = (frame.channel(), frame.seq(), frame.stamp());
+        let (view, entities) = match frame {
+            SnapshotFrame::Keyframe(mut keyframe) => {
+                self.quantizer = Some(keyframe.arena);
+                keyframe.entities.sort_unstable_by_key(|entity| entity.id);
+                (keyframe.view, keyframe.entities)
+            }
+            SnapshotFrame::Delta(delta) => {
+                if self.quantizer.is_none() {
+                    return Err(SnapshotError::NoKeyframe);
+                }
+                let base = self.frames(channel).iter()
+                    .find(|(seq, _)| *seq == delta.base)
+                    .map(|(_, entities)| entities)
+                    .ok_or(SnapshotError::MissingBase(delta.base))?;
+                let entities = apply_delta(base, &delta)?;
+                (delta.view, entities)
+            }
+        };
+
+        let history = self.history;
+        let frames = self.frames(channel);
+        frames.push_back((seq, entities.clone()));
+        while frames.len() > history {
+            frames.pop_front();
+        }
+        Ok(Snapshot {
+            seq,
+            stamp,
+            channel,
+            // set by the keyframe at the latest
+            quantizer: self.quantizer.unwrap(),
+            view,
+            entities,
+        })
+    }
+
+    /// Forgets every frame, for after `request_keyframe`.
+    pub fn reset(&mut self) {
+        self.players.clear();
+        self.food.clear();
+    }
+
+    fn frames(&mut self, channel: Channel) -> &mut VecDeque<(u32, Vec<Entity>)> {
+        match channel {
+            Channel::Players => &mut self.players,
+            Channel::Food => &mut self.food,
+        }
+    }
+}
+
+fn apply_delta(base: &[Entity], delta: &Delta) -> Result<Vec<Entity>, SnapshotError> {
+    let mut entities = base.to_vec();
+    for moved in &delta.moved {
+        let entity = entities.iter_mut()
+            .find(|entity| entity.id == moved.id)
+            .ok_or(SnapshotError::UnknownEntity(moved.id))?;
+        entity.x = moved.x;
+        entity.y = moved.y;
+        entity.radius = moved.radius;
+    }
+    entities.retain(|entity| {
+        !delta.despawned.contains(&entity.id) && !delta.spawned.iter().any(|s| s.id == entity.id)
+    });
+    entities.extend(delta.spawned.iter().copied());
+    entities.sort_unstable_by_key(|entity| entity.id);
+    Ok(entities)
+}
@@ -0,0 +1,174 @@
+use std::{
+    fmt,
+    ops::{Add, Sub},
+};
+use ethers::prelude::U256;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+
+/// Decimals of the reward token (BobaToken).
+pub const DEFAULT_DECIMALS: u8 = 9;
+
+
+/// An amount of the reward token, counted in the token's smallest unit.
+///
+/// Arithmetic panics on overflow/underflow rather than wrapping, use the
+/// `checked_*` methods where that is a possibility.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct TokenAmount(U256);
+
+impl TokenAmount {
+    pub fn zero() -> TokenAmount {
+        TokenAmount(U256::zero())
+    }
+
+    pub fn from_base_units(value: U256) -> TokenAmount {
+        TokenAmount(value)
+    }
+
+    pub fn base_units(&self) -> U256 {
+        self.0
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
+
+    pub fn checked_add(self, other: TokenAmount) -> Option<TokenAmount> {
+        self.0.checked_add(other.0).map(TokenAmount)
+    }
+
+    pub fn checked_sub(self, other: TokenAmount) -> Option<TokenAmount> {
+        self.0.checked_sub(other.0).map(TokenAmount)
+    }
+
+    pub fn saturating_sub(self, other: TokenAmount) -> TokenAmount {
+        TokenAmount(self.0.saturating_sub(other.0))
+    }
+
+    /// Human readable amount, e.g. `12.5` for 12.5 tokens.
+    pub fn format(&self, decimals: u8) -> String {
+        let unit = U256::exp10(decimals as usize);
+        let whole = self.0 / unit;
+        let fraction = self.0 % unit;
+        if fraction.is_zero() {
+            return whole.to_string();
+        }
+        let fraction = format!("{:0>width$}", fraction.to_string(), width = decimals as usize);
+        format!("{}.{}", whole, fraction.trim_end_matches('0'))
+    }
+}
+
+impl From<U256> for TokenAmount {
+    fn from(value: U256) -> Self {
+        TokenAmount(value)
+    }
+}
+
+impl Add for TokenAmount {
+    type Output = TokenAmount;
+
+    fn add(self, other: TokenAmount) -> TokenAmount {
+        self.checked_add(other).expect("token amount overflow")
+    }
+}
+
+impl Sub for TokenAmount {
+    type Output = TokenAmount;
+
+    fn sub(self, other: TokenAmount) -> TokenAmount {
+        self.checked_sub(other).expect("token amount underflow")
+    }
+}
+
+// base units as a decimal string, numbers this large don't survive JSON
+impl Serialize for TokenAmount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for TokenAmount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        U256::from_dec_str(&value).map(TokenAmount).map_err(|_| de::Error::custom(format!("invalid token amount {}", value)))
+    }
+}
+
+impl fmt::Display for TokenAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+
+/// The exchange rate between in-game mass and tokens.
+///
+/// One unit of mass is worth `multiplier` whole tokens. This is the only
+/// place mass and tokens are converted into each other:
+///
+/// * mass -> tokens is exact.
+/// * tokens -> mass rounds down and hands back the remainder, so the caller
+///   can carry it over instead of losing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MassConversion {
+    decimals: u8,
+    multiplier: u32,
+    mass_unit: U256,
+}
+
+impl MassConversion {
+    /// Returns `None` if the multiplier is zero or one mass unit is so large
+    /// that `u64::MAX` mass would not fit in a `U256`.
+    pub fn new(decimals: u8, multiplier: u32) -> Option<MassConversion> {
+        if multiplier == 0 {
+            return None;
+        }
+        let scale = U256::from(10u8).checked_pow(U256::from(decimals))?;
+        let mass_unit = scale.checked_mul(U256::from(multiplier))?;
+        mass_unit.checked_mul(U256::from(u64::MAX))?;
+        Some(MassConversion {
+            decimals,
+            multiplier,
+            mass_unit,
+        })
+    }
+
+    pub fn decimals(&self) -> u8 {
+        self.decimals
+    }
+
+    pub fn multiplier(&self) -> u32 {
+        self.multiplier
+    }
+
+    /// Value of a single unit of mass.
+    pub fn mass_unit(&self) -> TokenAmount {
+        TokenAmount(self.mass_unit)
+    }
+
+    pub fn mass_to_tokens(&self, mass: u64) -> TokenAmount {
+        // cannot overflow, checked in `new`
+        TokenAmount(self.mass_unit * U256::from(mass))
+    }
+
+    /// Splits `amount` into whole mass units and the token remainder.
+    ///
+    /// `mass_to_tokens(mass) + remainder == amount` always holds. Mass beyond
+    /// `u64::MAX` stays in the remainder.
+    pub fn tokens_to_mass(&self, amount: TokenAmount) -> (u64, TokenAmount) {
+        let mass = amount.0 / self.mass_unit;
+        let mass = if mass > U256::from(u64::MAX) {
+            u64::MAX
+        } else {
+            mass.as_u64()
+        };
+        let remainder = amount - self.mass_to_tokens(mass);
+        (mass, remainder)
+    }
+
+    /// Whole tokens a player sees for `mass`, for display only.
+    pub fn mass_to_display(&self, mass: u64) -> String {
+        self.mass_to_tokens(mass).format(self.decimals)
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    collections::HashSet,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use serde::Serialize;
+
+use crate::config::TournamentConfig;
+use crate::rooms::RoomId;
+
+
+pub type TournamentId = u32;
+
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+pub fn unix_now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Scheduled,
+    Registration,
+    Heats,
+    Final,
+    Finished,
+    /// Fewer than two players registered, tickets were given back.
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Heat {
+    pub room_id: RoomId,
+    pub players: Vec<String>,
+    // a player gets into their heat once, dying is final
+    #[serde(skip)]
+    pub entered: HashSet<String>,
+    /// Mass of every player when the heat ended, best first. Empty while the
+    /// heat is played.
+    pub results: Vec<(String, u64)>,
+}
+
+/// A tournament and its bracket.
+///
+/// Registering takes a ticket and moves its mass from the public rooms to
+/// the prize pool. Each round splits the players into heats of at most
+/// `heat_size`, played in their own rooms for `heat_secs`, and the best
+/// `advance_per_heat` of every heat play the next round. The round that
+/// fits in one heat is the final, its places are paid from the pool.
+#[derive(Debug, Clone)]
+pub struct Tournament {
+    pub id: TournamentId,
+    pub starts_at: u64,
+    pub registration_opens_at: u64,
+    pub stage: Stage,
+    pub registered: Vec<String>,
+    /// Mass the tournament plays for. It sits in the heat rooms while a
+    /// round is played.
+    pub prize_pool: u64,
+    /// Every round played so far, the last one is the current one.
+    pub rounds: Vec<Vec<Heat>>,
+    pub round_ends_at: Option<u64>,
+    /// Mass paid to each place of the final.
+    pub prizes: Vec<(String, u64)>,
+}
+
+impl Tournament {
+    pub fn new(id: TournamentId, starts_at: u64, config: &TournamentConfig) -> Tournament {
+        Tournament {
+            id,
+            starts_at,
+            registration_opens_at: starts_at.saturating_sub(config.registration_secs),
+            stage: Stage::Scheduled,
+            registered: Vec::new(),
+            prize_pool: 0,
+            rounds: Vec::new(),
+            round_ends_at: None,
+            prizes: Vec::new(),
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        matches!(self.stage, Stage::Finished | Stage::Cancelled)
+    }
+
+    pub fn is_playing(&self) -> bool {
+        matches!(self.stage, Stage::Heats | Stage::Final)
+    }
+
+    pub fn registration_open(&self, now: u64) -> bool {
+        matches!(self.stage, Stage::Scheduled | Stage::Registration)
+            && self.registration_opens_at <= now
+            && now < self.starts_at
+    }
+
+    /// The heat `eth_address` plays in this round, if they haven't entered it
+    /// yet.
+    pub fn heat_to_enter(&mut self, eth_address: &str) -> Option<&mut Heat> {
+        if !self.is_playing() {
+            return None;
+        }
+        self.rounds.last_mut()?.iter_mut().find(|heat| {
+            heat.players.iter().any(|p| p == eth_address) && !heat.entered.contains(eth_address)
+        })
+    }
+}
+
+/// Splits `players` into as few heats of at most `heat_size` as possible,
+/// dealt in turn so the heats differ by one player at most.
+pub fn seed_heats(players: &[String], heat_size: u32) -> Vec<Vec<String>> {
+    let heat_count = (players.len() + heat_size as usize - 1) / heat_size as usize;
+    let mut heats = vec![Vec::new(); heat_count];
+    for (i, player) in players.iter().enumerate() {
+        heats[i % heat_count].push(player.clone());
+    }
+    heats
+}
+
+/// Players of a finished heat that play the next round. A heat always drops
+/// at least one player so the bracket gets smaller every round.
+pub fn advancing(results: &[(String, u64)], advance_per_heat: u32) -> Vec<String> {
+    let count = (advance_per_heat as usize).min(results.len().saturating_sub(1)).max(1);
+    results.iter().take(count).map(|(player, _)| player.clone()).collect()
+}
+
+/// Mass for each place from `pool`, the rounding goes to no one.
+pub fn prize_shares(pool: u64, percentages: &[u32]) -> Vec<u64> {
+    percentages.iter()
+        .map(|&percent| (pool as u128 * percent as u128 / 100) as u64)
+        .collect()
+}
@@ -0,0 +1,250 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+use crate::config::GameplayConfig;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WinConditionKind {
+    /// The heaviest player above `win_mass_threshold` every `win_time`
+    /// seconds.
+    Timed,
+    /// The first to reach `win_mass_target`.
+    MassTarget,
+    /// The first to stay heaviest, above `win_mass_threshold`, for
+    /// `hold_secs` seconds in a row.
+    KingOfTheHill,
+    /// The first to hold `win_map_share` of all the mass on the map, once
+    /// above `win_mass_threshold`.
+    MapShare,
+}
+
+impl Default for WinConditionKind {
+    fn default() -> Self {
+        WinConditionKind::Timed
+    }
+}
+
+/// A player, or a whole team when teams are on, competing for the win.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Side {
+    /// The player's address, or `team:<n>`.
+    pub id: String,
+    pub members: Vec<String>,
+    pub mass: f64,
+}
+
+/// What win conditions look at every second.
+#[derive(Debug, Clone, Default)]
+pub struct Standings {
+    /// Heaviest first.
+    pub sides: Vec<Side>,
+    /// Mass of all the cells and food on the map.
+    pub map_mass: f64,
+}
+
+impl Standings {
+    pub fn leader(&self) -> Option<&Side> {
+        self.sides.first()
+    }
+}
+
+/// Decides when a room has a winner. The winning side is taken out of the
+/// room and paid like any other winner.
+pub trait WinCondition: fmt::Debug + Send {
+    fn kind(&self) -> WinConditionKind;
+
+    /// Called every second, returns the side that won.
+    fn tick(&mut self, standings: &Standings) -> Option<Side>;
+
+    /// Seconds shown to players as `win_counter`, for conditions that count
+    /// down.
+    fn countdown(&self) -> Option<u64>;
+
+    /// Takes a reloaded config without losing the progress made so far.
+    fn update_config(&mut self, config: &GameplayConfig);
+}
+
+pub fn from_config(config: &GameplayConfig) -> Box<dyn WinCondition> {
+    match config.win_condition {
+        WinConditionKind::Timed => Box::new(Timed::new(config)),
+        WinConditionKind::MassTarget => Box::new(MassTarget::new(config)),
+        WinConditionKind::KingOfTheHill => Box::new(KingOfTheHill::new(config)),
+        WinConditionKind::MapShare => Box::new(MapShare::new(config)),
+    }
+}
+
+fn above(side: Option<&Side>, threshold: u64) -> Option<&Side> {
+    side.filter(|side| side.mass > threshold as f64)
+}
+
+
+#[derive(Debug)]
+pub struct Timed {
+    win_time: u64,
+    threshold: u64,
+    counter: u64,
+}
+
+impl Timed {
+    pub fn new(config: &GameplayConfig) -> Timed {
+        Timed {
+            win_time: config.win_time,
+            threshold: config.win_mass_threshold,
+            counter: config.win_time,
+        }
+    }
+}
+
+impl WinCondition for Timed {
+    fn kind(&self) -> WinConditionKind {
+        WinConditionKind::Timed
+    }
+
+    fn tick(&mut self, standings: &Standings) -> Option<Side> {
+        self.counter = self.counter.saturating_sub(1);
+        if self.counter > 0 {
+            return None;
+        }
+        self.counter = self.win_time;
+        above(standings.leader(), self.threshold).cloned()
+    }
+
+    fn countdown(&self) -> Option<u64> {
+        Some(self.counter)
+    }
+
+    fn update_config(&mut self, config: &GameplayConfig) {
+        // a shorter round starts counting from the new win time
+        self.counter = self.counter.min(config.win_time);
+        self.win_time = config.win_time;
+        self.threshold = config.win_mass_threshold;
+    }
+}
+
+
+#[derive(Debug)]
+pub struct MassTarget {
+    target: u64,
+}
+
+impl MassTarget {
+    pub fn new(config: &GameplayConfig) -> MassTarget {
+        MassTarget { target: config.win_mass_target }
+    }
+}
+
+impl WinCondition for MassTarget {
+    fn kind(&self) -> WinConditionKind {
+        WinConditionKind::MassTarget
+    }
+
+    fn tick(&mut self, standings: &Standings) -> Option<Side> {
+        standings.leader().filter(|side| side.mass >= self.target as f64).cloned()
+    }
+
+    fn countdown(&self) -> Option<u64> {
+        None
+    }
+
+    fn update_config(&mut self, config: &GameplayConfig) {
+        self.target = config.win_mass_target;
+    }
+}
+
+
+#[derive(Debug)]
+pub struct KingOfTheHill {
+    hold_secs: u64,
+    threshold: u64,
+    // id of the side on top and the seconds it has been there
+    king: Option<(String, u64)>,
+}
+
+impl KingOfTheHill {
+    pub fn new(config: &GameplayConfig) -> KingOfTheHill {
+        KingOfTheHill {
+            hold_secs: config.hold_secs,
+            threshold: config.win_mass_threshold,
+            king: None,
+        }
+    }
+}
+
+impl WinCondition for KingOfTheHill {
+    fn kind(&self) -> WinConditionKind {
+        WinConditionKind::KingOfTheHill
+    }
+
+    fn tick(&mut self, standings: &Standings) -> Option<Side> {
+        let leader = match above(standings.leader(), self.threshold) {
+            Some(leader) => leader,
+            None => {
+                self.king = None;
+                return None;
+            }
+        };
+        let held = match &self.king {
+            Some((id, held)) if *id == leader.id => held + 1,
+            _ => 0,
+        };
+        if held >= self.hold_secs {
+            self.king = None;
+            return Some(leader.clone());
+        }
+        self.king = Some((leader.id.clone(), held));
+        None
+    }
+
+    fn countdown(&self) -> Option<u64> {
+        let held = self.king.as_ref().map(|(_, held)| *held).unwrap_or(0);
+        Some(self.hold_secs - held)
+    }
+
+    fn update_config(&mut self, config: &GameplayConfig) {
+        self.hold_secs = config.hold_secs;
+        self.threshold = config.win_mass_threshold;
+        if let Some((_, held)) = &mut self.king {
+            *held = (*held).min(self.hold_secs);
+        }
+    }
+}
+
+
+#[derive(Debug)]
+pub struct MapShare {
+    share: f64,
+    threshold: u64,
+}
+
+impl MapShare {
+    pub fn new(config: &GameplayConfig) -> MapShare {
+        MapShare {
+            share: config.win_map_share,
+            threshold: config.win_mass_threshold,
+        }
+    }
+}
+
+impl WinCondition for MapShare {
+    fn kind(&self) -> WinConditionKind {
+        WinConditionKind::MapShare
+    }
+
+    fn tick(&mut self, standings: &Standings) -> Option<Side> {
+        // a lone player on an empty map is not a win
+        above(standings.leader(), self.threshold)
+            .filter(|side| side.mass >= standings.map_mass * self.share)
+            .cloned()
+    }
+
+    fn countdown(&self) -> Option<u64> {
+        None
+    }
+
+    fn update_config(&mut self, config: &GameplayConfig) {
+        self.share = config.win_map_share;
+        self.threshold = config.win_mass_threshold;
+    }
+}
@@ -0,0 +1,50 @@
+use std::{env, fs};
+
+use tokio::time::{timeout, Duration};
+
+use agario_rust::admin::{self, AdminArgs, AdminCommand, Role};
+use agario_rust::config::Config;
+
+
+// nothing listens on these ports, a dry run has to get by without them
+const NO_NODE: &str = r#"
+[server]
+ws_port = 3000
+
+[chain]
+chain_id = 28
+provider_http_url = "http://127.0.0.1:1"
+provider_ws_url = "ws://127.0.0.1:1"
+secret_key = "not a key"
+fee_manager_address = "0x5FbDB2315678afecb367f032d93F642f64180aa3"
+game_pool_address = "0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512"
+"#;
+
+fn config() -> Config {
+    let path = env::temp_dir().join(format!("agario-admin-{}.toml", std::process::id()));
+    fs::write(&path, NO_NODE).unwrap();
+    Config::load_with_env(Some(&path), Vec::new(), &[]).unwrap()
+}
+
+
+#[tokio::test]
+async fn dry_runs_need_no_node_or_signer() {
+    let commands = vec![
+        AdminCommand::SetEntryFee { fee: 5 },
+        AdminCommand::WithdrawFees,
+        AdminCommand::GrantRole { role: Role::Manager, account: String::from("0x5FbDB2315678afecb367f032d93F642f64180aa3") },
+    ];
+    for command in commands {
+        let args = AdminArgs { dry_run: true, command };
+        let result = timeout(Duration::from_secs(5), admin::run(config(), args)).await
+            .expect("dry run tried to reach the chain");
+        assert!(result.is_ok(), "{:?}", result);
+    }
+}
+
+#[tokio::test]
+async fn dry_runs_of_reads_are_refused() {
+    let args = AdminArgs { dry_run: true, command: AdminCommand::Status };
+    let result = timeout(Duration::from_secs(5), admin::run(config(), args)).await.unwrap();
+    assert!(result.is_err());
+}
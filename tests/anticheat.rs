@@ -0,0 +1,108 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use agario_rust::anticheat::InputMonitor;
+use agario_rust::config::AntiCheatConfig;
+use agario_rust::game::GameConfig;
+use common::TestServer;
+
+
+fn ms(millis: u64) -> Duration {
+    Duration::from_millis(millis)
+}
+
+
+#[test]
+fn inputs_over_the_rate_are_dropped() {
+    let config = AntiCheatConfig::default();
+    let start = Instant::now();
+    let mut monitor = InputMonitor::new(start);
+    for _ in 0..config.max_splits_per_sec {
+        assert!(monitor.split(start, &config));
+    }
+    assert!(!monitor.split(start, &config));
+    assert_eq!(monitor.score(), config.rate_limit_score);
+    // a second later the limit starts over
+    assert!(monitor.split(start + Duration::from_secs(1), &config));
+}
+
+#[test]
+fn invalid_targets_are_dropped_and_scored() {
+    let config = AntiCheatConfig::default();
+    let start = Instant::now();
+    let mut monitor = InputMonitor::new(start);
+    assert!(!monitor.target(f64::NAN, 0., start, &config));
+    assert!(!monitor.target(0., f64::INFINITY, start, &config));
+    assert_eq!(monitor.score(), config.invalid_input_score * 2.);
+
+    // the score wears off
+    assert!(monitor.target(10., 0., start + Duration::from_secs(10), &config));
+    assert_eq!(monitor.score(), config.invalid_input_score * 2. - 10. * config.score_decay_per_sec);
+}
+
+#[test]
+fn timer_cadence_and_flicking_aim_are_scored() {
+    let config = AntiCheatConfig::default();
+    let start = Instant::now();
+
+    // every 16ms on the dot
+    let mut monitor = InputMonitor::new(start);
+    for i in 0..=config.pattern_window as u64 {
+        monitor.target(100., (i % 7) as f64 * 10., start + ms(i * 16), &config);
+    }
+    assert!(monitor.score() >= config.pattern_score - 1.);
+
+    // a hand is never that regular
+    let mut monitor = InputMonitor::new(start);
+    let mut at = 0;
+    for i in 0..=config.pattern_window as u64 {
+        at += 10 + (i * 7) % 13;
+        monitor.target(100., (i % 7) as f64 * 10., start + ms(at), &config);
+    }
+    assert_eq!(monitor.score(), 0.);
+
+    // turning right around every few milliseconds
+    let mut monitor = InputMonitor::new(start);
+    let mut at = 0;
+    for i in 0..=config.pattern_window as u64 {
+        at += 10 + (i * 7) % 13;
+        let x = if i % 2 == 0 { 100. } else { -100. };
+        monitor.target(x, 1., start + ms(at), &config);
+    }
+    assert!(monitor.score() >= config.pattern_score - 1.);
+}
+
+#[test]
+fn high_scores_are_banned_unless_disabled() {
+    let config = AntiCheatConfig::default();
+    let start = Instant::now();
+    let mut monitor = InputMonitor::new(start);
+    while !monitor.should_ban(&config) {
+        monitor.target(f64::NAN, 0., start, &config);
+    }
+    assert!(monitor.score() >= config.ban_score);
+
+    let disabled = AntiCheatConfig { enabled: false, ..Default::default() };
+    let mut monitor = InputMonitor::new(start);
+    assert!(monitor.target(f64::NAN, 0., start, &disabled));
+    assert!(!monitor.should_ban(&disabled));
+}
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn split_spam_gets_the_player_banned() {
+    let server = TestServer::start(GameConfig::default()).await;
+    let mut client = server.join_game().await;
+
+    for _ in 0..60 {
+        client.split().await;
+    }
+    let banned = client.recv_notification("notify_banned").await;
+    assert!(banned["params"]["until"].as_u64().is_some());
+    while client.recv().await.is_some() {}
+
+    let mut again = server.connect_as(client.wallet.clone()).await;
+    let response = again.authenticate().await;
+    assert_eq!(response["error"]["message"], "This address is banned");
+}
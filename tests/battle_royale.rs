@@ -0,0 +1,80 @@
+mod common;
+
+use ethers::prelude::*;
+use serde_json::{json, Value};
+
+use agario_rust::config::GameplayConfig;
+use agario_rust::game::GameConfig;
+use common::{TestClient, TestServer};
+
+
+fn config(win_time: u64) -> GameConfig {
+    GameConfig {
+        gameplay: GameplayConfig {
+            battle_royale: true,
+            round_min_players: 2,
+            max_rooms: 1,
+            win_time,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+async fn running_zone(client: &mut TestClient) -> Value {
+    loop {
+        let zone = client.recv_notification("notify_zone").await;
+        if zone["params"]["round"] == "running" {
+            return zone["params"].clone();
+        }
+    }
+}
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn round_starts_with_enough_players_and_blocks_late_joins() {
+    let server = TestServer::start(config(60)).await;
+    let mut first = server.join_game().await;
+    let waiting = first.recv_notification("notify_zone").await;
+    assert_eq!(waiting["params"]["round"], "waiting");
+    assert_eq!(waiting["params"]["players_needed"], 1);
+    assert_eq!(waiting["params"]["zone"], json!({ "x": 0., "y": 0., "width": 5000., "height": 5000. }));
+
+    let _second = server.join_game().await;
+    running_zone(&mut first).await;
+
+    let mut late = server.login().await;
+    server.buy_ticket(&mut late).await;
+    assert_eq!(
+        late.call("join_room", json!({ "room_id": 1 })).await["error"]["message"],
+        "A round is in progress, wait for the next one!"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn safe_zone_shrinks_during_the_round() {
+    let server = TestServer::start(config(60)).await;
+    let mut first = server.join_game().await;
+    let _second = server.join_game().await;
+
+    let before = running_zone(&mut first).await;
+    let after = running_zone(&mut first).await;
+    let width = |zone: &Value| zone["zone"]["width"].as_f64().unwrap();
+    assert!(width(&after) < width(&before));
+    assert!(width(&after) > before["final_zone"]["width"].as_f64().unwrap());
+    assert_eq!(before["final_zone"]["width"], 500.);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn last_survivor_wins_the_round() {
+    let mut server = TestServer::start(config(60)).await;
+    let mut survivor = server.join_game().await;
+    let quitter = server.join_game().await;
+    running_zone(&mut survivor).await;
+
+    drop(quitter);
+    survivor.recv_notification("notify_won").await;
+    let winner = server.chain.next_winner().await.unwrap();
+    assert_eq!(format!("{:#x}", winner.address), survivor.address);
+    assert!(winner.amount > U256::zero());
+}
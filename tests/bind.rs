@@ -0,0 +1,40 @@
+#![cfg(unix)]
+
+use std::{env, fs, io};
+
+use agario_rust::config::BindAddress;
+use agario_rust::server;
+
+
+#[tokio::test]
+async fn stale_sockets_are_replaced() {
+    let path = env::temp_dir().join(format!("agario-stale-{}.sock", std::process::id()));
+    let _ = fs::remove_file(&path);
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+    assert!(server::bind(&BindAddress::Unix(path.clone())).await.is_ok());
+    fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn other_files_are_left_alone() {
+    let path = env::temp_dir().join(format!("agario-not-a-socket-{}", std::process::id()));
+    fs::write(&path, "keep me").unwrap();
+
+    let err = server::bind(&BindAddress::Unix(path.clone())).await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "keep me");
+    fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn sockets_in_use_are_left_alone() {
+    let path = env::temp_dir().join(format!("agario-in-use-{}.sock", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let _listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+
+    let err = server::bind(&BindAddress::Unix(path.clone())).await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+    assert!(path.exists());
+    fs::remove_file(&path).unwrap();
+}
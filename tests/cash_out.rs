@@ -0,0 +1,87 @@
+mod common;
+
+use ethers::prelude::U256;
+use serde_json::{json, Value};
+
+use agario_rust::config::{EconomyConfig, GameplayConfig};
+use agario_rust::game::GameConfig;
+use common::TestServer;
+
+
+fn config(gameplay: GameplayConfig) -> GameConfig {
+    GameConfig {
+        gameplay: GameplayConfig {
+            // no food to pick up while waiting
+            food_to_add_per_tick: 0,
+            cash_out_secs: 1,
+            ..gameplay
+        },
+        ..Default::default()
+    }
+}
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cashing_out_pays_mass_minus_the_fee() {
+    let server = TestServer::start(config(GameplayConfig::default())).await;
+    let mut client = server.join_game().await;
+
+    let response = client.call("cash_out", json!([])).await;
+    assert_eq!(response["result"], json!({ "ready_in": 1 }));
+
+    // 10 mass less the 10% fee
+    let cashed_out = client.recv_notification("notify_cashed_out").await;
+    assert_eq!(cashed_out["params"][0], "9");
+    let winner = server.chain.next_winner().await.unwrap();
+    assert_eq!(format!("{:#x}", winner.address), client.address);
+    assert_eq!(winner.amount, U256::from(9) * U256::exp10(9));
+
+    // the player is out of the game and can play again
+    server.enter_with_ticket(&mut client).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cash_out_needs_a_player_in_game() {
+    let server = TestServer::start(config(GameplayConfig::default())).await;
+    let mut client = server.login().await;
+    let response = client.call("cash_out", json!([])).await;
+    assert_eq!(response["error"]["message"], "You are not in game!");
+
+    let mut client = server.join_game().await;
+    assert_eq!(client.call("cash_out", json!([])).await["result"]["ready_in"], 1);
+    let response = client.call("cash_out", json!([])).await;
+    assert_eq!(response["error"]["message"], "You are already cashing out!");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn battle_royale_rooms_cannot_cash_out() {
+    let server = TestServer::start(config(GameplayConfig {
+        battle_royale: true,
+        ..Default::default()
+    })).await;
+    let mut client = server.join_game().await;
+    let response = client.call("cash_out", json!([])).await;
+    assert_eq!(response["error"]["message"], "Cashing out is not allowed in this room!");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn free_entries_cannot_cash_out() {
+    let server = TestServer::start(GameConfig {
+        economy: EconomyConfig {
+            no_entry_fee: true,
+            free_entry_min_food: 0,
+            ..Default::default()
+        },
+        ..config(GameplayConfig::default())
+    }).await;
+    let mut client = server.login().await;
+
+    // the mass free players spawn with comes out of the room's rewards
+    let response = client.enter_game().await;
+    assert_eq!(response["error"]["message"], "This room is out of rewards to spawn you with!");
+    server.chain.rewards_added(U256::from(100) * U256::exp10(9));
+    while client.enter_game().await["result"] != Value::Null {}
+
+    let response = client.call("cash_out", json!([])).await;
+    assert_eq!(response["error"]["message"], "Cashing out is not allowed in this room!");
+}
@@ -0,0 +1,169 @@
+mod common;
+
+use std::{env, fs, net::IpAddr};
+
+use ethers::prelude::*;
+use serde_json::{json, Value};
+
+use agario_rust::collusion::{CollusionDetector, Flag, HeldPayout, HeldPayouts, Join, MassTransfer};
+use agario_rust::config::CollusionConfig;
+use agario_rust::token::TokenAmount;
+use common::TestServer;
+
+
+fn transfer(from: &str, to: &str, mass: f64, at: u64) -> MassTransfer {
+    MassTransfer { from: from.to_string(), to: to.to_string(), mass, at }
+}
+
+fn join(address: &str, ip: &str, room_id: u32, at: u64) -> Join {
+    Join { address: address.to_string(), ip: ip.parse::<IpAddr>().unwrap(), room_id, at }
+}
+
+
+#[test]
+fn one_way_feeding_is_flagged() {
+    let mut detector = CollusionDetector::new(CollusionConfig::default());
+    for at in 0..3 {
+        detector.record_transfer(transfer("feeder", "main", 40., at));
+    }
+    assert_eq!(detector.flags("main"), vec![Flag::OneWayFeeding {
+        from: String::from("feeder"),
+        mass: 120,
+        count: 3,
+    }]);
+    // only the one fed is paid for it
+    assert_eq!(detector.flags("feeder"), vec![]);
+
+    // a fair fight goes both ways
+    detector.record_transfer(transfer("main", "feeder", 100., 3));
+    assert_eq!(detector.flags("main"), vec![]);
+
+    detector.forget_old(10_000);
+    detector.record_transfer(transfer("feeder", "main", 150., 10_000));
+    assert_eq!(detector.flags("main"), vec![]);
+}
+
+#[test]
+fn shared_ips_and_synchronized_joins_are_flagged() {
+    let mut detector = CollusionDetector::new(CollusionConfig::default());
+    detector.record_join(join("a", "203.0.113.7", 1, 100));
+    detector.record_join(join("b", "203.0.113.7", 1, 500));
+    detector.record_join(join("c", "203.0.113.8", 2, 100));
+    assert_eq!(detector.flags("a"), vec![Flag::SharedIp {
+        with: String::from("b"),
+        ip: "203.0.113.7".parse().unwrap(),
+    }]);
+    // same ip, different rooms
+    detector.record_join(join("d", "203.0.113.8", 1, 900));
+    assert_eq!(detector.flags("d"), vec![]);
+
+    for (room_id, at) in [(3, 1000), (4, 2000), (5, 3000)].iter() {
+        detector.record_join(join("x", "198.51.100.1", *room_id, *at));
+        detector.record_join(join("y", "198.51.100.2", *room_id, *at + 1));
+    }
+    assert_eq!(detector.flags("y"), vec![Flag::SynchronizedJoins {
+        with: String::from("x"),
+        count: 3,
+    }]);
+}
+
+#[test]
+fn local_connections_and_disabled_detection_are_not_flagged() {
+    let mut detector = CollusionDetector::new(CollusionConfig::default());
+    detector.record_join(join("a", "127.0.0.1", 1, 100));
+    detector.record_join(join("b", "127.0.0.1", 1, 500));
+    assert_eq!(detector.flags("a"), vec![]);
+
+    let mut detector = CollusionDetector::new(CollusionConfig {
+        enabled: false,
+        ..Default::default()
+    });
+    for at in 0..5 {
+        detector.record_transfer(transfer("feeder", "main", 40., at));
+    }
+    assert_eq!(detector.flags("main"), vec![]);
+}
+
+#[test]
+fn held_payouts_are_saved() {
+    let path = env::temp_dir().join(format!("agario-held-payouts-{}.json", std::process::id()));
+    fs::remove_file(&path).ok();
+
+    let mut payouts = HeldPayouts::load(Some(path.clone())).unwrap();
+    assert_eq!(payouts.next_id(), 1);
+    for id in 1..=2 {
+        payouts.add(HeldPayout {
+            id,
+            address: String::from("0x5fbdb2315678afecb367f032d93f642f64180aa3"),
+            amount: TokenAmount::from(U256::from(9) * U256::exp10(9)),
+            flags: vec![Flag::SharedIp { with: String::from("b"), ip: "203.0.113.7".parse().unwrap() }],
+            held_at: 100,
+        });
+    }
+
+    let mut payouts = HeldPayouts::load(Some(path.clone())).unwrap();
+    assert_eq!(payouts.all().len(), 2);
+    assert_eq!(payouts.all()[0].flags[0], Flag::SharedIp { with: String::from("b"), ip: "203.0.113.7".parse().unwrap() });
+    assert_eq!(payouts.total(), TokenAmount::from(U256::from(18) * U256::exp10(9)));
+    assert_eq!(payouts.next_id(), 3);
+
+    assert!(payouts.remove(1).is_some());
+    let payouts = HeldPayouts::load(Some(path.clone())).unwrap();
+    assert_eq!(payouts.all().len(), 1);
+    assert_eq!(payouts.next_id(), 3);
+    fs::remove_file(&path).ok();
+}
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn flagged_winnings_wait_for_an_admin() {
+    let admin = LocalWallet::new(&mut rand::thread_rng());
+    let mut server = TestServer::start_with_admin(&admin, r#"
+[gameplay]
+width = 60
+height = 60
+win_time = 1
+win_mass_threshold = 150
+
+[collusion]
+feed_min_mass = 5
+feed_min_count = 1
+"#).await;
+
+    let mut admin = server.login_as(admin).await;
+    let mut hunter = server.join_game().await;
+    let mut prey = server.login().await;
+    loop {
+        let metadata = hunter.recv_notification("notify_update_metadata").await;
+        let mass = metadata["params"]["scores"].as_array()
+            .and_then(|scores| scores.iter().find(|s| s[0] == json!(hunter.address)))
+            .and_then(|s| s[1].as_u64())
+            .unwrap_or(0);
+        if mass >= 70 {
+            break;
+        }
+    }
+    server.enter_with_ticket(&mut prey).await;
+    prey.recv_notification("notify_game_over").await;
+
+    // fed once by the prey, which the config flags
+    let held = hunter.recv_notification("notify_payout_held").await;
+    let payout_id = held["params"]["payout_id"].clone();
+
+    let response = hunter.call("list_held_payouts", json!([])).await;
+    assert_eq!(response["error"]["message"], "Not allowed to review payouts");
+
+    let payouts = admin.call("list_held_payouts", json!([])).await["result"].clone();
+    assert_eq!(payouts[0]["id"], payout_id);
+    assert_eq!(payouts[0]["address"], json!(hunter.address));
+    assert_eq!(payouts[0]["flags"][0]["kind"], "one_way_feeding");
+    assert_eq!(payouts[0]["flags"][0]["from"], json!(prey.address));
+
+    let response = admin.call("review_payout", json!({ "payout_id": payout_id, "approve": true })).await;
+    assert_eq!(response["result"], Value::Null);
+    let winner = server.chain.next_winner().await.unwrap();
+    assert_eq!(format!("{:#x}", winner.address), hunter.address);
+
+    let response = admin.call("review_payout", json!({ "payout_id": payout_id, "approve": true })).await;
+    assert_eq!(response["error"]["message"], "There is no held payout with this id!");
+}
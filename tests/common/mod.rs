@@ -0,0 +1,347 @@
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::{env, fs};
+
+use ethers::prelude::*;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{timeout, Duration};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tokio_tungstenite::tungstenite::Message;
+
+use agario_rust::{chain, config::ConfigArgs, game::GameConfig, reload::ConfigReloader, server};
+use agario_rust::protocol::{self, AuthParams, ClientMessage, Stamp};
+use agario_rust::snapshot::{Snapshot, SnapshotReceiver};
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+pub const RECV_TIMEOUT: Duration = Duration::from_secs(10);
+
+
+pub struct TestServer {
+    pub addr: SocketAddr,
+    pub chain: chain::MockChain,
+}
+
+impl TestServer {
+    pub async fn start(config: GameConfig) -> TestServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (backend, chain) = chain::mock();
+        tokio::spawn(server::serve(config, listener, backend, None));
+        TestServer { addr, chain }
+    }
+
+    /// Like `start`, but reloading the config through `reloader`.
+    pub async fn start_reloadable(config: GameConfig, reloader: ConfigReloader) -> TestServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (backend, chain) = chain::mock();
+        tokio::spawn(server::serve(config, listener, backend, Some(Arc::new(reloader))));
+        TestServer { addr, chain }
+    }
+
+    /// Like `start_reloadable`, loading a config file with `admin` as the
+    /// only admin and `toml` appended.
+    pub async fn start_with_admin(admin: &LocalWallet, toml: &str) -> TestServer {
+        let admin = format!("{:#x}", admin.address());
+        let path = env::temp_dir().join(format!("agario-test-{}.toml", admin));
+        fs::write(&path, format!(r#"
+[server]
+ws_port = 3000
+admin_addresses = ["{}"]
+
+[chain]
+chain_id = 28
+provider_http_url = "http://localhost:8545"
+provider_ws_url = "ws://localhost:8546"
+secret_key = "0x0000000000000000000000000000000000000000000000000000000000000001"
+fee_manager_address = "0x5FbDB2315678afecb367f032d93F642f64180aa3"
+game_pool_address = "0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512"
+{}"#, admin, toml)).unwrap();
+        let args = ConfigArgs { config: Some(path), ..Default::default() };
+        let config = args.load().unwrap();
+        let game_config = GameConfig {
+            gameplay: config.gameplay.clone(),
+            economy: config.economy.clone(),
+            tournament: config.tournament.clone(),
+            collusion: config.collusion.clone(),
+            anti_cheat: config.anti_cheat.clone(),
+            connections: config.connections.clone(),
+        };
+        TestServer::start_reloadable(game_config, ConfigReloader::new(args, config)).await
+    }
+
+    /// Connects a client with a fresh wallet, without authenticating.
+    pub async fn connect(&self) -> TestClient {
+        self.connect_as(LocalWallet::new(&mut rand::thread_rng())).await
+    }
+
+    pub async fn connect_as(&self, wallet: LocalWallet) -> TestClient {
+        let url = format!("ws://{}", self.addr);
+        let (ws, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        TestClient {
+            address: format!("{:#x}", wallet.address()),
+            wallet,
+            ws,
+            next_id: 1,
+            // until it authenticates with a newer version
+            snapshots: SnapshotReceiver::for_version(protocol::MIN_PROTOCOL_VERSION, 256),
+            stamp: Stamp::default(),
+        }
+    }
+
+    /// Connects and authenticates a client, panicking if auth is rejected.
+    pub async fn login(&self) -> TestClient {
+        self.login_as(LocalWallet::new(&mut rand::thread_rng())).await
+    }
+
+    pub async fn login_as(&self, wallet: LocalWallet) -> TestClient {
+        let mut client = self.connect_as(wallet).await;
+        let response = client.authenticate().await;
+        assert!(response["error"].is_null(), "auth failed: {}", response);
+        client
+    }
+
+    /// Logs in a fresh player, buys them a ticket and enters them in a room.
+    pub async fn join_game(&self) -> TestClient {
+        let mut client = self.login().await;
+        self.enter_with_ticket(&mut client).await;
+        client
+    }
+
+    /// Buys an authenticated client a ticket and enters them in a room,
+    /// panicking if they are refused.
+    pub async fn enter_with_ticket(&self, client: &mut TestClient) {
+        self.buy_ticket(client).await;
+        let response = client.enter_game().await;
+        assert_eq!(response["result"], Value::Null, "enter_game failed: {}", response);
+    }
+
+    /// Mocks an `EntryFeePaid` event and waits for the ticket notification.
+    pub async fn buy_ticket(&self, client: &mut TestClient) -> i64 {
+        self.chain.entry_fee_paid(&client.address);
+        let notification = client.recv_notification("notify_tickets_update").await;
+        notification["params"][0].as_i64().unwrap()
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellFrame {
+    pub id: u32,
+    pub owner: u32,
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+    pub hue: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoodFrame {
+    pub id: u32,
+    pub x: f32,
+    pub y: f32,
+    pub hue: f32,
+}
+
+#[derive(Debug, Clone)]
+pub enum Frame {
+    Players {
+        x: f32,
+        y: f32,
+        visible_range: f32,
+        cells: Vec<CellFrame>,
+    },
+    Food(Vec<FoodFrame>),
+}
+
+impl Frame {
+    fn from_snapshot(snapshot: &Snapshot) -> Frame {
+        let quantizer = snapshot.quantizer;
+        match snapshot.view {
+            Some(view) => Frame::Players {
+                x: view.x,
+                y: view.y,
+                visible_range: view.visible_range,
+                cells: snapshot.entities.iter()
+                    .map(|entity| {
+                        let (x, y) = quantizer.position(entity);
+                        CellFrame {
+                            id: entity.id,
+                            owner: entity.owner,
+                            x,
+                            y,
+                            radius: quantizer.radius(entity),
+                            hue: quantizer.hue(entity),
+                        }
+                    })
+                    .collect(),
+            },
+            None => Frame::Food(snapshot.entities.iter()
+                .map(|entity| {
+                    let (x, y) = quantizer.position(entity);
+                    FoodFrame { id: entity.id, x, y, hue: quantizer.hue(entity) }
+                })
+                .collect()),
+        }
+    }
+}
+
+
+pub struct TestClient {
+    pub wallet: LocalWallet,
+    pub address: String,
+    ws: WsStream,
+    next_id: i64,
+    snapshots: SnapshotReceiver,
+    /// Of the last snapshot received.
+    pub stamp: Stamp,
+}
+
+impl TestClient {
+    pub async fn authenticate(&mut self) -> Value {
+        let signature = self.wallet.sign_message("let's play").await.unwrap();
+        let address = self.address.clone();
+        self.authenticate_with(&address, &signature.to_string()).await
+    }
+
+    pub async fn authenticate_with(&mut self, address: &str, signature: &str) -> Value {
+        self.send_json(json!({
+            "id": 0,
+            "jsonrpc": "2.0",
+            "method": "authenticate",
+            "params": {
+                "address": address,
+                "signature": signature,
+                "protocol_version": protocol::MIN_PROTOCOL_VERSION,
+            },
+        })).await;
+        self.recv_response(0).await
+    }
+
+    /// Authenticates as a client speaking protocol `version`.
+    pub async fn authenticate_speaking(&mut self, version: u32) -> Value {
+        let signature = self.wallet.sign_message("let's play").await.unwrap();
+        let message = ClientMessage::Authenticate(AuthParams {
+            address: self.address.clone(),
+            signature: signature.to_string(),
+            protocol_version: Some(version),
+        });
+        self.ws.send(Message::text(message.encode(Some(0)))).await.unwrap();
+        self.snapshots = SnapshotReceiver::for_version(version, 256);
+        self.recv_response(0).await
+    }
+
+    pub async fn send(&mut self, message: Message) {
+        self.ws.send(message).await.unwrap();
+    }
+
+    pub async fn send_json(&mut self, value: Value) {
+        self.send(Message::text(value.to_string())).await;
+    }
+
+    pub async fn call(&mut self, method: &str, params: Value) -> Value {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send_json(json!({
+            "id": id,
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        })).await;
+        self.recv_response(id).await
+    }
+
+    pub async fn notify(&mut self, method: &str, params: Value) {
+        self.send_json(json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        })).await;
+    }
+
+    pub async fn enter_game(&mut self) -> Value {
+        self.call("enter_game", json!([])).await
+    }
+
+    pub async fn target(&mut self, x: f64, y: f64) {
+        self.notify("target", json!({ "x": x, "y": y })).await;
+    }
+
+    pub async fn split(&mut self) {
+        self.notify("split", json!([])).await;
+    }
+
+    pub async fn eject(&mut self) {
+        self.notify("eject", json!([])).await;
+    }
+
+    /// Next message of any kind, or `None` when the server hung up.
+    pub async fn recv(&mut self) -> Option<Message> {
+        let msg = timeout(RECV_TIMEOUT, self.ws.next()).await
+            .expect("timed out waiting for a message from the server");
+        match msg {
+            Some(Ok(Message::Close(_))) | Some(Err(_)) | None => None,
+            Some(Ok(msg)) => Some(msg),
+        }
+    }
+
+    async fn recv_text_matching(&mut self, pred: impl Fn(&Value) -> bool) -> Value {
+        loop {
+            match self.recv().await {
+                Some(Message::Text(text)) => {
+                    let value: Value = serde_json::from_str(&text).unwrap();
+                    if pred(&value) {
+                        return value;
+                    }
+                }
+                Some(_) => continue,
+                None => panic!("connection closed before the expected message arrived"),
+            }
+        }
+    }
+
+    pub async fn recv_response(&mut self, id: i64) -> Value {
+        self.recv_text_matching(|v| v["id"] == json!(id)).await
+    }
+
+    pub async fn recv_notification(&mut self, method: &str) -> Value {
+        self.recv_text_matching(|v| v["method"] == json!(method)).await
+    }
+
+    pub async fn recv_frame(&mut self, pred: impl Fn(&Frame) -> bool) -> Frame {
+        loop {
+            match self.recv().await {
+                Some(Message::Binary(data)) => {
+                    let snapshot = match self.snapshots.apply(&data) {
+                        Ok(snapshot) => snapshot,
+                        Err(err) => panic!("undecodable snapshot: {}", err),
+                    };
+                    self.notify("ack_snapshot", json!({ "seq": snapshot.seq })).await;
+                    self.stamp = snapshot.stamp;
+                    let frame = Frame::from_snapshot(&snapshot);
+                    if pred(&frame) {
+                        return frame;
+                    }
+                }
+                Some(_) => continue,
+                None => panic!("connection closed before the expected frame arrived"),
+            }
+        }
+    }
+
+    pub async fn recv_players_frame(&mut self) -> Frame {
+        self.recv_frame(|f| matches!(f, Frame::Players { .. })).await
+    }
+
+    /// Steers the player's cells towards `(x, y)` using its latest position.
+    pub async fn steer_towards(&mut self, x: f32, y: f32) {
+        if let Frame::Players { x: px, y: py, .. } = self.recv_players_frame().await {
+            self.target((x - px) as f64, (y - py) as f64).await;
+        }
+    }
+}
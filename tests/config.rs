@@ -0,0 +1,119 @@
+use std::{env, fs, path::{Path, PathBuf}};
+
+use agario_rust::config::{BindAddress, Config, ConfigError};
+
+
+const VALID_CHAIN: &str = r#"
+[server]
+ws_port = 3000
+
+[chain]
+chain_id = 28
+provider_http_url = "http://localhost:8545"
+provider_ws_url = "ws://localhost:8546"
+secret_key = "0x0000000000000000000000000000000000000000000000000000000000000001"
+fee_manager_address = "0x5FbDB2315678afecb367f032d93F642f64180aa3"
+game_pool_address = "0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512"
+"#;
+
+fn write_config(name: &str, contents: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("agario-{}-{}.toml", name, std::process::id()));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+// tests run in parallel, so none of them reads or sets the process env
+fn load(path: &Path, overrides: &[String]) -> Result<Config, ConfigError> {
+    Config::load_with_env(Some(path), Vec::new(), overrides)
+}
+
+
+#[test]
+fn reports_every_error_at_once() {
+    let path = write_config("errors", r#"
+[chain]
+game_pool_address = "nope"
+
+[gameplay]
+log_base = 0.5
+
+[economy]
+win_percentage = 2.0
+"#);
+    let overrides = vec![String::from("gameplay.width=wide"), String::from("no_equals_sign")];
+    let errors = load(&path, &overrides).unwrap_err().0;
+
+    let expected = [
+        "--set gameplay.width=wide: expected an integer, got 'wide'",
+        "--set no_equals_sign: expected section.key=value",
+        "chain.game_pool_address must be an address (GAME_POOL_ADDRESS)",
+        "gameplay.log_base must be greater than 1",
+        "economy.win_percentage must be in (0, 1]",
+    ];
+    for message in expected.iter() {
+        assert!(errors.iter().any(|e| e == message), "missing '{}' in {:?}", message, errors);
+    }
+}
+
+#[test]
+fn rejects_unknown_keys() {
+    let path = write_config("unknown", &format!("{}\n[gameplay]\nmax_player = 5\n", VALID_CHAIN));
+    let errors = load(&path, &[]).unwrap_err().0;
+    assert!(errors[0].contains("max_player"), "{:?}", errors);
+}
+
+#[test]
+fn reports_every_mistyped_key() {
+    let path = write_config("types", &format!(
+        "{}\n[gameplay]\nwidth = \"wide\"\nmax_players = 5\n\n[economy]\nmultiplier = \"many\"\n",
+        VALID_CHAIN,
+    ));
+    let errors = load(&path, &[]).unwrap_err().0;
+    assert_eq!(errors.len(), 2, "{:?}", errors);
+    assert!(errors.iter().any(|e| e.starts_with("gameplay.width:")), "{:?}", errors);
+    assert!(errors.iter().any(|e| e.starts_with("economy.multiplier:")), "{:?}", errors);
+}
+
+#[test]
+fn file_then_env_then_flags() {
+    let path = write_config("layers", &format!("{}\n[gameplay]\nmax_players = 10\nwin_time = 5\n\n[economy]\nmultiplier = 3\n", VALID_CHAIN));
+    let vars = vec![
+        (String::from("AGARIO__GAMEPLAY__MAX_PLAYERS"), String::from("20")),
+        (String::from("MULTIPLIER"), String::from("4")),
+    ];
+
+    let overrides = vec![String::from("gameplay.max_players=30")];
+    let config = Config::load_with_env(Some(&path), vars, &overrides).unwrap();
+
+    assert_eq!(config.gameplay.max_players, 30);
+    assert_eq!(config.gameplay.win_time, 5);
+    assert_eq!(config.economy.multiplier, 4);
+    assert_eq!(config.server.ws_port, 3000);
+    assert_eq!(config.chain.token_address, None);
+    // untouched values keep their defaults
+    assert_eq!(config.gameplay.width, 5000);
+}
+
+#[test]
+fn parses_bind_addresses() {
+    assert_eq!("0.0.0.0:3000".parse(), Ok(BindAddress::Tcp(([0, 0, 0, 0], 3000).into())));
+    assert_eq!(
+        "[::]:3000".parse::<BindAddress>().unwrap().to_string(),
+        "[::]:3000",
+    );
+    assert_eq!("unix:/run/agario.sock".parse(), Ok(BindAddress::Unix(PathBuf::from("/run/agario.sock"))));
+    assert!("unix:".parse::<BindAddress>().is_err());
+    assert!("localhost".parse::<BindAddress>().is_err());
+}
+
+#[test]
+fn effective_config_hides_the_secret_key() {
+    let path = write_config("redacted", VALID_CHAIN);
+    let overrides = vec![String::from("server.bind=unix:/tmp/agario.sock")];
+    let config = load(&path, &overrides).unwrap();
+    assert_eq!(config.server.bind_address(), Ok(BindAddress::Unix(PathBuf::from("/tmp/agario.sock"))));
+
+    let printed = config.to_redacted_toml();
+    assert!(printed.contains("secret_key = \"<redacted>\""));
+    assert!(!printed.contains("0000000000000001"));
+}
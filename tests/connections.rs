@@ -0,0 +1,190 @@
+mod common;
+
+use std::{env, fs, time::{Duration, Instant}};
+
+use ethers::prelude::*;
+use serde_json::{json, Value};
+use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Message};
+
+use agario_rust::bans::{Ban, BanList, BanTarget, IpRange};
+use agario_rust::config::ConnectionConfig;
+use agario_rust::game::GameConfig;
+use agario_rust::limits::{ConnectionLimiter, Refusal};
+use common::TestServer;
+
+
+#[test]
+fn connections_are_limited_per_ip() {
+    let config = ConnectionConfig {
+        max_connections_per_ip: 2,
+        max_connection_attempts_per_min: 4,
+        max_failed_auths_per_min: 1,
+        ..Default::default()
+    };
+    let ip = "203.0.113.7".parse().unwrap();
+    let other = "203.0.113.8".parse().unwrap();
+    let start = Instant::now();
+    let mut limiter = ConnectionLimiter::default();
+
+    assert_eq!(limiter.open(ip, start, &config), Ok(()));
+    assert_eq!(limiter.open(ip, start, &config), Ok(()));
+    assert_eq!(limiter.open(ip, start, &config), Err(Refusal::TooManyConnections));
+    assert_eq!(limiter.open(other, start, &config), Ok(()));
+    limiter.close(ip);
+    assert_eq!(limiter.open(ip, start, &config), Ok(()));
+    // the refused attempt counts too
+    assert_eq!(limiter.open(ip, start, &config), Err(Refusal::TooManyAttempts));
+
+    limiter.auth_failed(other, start);
+    assert_eq!(limiter.open(other, start, &config), Err(Refusal::TooManyFailedAuths));
+    // a minute later both are forgotten
+    let later = start + Duration::from_secs(60);
+    limiter.close(ip);
+    assert_eq!(limiter.open(ip, later, &config), Ok(()));
+    assert_eq!(limiter.open(other, later, &config), Ok(()));
+}
+
+#[test]
+fn ban_targets_parse_addresses_and_ip_ranges() {
+    let range: IpRange = "203.0.113.0/24".parse().unwrap();
+    assert!(range.contains("203.0.113.200".parse().unwrap()));
+    assert!(!range.contains("203.0.114.1".parse().unwrap()));
+    assert!(!range.contains("::1".parse().unwrap()));
+    let range: IpRange = "2001:db8::/32".parse().unwrap();
+    assert!(range.contains("2001:db8:1::1".parse().unwrap()));
+    assert!("203.0.113.0/33".parse::<IpRange>().is_err());
+
+    assert_eq!(
+        "0x5FbDB2315678afecb367f032d93F642f64180aa3".parse::<BanTarget>(),
+        Ok(BanTarget::Address(String::from("0x5fbdb2315678afecb367f032d93f642f64180aa3"))),
+    );
+    assert_eq!("203.0.113.7".parse::<BanTarget>().unwrap().to_string(), "203.0.113.7/32");
+    assert!("not a target".parse::<BanTarget>().is_err());
+}
+
+#[test]
+fn ban_list_is_saved_and_expires() {
+    let path = env::temp_dir().join(format!("agario-bans-{}.json", std::process::id()));
+    fs::remove_file(&path).ok();
+
+    let mut bans = BanList::load(Some(path.clone())).unwrap();
+    bans.add(Ban {
+        target: "203.0.113.0/24".parse().unwrap(),
+        reason: String::from("bots"),
+        until: None,
+    });
+    bans.add(Ban {
+        target: "0x5FbDB2315678afecb367f032d93F642f64180aa3".parse().unwrap(),
+        reason: String::from("cheating"),
+        until: Some(1000),
+    });
+
+    let mut bans = BanList::load(Some(path.clone())).unwrap();
+    assert!(bans.is_ip_banned("203.0.113.9".parse().unwrap(), 500));
+    assert!(bans.is_address_banned("0x5fbdb2315678afecb367f032d93f642f64180aa3", 500));
+    assert!(!bans.is_address_banned("0x5fbdb2315678afecb367f032d93f642f64180aa3", 1000));
+    assert_eq!(bans.active(1000).len(), 1);
+
+    assert!(bans.remove(&"203.0.113.0/24".parse().unwrap()));
+    let bans = BanList::load(Some(path.clone())).unwrap();
+    assert!(!bans.is_ip_banned("203.0.113.9".parse().unwrap(), 500));
+    fs::remove_file(&path).ok();
+}
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn connections_over_the_limit_are_refused() {
+    let server = TestServer::start(GameConfig {
+        connections: ConnectionConfig {
+            max_connections_per_ip: 2,
+            ..Default::default()
+        },
+        ..Default::default()
+    }).await;
+    let _first = server.login().await;
+    let second = server.login().await;
+
+    let url = format!("ws://{}", server.addr);
+    assert!(tokio_tungstenite::connect_async(url.clone()).await.is_err());
+
+    // closing one frees its slot
+    drop(second);
+    let mut attempts = 0;
+    while tokio_tungstenite::connect_async(url.clone()).await.is_err() {
+        attempts += 1;
+        assert!(attempts < 50, "the closed connection was never released");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn proxied_clients_are_limited_by_their_own_ip() {
+    let server = TestServer::start(GameConfig {
+        connections: ConnectionConfig {
+            max_connections_per_ip: 1,
+            real_ip_header: Some(String::from("X-Forwarded-For")),
+            trusted_proxies: vec!["127.0.0.1".parse().unwrap()],
+            ..Default::default()
+        },
+        ..Default::default()
+    }).await;
+    let connect = |ip: &str| {
+        let mut request = format!("ws://{}", server.addr).into_client_request().unwrap();
+        // the proxy appends the ip it saw to whatever the client sent
+        request.headers_mut().insert("X-Forwarded-For", format!("198.51.100.1, {}", ip).parse().unwrap());
+        tokio_tungstenite::connect_async(request)
+    };
+
+    let _first = connect("203.0.113.7").await.unwrap();
+    assert!(connect("203.0.113.7").await.is_err());
+    assert!(connect("203.0.113.8").await.is_ok());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn frames_that_are_not_text_are_ignored() {
+    let server = TestServer::start(GameConfig::default()).await;
+    let mut client = server.login().await;
+
+    client.send(Message::binary(vec![0xff, 0xfe])).await;
+    assert!(client.call("get_server_info", json!([])).await["result"].is_object());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn admins_ban_addresses_at_runtime() {
+    let admin = LocalWallet::new(&mut rand::thread_rng());
+    let server = TestServer::start_with_admin(&admin, "").await;
+    let mut admin = server.login_as(admin).await;
+    let mut player = server.login().await;
+
+    let response = player.call("add_ban", json!({ "target": admin.address })).await;
+    assert_eq!(response["error"]["message"], "Not allowed to manage bans");
+    let response = admin.call("add_ban", json!({ "target": "nonsense" })).await;
+    assert!(response["error"]["message"].as_str().unwrap().contains("nonsense"));
+
+    let response = admin.call("add_ban", json!({
+        "target": player.address,
+        "reason": "botting",
+        "secs": 3600,
+    })).await;
+    assert_eq!(response["result"], Value::Null);
+    let banned = player.recv_notification("notify_banned").await;
+    assert_eq!(banned["params"]["reason"], "botting");
+    while player.recv().await.is_some() {}
+
+    let bans = admin.call("list_bans", json!([])).await["result"].clone();
+    assert_eq!(bans[0]["target"], json!(player.address));
+    let mut again = server.connect_as(player.wallet.clone()).await;
+    assert_eq!(again.authenticate().await["error"]["message"], "This address is banned");
+
+    let response = admin.call("remove_ban", json!({ "target": player.address })).await;
+    assert_eq!(response["result"]["removed"], true);
+    // the banned connection may still be closing
+    for attempt in 0.. {
+        let mut client = server.connect_as(player.wallet.clone()).await;
+        if client.authenticate().await["error"].is_null() {
+            break;
+        }
+        assert!(attempt < 5, "could not log in after the ban was lifted");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
@@ -0,0 +1,221 @@
+mod common;
+
+use ethers::prelude::*;
+use serde_json::{json, Value};
+
+use agario_rust::config::{EconomyConfig, GameplayConfig};
+use agario_rust::game::GameConfig;
+use common::{CellFrame, Frame, TestServer};
+
+
+fn score_of(metadata: &Value, address: &str) -> Option<u64> {
+    metadata["params"]["scores"].as_array()?
+        .iter()
+        .find(|s| s[0] == json!(address))
+        .and_then(|s| s[1].as_u64())
+}
+
+/// Whether the cell is at `(x, y)`, give or take the position quantization.
+fn is_at(cell: &CellFrame, x: f32, y: f32) -> bool {
+    (cell.x - x).abs() < 0.1 && (cell.y - y).abs() < 0.1
+}
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejects_signature_from_another_wallet() {
+    let server = TestServer::start(GameConfig::default()).await;
+    let other = LocalWallet::new(&mut rand::thread_rng());
+    let signature = other.sign_message("let's play").await.unwrap();
+
+    let mut client = server.connect().await;
+    let address = client.address.clone();
+    let response = client.authenticate_with(&address, &signature.to_string()).await;
+
+    assert_eq!(response["error"]["message"], "Address cannot be recovered from signature");
+    assert!(client.recv().await.is_none());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejects_second_connection_for_same_address() {
+    let server = TestServer::start(GameConfig::default()).await;
+    let first = server.login().await;
+    let signature = first.wallet.sign_message("let's play").await.unwrap();
+
+    let mut second = server.connect().await;
+    let response = second.authenticate_with(&first.address, &signature.to_string()).await;
+
+    assert_eq!(response["error"]["message"], "This address is already connected from a different client");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn entering_requires_a_ticket() {
+    let server = TestServer::start(GameConfig::default()).await;
+    let mut client = server.login().await;
+
+    let response = client.enter_game().await;
+    assert_eq!(response["error"]["message"], "You have no tickets to play!");
+
+    assert_eq!(server.buy_ticket(&mut client).await, 1);
+    assert_eq!(client.call("get_available_tickets", json!([])).await["result"], 1);
+
+    let response = client.enter_game().await;
+    assert_eq!(response["result"], Value::Null);
+    let notification = client.recv_notification("notify_tickets_update").await;
+    assert_eq!(notification["params"][0], 0);
+
+    let response = client.enter_game().await;
+    assert_eq!(response["error"]["message"], "You are already in game!");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn streams_own_cell_and_score() {
+    let server = TestServer::start(GameConfig::default()).await;
+    let mut client = server.join_game().await;
+
+    let frame = client.recv_players_frame().await;
+    let (x, y) = match frame {
+        Frame::Players { x, y, cells, .. } => {
+            let own = cells.iter()
+                .find(|c| is_at(c, x, y))
+                .expect("own cell missing from update");
+            // default mass of 10, sent in eighths
+            assert!((own.radius - (4. + 10f32.sqrt() * 6.)).abs() <= 1. / 16.);
+            (x, y)
+        }
+        _ => unreachable!(),
+    };
+
+    // a fresh cell is too small to split
+    client.split().await;
+    client.target(500., 0.).await;
+    loop {
+        if let Frame::Players { x: new_x, y: new_y, cells, .. } = client.recv_players_frame().await {
+            if new_x != x {
+                assert!(new_x > x);
+                assert_eq!(new_y, y);
+                assert_eq!(cells.iter().filter(|c| is_at(c, new_x, new_y)).count(), 1);
+                break;
+            }
+        }
+    }
+
+    let metadata = client.recv_notification("notify_update_metadata").await;
+    assert_eq!(score_of(&metadata, &client.address), Some(10));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn largest_player_eats_newcomer_and_gets_paid() {
+    // Small enough that a cell of mass 70 covers every spawn point.
+    let config = GameConfig {
+        gameplay: GameplayConfig {
+            width: 60,
+            height: 60,
+            win_time: 1,
+            win_mass_threshold: 150,
+            ..Default::default()
+        },
+        economy: EconomyConfig {
+            multiplier: 2,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut server = TestServer::start(config).await;
+    let mut hunter = server.join_game().await;
+    let mut prey = server.login().await;
+    loop {
+        let metadata = hunter.recv_notification("notify_update_metadata").await;
+        if score_of(&metadata, &hunter.address).unwrap_or(0) >= 70 {
+            break;
+        }
+    }
+
+    server.enter_with_ticket(&mut prey).await;
+    prey.recv_notification("notify_game_over").await;
+
+    let won = hunter.recv_notification("notify_won").await;
+    // a number for clients from before version 3
+    let amount_won = won["params"][0].as_f64().unwrap() as u64;
+    assert!(amount_won >= 135 * 2);
+
+    let winner = server.chain.next_winner().await.unwrap();
+    assert_eq!(format!("{:#x}", winner.address), hunter.address);
+    assert_eq!(winner.amount, U256::from(amount_won) * U256::exp10(9));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn server_info_follows_chain_parameters() {
+    let server = TestServer::start(GameConfig::default()).await;
+    let mut client = server.login().await;
+
+    let info = client.call("get_server_info", json!([])).await;
+    assert_eq!(info["result"]["entry_fee"], Value::Null);
+
+    server.chain.token_decimals_changed(18);
+    server.chain.entry_fee_changed(U256::from(25) * U256::exp10(17));
+    loop {
+        let info = client.call("get_server_info", json!([])).await;
+        if info["result"]["entry_fee"] != Value::Null {
+            assert_eq!(info["result"]["entry_fee"], "2.5");
+            assert_eq!(info["result"]["token_decimals"], 18);
+            break;
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn token_decimals_are_kept_and_entries_paused_once_tokens_are_held() {
+    let server = TestServer::start(GameConfig::default()).await;
+    let mut client = server.login().await;
+    server.chain.rewards_added(U256::from(500) * U256::exp10(9));
+    loop {
+        let info = client.call("get_server_info", json!([])).await;
+        if info["result"]["obligations"] != "0" {
+            break;
+        }
+    }
+
+    server.chain.token_decimals_changed(18);
+    // changes after it are applied in order
+    server.chain.entry_fee_changed(U256::exp10(9));
+    loop {
+        let info = client.call("get_server_info", json!([])).await;
+        if info["result"]["entry_fee"] != Value::Null {
+            assert_eq!(info["result"]["token_decimals"], 9);
+            // until then paid entries wait
+            assert_eq!(info["result"]["reported_token_decimals"], 18);
+            assert_eq!(info["result"]["accepting_entries"], false);
+            break;
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pauses_paid_entries_when_pool_cannot_pay() {
+    let server = TestServer::start(GameConfig::default()).await;
+    let mut client = server.login().await;
+    server.buy_ticket(&mut client).await;
+
+    // the ticket put 90 mass into play, worth 90 tokens
+    let gas = U256::exp10(18);
+    server.chain.wallet_status(gas, U256::from(89) * U256::exp10(9));
+    loop {
+        let info = client.call("get_server_info", json!([])).await;
+        if info["result"]["accepting_entries"] == false {
+            assert_eq!(info["result"]["obligations"], "90000000000");
+            break;
+        }
+    }
+    let response = client.enter_game().await;
+    assert_eq!(response["error"]["message"], "Server cannot pay out winners right now, entries are paused!");
+    assert_eq!(client.call("get_available_tickets", json!([])).await["result"], 1);
+
+    server.chain.wallet_status(gas, U256::from(90) * U256::exp10(9));
+    loop {
+        let info = client.call("get_server_info", json!([])).await;
+        if info["result"]["accepting_entries"] == true {
+            break;
+        }
+    }
+    assert_eq!(client.enter_game().await["result"], Value::Null);
+}
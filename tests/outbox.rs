@@ -0,0 +1,80 @@
+mod common;
+
+use std::time::Duration;
+
+use serde_json::json;
+use tokio_tungstenite::tungstenite::Message;
+
+use agario_rust::game::GameConfig;
+use agario_rust::outbox::{Frame, Outbox, OutboxStats};
+use common::TestServer;
+
+
+#[tokio::test]
+async fn only_the_newest_frame_is_written() {
+    let outbox = Outbox::new(16, Duration::from_secs(10));
+    outbox.send_frame(Frame::Players, Message::binary(vec![0, 1]));
+    outbox.send_frame(Frame::Food, Message::binary(vec![1, 1]));
+    outbox.send_frame(Frame::Players, Message::binary(vec![0, 2]));
+    outbox.send(Message::text("response"));
+    assert_eq!(outbox.stats(), OutboxStats { queued: 3, dropped_frames: 1 });
+
+    // responses go first, frames keep their place in line
+    assert_eq!(outbox.next().await, Some(Message::text("response")));
+    assert_eq!(outbox.next().await, Some(Message::binary(vec![0, 2])));
+    assert_eq!(outbox.next().await, Some(Message::binary(vec![1, 1])));
+    assert_eq!(outbox.stats().queued, 0);
+
+    // a writer waiting on an empty queue wakes up on the next message
+    let writer = tokio::spawn({
+        let outbox = outbox.clone();
+        async move { outbox.next().await }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    outbox.send_frame(Frame::Metadata, Message::text("metadata"));
+    assert_eq!(writer.await.unwrap(), Some(Message::text("metadata")));
+}
+
+#[tokio::test]
+async fn clients_that_pile_up_messages_are_lagging() {
+    let outbox = Outbox::new(2, Duration::from_secs(10));
+    outbox.send(Message::text("1"));
+    outbox.send(Message::text("2"));
+    assert!(!outbox.is_lagging());
+    outbox.send(Message::text("3"));
+    assert!(outbox.is_lagging());
+    assert_eq!(outbox.next().await, None);
+    tokio::time::timeout(Duration::from_secs(1), outbox.lagged()).await.unwrap();
+}
+
+#[tokio::test]
+async fn clients_that_keep_missing_frames_are_lagging() {
+    let outbox = Outbox::new(16, Duration::from_millis(50));
+    outbox.send_frame(Frame::Players, Message::binary(vec![0]));
+    // catching up in time is fine
+    outbox.send_frame(Frame::Players, Message::binary(vec![0]));
+    tokio::time::sleep(Duration::from_millis(30)).await;
+    assert!(outbox.next().await.is_some());
+    outbox.send_frame(Frame::Players, Message::binary(vec![0]));
+    tokio::time::sleep(Duration::from_millis(30)).await;
+    outbox.send_frame(Frame::Players, Message::binary(vec![0]));
+    assert!(!outbox.is_lagging());
+
+    for _ in 0..5 {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        outbox.send_frame(Frame::Players, Message::binary(vec![0]));
+    }
+    assert!(outbox.is_lagging());
+    tokio::time::timeout(Duration::from_secs(1), outbox.lagged()).await.unwrap();
+}
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn server_info_reports_outbound_queues() {
+    let server = TestServer::start(GameConfig::default()).await;
+    let mut client = server.login().await;
+    let info = client.call("get_server_info", json!([])).await;
+    let outbound = &info["result"]["outbound"];
+    assert!(outbound["connections"].as_u64().unwrap() >= 1);
+    assert_eq!(outbound["lagging_disconnects"], 0);
+}
@@ -0,0 +1,306 @@
+mod common;
+
+use proptest::prelude::*;
+use serde_json::json;
+
+use agario_rust::game::{GameConfig, TeamScore, Zone};
+use agario_rust::protocol::{
+    self, Channel, ClientMessage, Delta, Entity, InputParams, Keyframe, Metadata, Move, Notification,
+    Owner, Owners, PingParams, Quantizer, SnapshotFrame, Stamp, TargetParams, View, ZoneStatus,
+};
+use agario_rust::snapshot::SnapshotReceiver;
+use common::TestServer;
+
+
+fn channel() -> impl Strategy<Value = Channel> {
+    prop_oneof![Just(Channel::Players), Just(Channel::Food)]
+}
+
+fn view(channel: Channel) -> BoxedStrategy<Option<View>> {
+    match channel {
+        Channel::Players => (-1e6f32..1e6, -1e6f32..1e6, 0f32..1e6)
+            .prop_map(|(x, y, visible_range)| Some(View { x, y, visible_range }))
+            .boxed(),
+        Channel::Food => Just(None).boxed(),
+    }
+}
+
+fn entity(channel: Channel) -> impl Strategy<Value = Entity> {
+    let owner = match channel {
+        Channel::Players => any::<u32>().boxed(),
+        Channel::Food => Just(0).boxed(),
+    };
+    (any::<u32>(), owner, any::<u16>(), any::<u16>(), any::<u16>(), any::<u16>())
+        .prop_map(|(id, owner, x, y, radius, hue)| Entity { id, owner, x, y, radius, hue })
+}
+
+fn moved() -> impl Strategy<Value = Move> {
+    (any::<u32>(), any::<u16>(), any::<u16>(), any::<u16>())
+        .prop_map(|(id, x, y, radius)| Move { id, x, y, radius })
+}
+
+fn stamp() -> impl Strategy<Value = Stamp> {
+    (any::<u32>(), any::<u32>()).prop_map(|(tick, input)| Stamp { tick, input })
+}
+
+fn version() -> impl Strategy<Value = u32> {
+    protocol::MIN_PROTOCOL_VERSION..=protocol::PROTOCOL_VERSION
+}
+
+fn frame() -> impl Strategy<Value = SnapshotFrame> {
+    channel().prop_flat_map(|channel| {
+        let keyframe = (any::<u32>(), stamp(), 1f32..1e5, 1f32..1e5, view(channel), prop::collection::vec(entity(channel), 0..20))
+            .prop_map(move |(seq, stamp, width, height, view, entities)| SnapshotFrame::Keyframe(Keyframe {
+                channel,
+                seq,
+                stamp,
+                arena: Quantizer { width, height },
+                view,
+                entities,
+            }));
+        let delta = (
+            any::<u32>(),
+            stamp(),
+            any::<u32>(),
+            view(channel),
+            prop::collection::vec(entity(channel), 0..20),
+            prop::collection::vec(moved(), 0..20),
+            prop::collection::vec(any::<u32>(), 0..20),
+        )
+            .prop_map(move |(seq, stamp, base, view, spawned, moved, despawned)| SnapshotFrame::Delta(Delta {
+                channel,
+                seq,
+                stamp,
+                base,
+                view,
+                spawned,
+                moved,
+                despawned,
+            }));
+        prop_oneof![keyframe, delta]
+    })
+}
+
+fn json_value() -> impl Strategy<Value = serde_json::Value> {
+    let leaf = prop_oneof![
+        Just(serde_json::Value::Null),
+        any::<bool>().prop_map(|b| json!(b)),
+        any::<i64>().prop_map(|n| json!(n)),
+        any::<f64>().prop_filter("finite", |f| f.is_finite()).prop_map(|f| json!(f)),
+        ".{0,12}".prop_map(|s| json!(s)),
+    ];
+    leaf.prop_recursive(3, 24, 6, |inner| prop_oneof![
+        prop::collection::vec(inner.clone(), 0..6).prop_map(|values| json!(values)),
+        prop::collection::hash_map("[a-z_]{1,12}", inner, 0..6).prop_map(|map| json!(map)),
+    ])
+}
+
+fn method() -> impl Strategy<Value = String> {
+    prop_oneof![
+        Just("notify_tickets_update"),
+        Just("notify_owners"),
+        Just("notify_update_metadata"),
+        Just("notify_zone"),
+        Just("notify_won"),
+        Just("notify_banned"),
+        Just("authenticate"),
+        Just("target"),
+        Just("ack_snapshot"),
+        Just("enter_game"),
+        Just("split"),
+        Just("ping"),
+    ].prop_map(String::from)
+}
+
+// frames of versions without stamps come back without them
+fn as_sent(mut frame: SnapshotFrame, version: u32) -> SnapshotFrame {
+    if version < 2 {
+        match &mut frame {
+            SnapshotFrame::Keyframe(keyframe) => keyframe.stamp = Stamp::default(),
+            SnapshotFrame::Delta(delta) => delta.stamp = Stamp::default(),
+        }
+    }
+    frame
+}
+
+
+proptest! {
+    #[test]
+    fn frames_round_trip(frame in frame(), version in version()) {
+        prop_assert_eq!(SnapshotFrame::decode(&frame.encode(version), version), Ok(as_sent(frame, version)));
+    }
+
+    #[test]
+    fn random_bytes_never_panic(data in prop::collection::vec(any::<u8>(), 0..256), version in version()) {
+        let _ = SnapshotFrame::decode(&data, version);
+        let mut receiver = SnapshotReceiver::for_version(version, 8);
+        let _ = receiver.apply(&data);
+    }
+
+    #[test]
+    fn mutated_frames_never_panic(
+        base in frame(),
+        frame in frame(),
+        flips in prop::collection::vec((any::<prop::sample::Index>(), any::<u8>()), 1..4),
+        cut in any::<prop::sample::Index>(),
+    ) {
+        let mut receiver = SnapshotReceiver::new(8);
+        let _ = receiver.apply(&base.encode(protocol::PROTOCOL_VERSION));
+        let mut data = frame.encode(protocol::PROTOCOL_VERSION);
+        for (at, byte) in flips {
+            let at = at.index(data.len());
+            data[at] ^= byte;
+        }
+        let _ = receiver.apply(&data);
+        let _ = receiver.apply(&data[..cut.index(data.len())]);
+    }
+
+    #[test]
+    fn random_text_never_panics(text in ".{0,200}") {
+        let _ = Notification::decode(&text);
+        let _ = ClientMessage::decode(&text);
+    }
+
+    #[test]
+    fn random_params_never_panic(method in method(), params in json_value(), id in any::<Option<i64>>()) {
+        let text = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }).to_string();
+        let _ = Notification::decode(&text);
+        let _ = ClientMessage::decode(&text);
+    }
+}
+
+
+#[test]
+fn notifications_round_trip() {
+    let zone = Zone { x: 100., y: 200., width: 2500.5, height: 1000. };
+    let notifications = vec![
+        Notification::TicketsUpdate(3),
+        Notification::Owners(Owners {
+            added: vec![Owner { id: 7, address: String::from("0xabc"), team: Some(1) }],
+            removed: vec![3],
+            own: Some(7),
+        }),
+        Notification::UpdateMetadata(Metadata {
+            scores: vec![(String::from("0xabc"), 120)],
+            teams: vec![TeamScore { team: 1, mass: 120, players: vec![String::from("0xabc")] }],
+            win_counter: None,
+        }),
+        Notification::Zone(ZoneStatus::Running { zone, final_zone: zone, ends_in: 30 }),
+        Notification::Zone(ZoneStatus::Waiting { zone, final_zone: None, players_needed: 2 }),
+        Notification::GameOver,
+        Notification::Won(String::from("1.5")),
+    ];
+    for notification in notifications {
+        assert_eq!(Notification::decode(&notification.encode()), Ok(notification));
+    }
+}
+
+#[test]
+fn amounts_won_are_numbers_before_version_3() {
+    let won = Notification::Won(String::from("1.5"));
+    let old: serde_json::Value = serde_json::from_str(&won.encode_for(2)).unwrap();
+    assert_eq!(old["params"], json!([1.5]));
+    assert_eq!(Notification::decode(&won.encode_for(2)), Ok(won.clone()));
+    let new: serde_json::Value = serde_json::from_str(&won.encode_for(3)).unwrap();
+    assert_eq!(new["params"], json!(["1.5"]));
+}
+
+#[test]
+fn client_messages_round_trip() {
+    let messages = vec![
+        ClientMessage::Target(TargetParams { x: 1.5, y: -2., seq: Some(9) }),
+        ClientMessage::Split(InputParams { seq: Some(10) }),
+        ClientMessage::Split(InputParams::default()),
+        ClientMessage::Ping(PingParams { client_time: 1234.5 }),
+    ];
+    for message in messages {
+        assert_eq!(ClientMessage::decode(&message.encode(Some(4))), Ok((Some(json!(4)), message)));
+    }
+
+    // the forms older clients send
+    let text = json!({ "jsonrpc": "2.0", "method": "target", "params": [3., 4.] }).to_string();
+    assert_eq!(
+        ClientMessage::decode(&text),
+        Ok((None, ClientMessage::Target(TargetParams { x: 3., y: 4., seq: None }))),
+    );
+    let text = json!({ "jsonrpc": "2.0", "method": "split", "params": [] }).to_string();
+    assert_eq!(ClientMessage::decode(&text), Ok((None, ClientMessage::Split(InputParams::default()))));
+}
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn supported_versions_are_confirmed() {
+    let server = TestServer::start(GameConfig::default()).await;
+    let mut client = server.connect().await;
+    let response = client.authenticate_speaking(protocol::PROTOCOL_VERSION).await;
+    assert_eq!(response["result"], json!({ "protocol_version": protocol::PROTOCOL_VERSION }));
+
+    server.enter_with_ticket(&mut client).await;
+    let joined = client.recv_notification("notify_room_joined").await;
+    assert!(matches!(Notification::decode(&joined.to_string()), Ok(Notification::RoomJoined(_))));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unsupported_versions_are_refused() {
+    let server = TestServer::start(GameConfig::default()).await;
+    let mut client = server.connect().await;
+    let response = client.authenticate_speaking(protocol::PROTOCOL_VERSION + 1).await;
+    assert_eq!(response["error"]["code"], 1000);
+    assert_eq!(response["error"]["message"], "Unsupported protocol version");
+    assert_eq!(response["error"]["data"]["max"], protocol::PROTOCOL_VERSION);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn clients_from_before_versioning_are_refused() {
+    let server = TestServer::start(GameConfig::default()).await;
+    let mut client = server.connect().await;
+    client.send_json(json!({
+        "id": 0,
+        "jsonrpc": "2.0",
+        "method": "authenticate",
+        "params": { "address": client.address, "signature": "0x00" },
+    })).await;
+    let response = client.recv_response(0).await;
+    assert_eq!(response["error"]["message"], "Unsupported protocol version");
+    assert_eq!(response["error"]["data"]["min"], protocol::MIN_PROTOCOL_VERSION);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn snapshots_acknowledge_inputs() {
+    let server = TestServer::start(GameConfig::default()).await;
+    let mut client = server.connect().await;
+    client.authenticate_speaking(protocol::PROTOCOL_VERSION).await;
+    server.enter_with_ticket(&mut client).await;
+
+    client.recv_players_frame().await;
+    assert_eq!(client.stamp.input, 0);
+    let tick = client.stamp.tick;
+    client.notify("target", json!({ "x": 10., "y": 0., "seq": 5 })).await;
+    client.notify("split", json!({ "seq": 6 })).await;
+    // an input is acknowledged from the tick that moved the player on
+    let mut previous = client.stamp;
+    while client.stamp.input != 6 {
+        client.recv_players_frame().await;
+        if client.stamp.input != previous.input {
+            assert_ne!(client.stamp.tick, previous.tick);
+        }
+        previous = client.stamp;
+    }
+    assert!(client.stamp.tick > tick);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ping_reports_server_time_and_tick() {
+    let server = TestServer::start(GameConfig::default()).await;
+    let mut client = server.login().await;
+    let pong = client.call("ping", json!({ "client_time": 12.5 })).await["result"].clone();
+    assert_eq!(pong["client_time"], 12.5);
+    assert_eq!(pong["tick"], serde_json::Value::Null);
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64;
+    assert!((pong["server_time"].as_u64().unwrap() as i64 - now as i64).abs() < 5000);
+
+    server.enter_with_ticket(&mut client).await;
+    client.recv_players_frame().await;
+    let pong = client.call("ping", json!({ "client_time": 13 })).await["result"].clone();
+    assert!(pong["tick"].as_u64().is_some());
+}
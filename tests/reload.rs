@@ -0,0 +1,128 @@
+mod common;
+
+use std::{env, fs, path::PathBuf};
+
+use ethers::prelude::*;
+use serde_json::json;
+use tokio::time::{sleep, Duration};
+
+use agario_rust::config::ConfigArgs;
+use agario_rust::game::GameConfig;
+use agario_rust::reload::ConfigReloader;
+use common::TestServer;
+
+
+fn write_config(admin: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("agario-reload-{}.toml", admin));
+    fs::write(&path, format!(r#"
+[server]
+ws_port = 3000
+admin_addresses = ["{}"]
+
+[chain]
+chain_id = 28
+provider_http_url = "http://localhost:8545"
+provider_ws_url = "ws://localhost:8546"
+secret_key = "0x0000000000000000000000000000000000000000000000000000000000000001"
+fee_manager_address = "0x5FbDB2315678afecb367f032d93F642f64180aa3"
+game_pool_address = "0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512"
+
+[gameplay]
+max_players = 2
+"#, admin)).unwrap();
+    path
+}
+
+async fn start(admin: &LocalWallet) -> TestServer {
+    let args = ConfigArgs {
+        config: Some(write_config(&format!("{:#x}", admin.address()))),
+        ..Default::default()
+    };
+    let config = args.load().unwrap();
+    let game_config = GameConfig {
+        gameplay: config.gameplay.clone(),
+        economy: config.economy.clone(),
+        tournament: config.tournament.clone(),
+        collusion: config.collusion.clone(),
+        anti_cheat: config.anti_cheat.clone(),
+        connections: config.connections.clone(),
+    };
+    TestServer::start_reloadable(game_config, ConfigReloader::new(args, config)).await
+}
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn admin_reloads_gameplay_at_the_next_tick() {
+    let admin = LocalWallet::new(&mut rand::thread_rng());
+    let server = start(&admin).await;
+    let mut client = server.login_as(admin).await;
+
+    let info = client.call("get_server_info", json!([])).await;
+    assert_eq!(info["result"]["max_players"], 2);
+
+    let response = client.call("reload_config", json!({ "set": ["gameplay.max_players=5"] })).await;
+    assert!(response["error"].is_null(), "{}", response);
+
+    let mut max_players = None;
+    for _ in 0..50 {
+        let info = client.call("get_server_info", json!([])).await;
+        max_players = info["result"]["max_players"].as_u64();
+        if max_players == Some(5) {
+            break;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(max_players, Some(5));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejects_fields_that_need_a_restart() {
+    let admin = LocalWallet::new(&mut rand::thread_rng());
+    let server = start(&admin).await;
+    let mut client = server.login_as(admin).await;
+
+    let response = client.call("reload_config", json!({
+        "set": ["gameplay.width=6000", "chain.chain_id=1", "gameplay.max_players=9"],
+    })).await;
+    assert_eq!(response["error"]["message"], "Config reload rejected");
+    let errors = response["error"]["data"].as_array().unwrap();
+    assert!(errors.contains(&json!("gameplay.width cannot change while the server is running")));
+    assert!(errors.contains(&json!("chain cannot change while the server is running")));
+
+    // nothing from a rejected reload is applied
+    sleep(Duration::from_millis(100)).await;
+    let info = client.call("get_server_info", json!([])).await;
+    assert_eq!(info["result"]["max_players"], 2);
+}
+
+#[test]
+fn overrides_survive_later_reloads() {
+    let admin = LocalWallet::new(&mut rand::thread_rng());
+    let args = ConfigArgs {
+        config: Some(write_config(&format!("{:#x}", admin.address()))),
+        ..Default::default()
+    };
+    let config = args.load().unwrap();
+    let reloader = ConfigReloader::new(args, config);
+
+    let reloaded = reloader.reload(&[String::from("gameplay.max_players=5")]).unwrap();
+    assert_eq!(reloaded.gameplay.max_players, 5);
+    // as a SIGHUP does
+    let reloaded = reloader.reload(&[]).unwrap();
+    assert_eq!(reloaded.gameplay.max_players, 5);
+
+    // a rejected reload doesn't keep its overrides either
+    let errors = reloader.reload(&[String::from("economy.no_entry_fee=true")]).unwrap_err();
+    assert!(errors.contains(&String::from("economy.no_entry_fee cannot change while the server is running")), "{:?}", errors);
+    assert!(!reloader.reload(&[]).unwrap().economy.no_entry_fee);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn only_admins_can_reload() {
+    let admin = LocalWallet::new(&mut rand::thread_rng());
+    let server = start(&admin).await;
+    let mut client = server.login().await;
+
+    let response = client.call("reload_config", json!([])).await;
+    assert_eq!(response["error"]["message"], "Not allowed to reload the config");
+}
@@ -0,0 +1,264 @@
+mod common;
+
+use ethers::prelude::*;
+use serde_json::{json, Value};
+
+use agario_rust::config::{EconomyConfig, GameplayConfig};
+use agario_rust::game::GameConfig;
+use common::{TestClient, TestServer};
+
+
+fn config() -> GameConfig {
+    GameConfig {
+        gameplay: GameplayConfig {
+            max_players: 1,
+            max_rooms: 2,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+async fn player_with_ticket(server: &TestServer) -> TestClient {
+    let mut client = server.login().await;
+    server.buy_ticket(&mut client).await;
+    client
+}
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn opens_rooms_as_they_fill() {
+    let server = TestServer::start(config()).await;
+
+    let mut first = server.join_game().await;
+    assert_eq!(first.recv_notification("notify_room_joined").await["params"]["room_id"], 1);
+
+    let mut second = server.join_game().await;
+    assert_eq!(second.recv_notification("notify_room_joined").await["params"]["room_id"], 2);
+
+    let rooms = second.call("list_rooms", json!([])).await;
+    let rooms = rooms["result"].as_array().unwrap();
+    assert_eq!(rooms.len(), 2);
+    assert!(rooms.iter().all(|room| room["players"] == 1 && room["max_players"] == 1));
+
+    // both rooms are full and no more can be opened
+    let mut third = player_with_ticket(&server).await;
+    assert_eq!(third.enter_game().await["error"]["message"], "Server is full!");
+    assert_eq!(third.call("join_room", json!({ "room_id": 1 })).await["error"]["message"], "Server is full!");
+    assert_eq!(third.call("get_available_tickets", json!([])).await["result"], 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn joins_a_room_by_id() {
+    let server = TestServer::start(config()).await;
+
+    let mut first = player_with_ticket(&server).await;
+    assert_eq!(first.call("join_room", json!({ "room_id": 3 })).await["error"]["message"], "There is no room with this id!");
+    assert_eq!(first.call("join_room", json!({ "room_id": 1 })).await["result"], Value::Null);
+    assert_eq!(first.call("join_room", json!({ "room_id": 1 })).await["error"]["message"], "You are already in game!");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rewards_are_shared_between_rooms() {
+    let server = TestServer::start(config()).await;
+    let _first = server.join_game().await;
+    let mut second = server.join_game().await;
+
+    // 2.5 tokens per room, half a token of each stays as dust
+    server.chain.rewards_added(U256::from(5) * U256::exp10(9));
+
+    // two tickets of 90 mass, two players spawned with 10 mass and the deposit
+    let expected = (U256::from(2 * 90 + 2 * 10 + 5) * U256::exp10(9)).to_string();
+    loop {
+        let info = second.call("get_server_info", json!([])).await;
+        assert_eq!(info["result"]["rooms"], 2);
+        if info["result"]["obligations"] == json!(expected) {
+            break;
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn find_match_holds_the_slot() {
+    let server = TestServer::start(GameConfig {
+        gameplay: GameplayConfig {
+            max_players: 1,
+            max_rooms: 1,
+            ..Default::default()
+        },
+        ..Default::default()
+    }).await;
+
+    let mut without_ticket = server.login().await;
+    assert_eq!(without_ticket.call("find_match", json!([])).await["error"]["message"], "You have no tickets to play!");
+
+    let mut first = player_with_ticket(&server).await;
+    let matched = first.call("find_match", json!([])).await;
+    assert_eq!(matched["result"], json!({ "room_id": 1, "expires_in": 10 }));
+
+    let mut second = player_with_ticket(&server).await;
+    assert_eq!(second.enter_game().await["error"]["message"], "Server is full!");
+    assert_eq!(first.enter_game().await["result"], Value::Null);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn find_match_opens_rooms_for_tags() {
+    let server = TestServer::start(GameConfig {
+        gameplay: GameplayConfig {
+            max_players: 1,
+            max_rooms: 3,
+            ..Default::default()
+        },
+        ..Default::default()
+    }).await;
+
+    // the first room is untagged and takes anyone
+    let mut first = player_with_ticket(&server).await;
+    assert_eq!(first.call("find_match", json!({ "region": "us" })).await["result"]["room_id"], 1);
+    first.enter_game().await;
+
+    let mut second = player_with_ticket(&server).await;
+    assert_eq!(second.call("find_match", json!({ "region": "us" })).await["result"]["room_id"], 2);
+
+    let mut third = player_with_ticket(&server).await;
+    assert_eq!(third.call("find_match", json!({ "region": "eu", "skill": "new" })).await["result"]["room_id"], 3);
+
+    let rooms = third.call("list_rooms", json!([])).await;
+    let regions: Vec<&Value> = rooms["result"].as_array().unwrap().iter().map(|room| &room["region"]).collect();
+    assert_eq!(regions, [&Value::Null, &json!("us"), &json!("eu")]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn private_rooms_need_an_invite() {
+    let server = TestServer::start(GameConfig::default()).await;
+    let mut host = server.login().await;
+    let mut guest = server.login().await;
+    let mut stranger = server.login().await;
+
+    let created = host.call("create_private_room", json!({
+        "rules": { "max_players": 2, "width": 1000, "height": 1000, "win_time": 30 },
+        "members": [guest.address],
+        "free_entry": true,
+    })).await;
+    let room_id = created["result"]["room_id"].clone();
+    let invite_code = created["result"]["invite_code"].as_str().unwrap().to_string();
+    assert_eq!(room_id, 2);
+    assert_eq!(invite_code.len(), 8);
+
+    let rooms = guest.call("list_rooms", json!([])).await;
+    assert_eq!(rooms["result"].as_array().unwrap().len(), 1);
+    assert_eq!(guest.call("join_room", json!({ "room_id": room_id })).await["error"]["message"], "There is no room with this id!");
+    assert_eq!(
+        stranger.call("join_room", json!({ "invite_code": invite_code })).await["error"]["message"],
+        "You are not invited to this room!"
+    );
+
+    // free entry draws on the pot the host funds
+    let join = json!({ "invite_code": invite_code.to_lowercase() });
+    assert_eq!(guest.call("join_room", join.clone()).await["error"]["message"], "Server is out of free rewards to give a free entry!");
+    server.chain.rewards_added_by(&host.address, U256::from(1000) * U256::exp10(9));
+    loop {
+        let response = guest.call("join_room", join.clone()).await;
+        if response["result"] == Value::Null {
+            break;
+        }
+    }
+    assert_eq!(guest.recv_notification("notify_room_joined").await["params"]["room_id"], 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn private_room_rules_stay_within_server_limits() {
+    let server = TestServer::start(GameConfig::default()).await;
+    let mut host = server.login().await;
+
+    let response = host.call("create_private_room", json!({
+        "rules": { "width": 10000, "win_time": 0 },
+        "members": ["not an address"],
+    })).await;
+    let errors = response["error"]["data"].as_array().unwrap();
+    for message in ["rules.width and rules.height must be at most 5000x5000", "rules.win_time must be positive", "members must all be addresses"].iter() {
+        assert!(errors.contains(&json!(message)), "missing '{}' in {:?}", message, errors);
+    }
+
+    assert!(host.call("create_private_room", json!([])).await["error"].is_null());
+    let again = host.call("create_private_room", json!([])).await;
+    assert_eq!(again["error"]["data"], json!(["You already have a private room"]));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn empty_rooms_close_keeping_one() {
+    let server = TestServer::start(GameConfig {
+        gameplay: GameplayConfig {
+            max_players: 1,
+            max_rooms: 3,
+            empty_room_secs: 2,
+            ..Default::default()
+        },
+        ..Default::default()
+    }).await;
+    let first = server.join_game().await;
+    let second = server.join_game().await;
+    let mut watcher = server.login().await;
+    assert_eq!(watcher.call("get_server_info", json!([])).await["result"]["rooms"], 2);
+
+    drop(first);
+    drop(second);
+    loop {
+        let rooms = watcher.call("list_rooms", json!([])).await;
+        if rooms["result"].as_array().unwrap().iter().all(|room| room["players"] == 0) {
+            break;
+        }
+    }
+    let obligations = watcher.call("get_server_info", json!([])).await["result"]["obligations"].clone();
+
+    // the closed room's food moves to the one left
+    loop {
+        let info = watcher.call("get_server_info", json!([])).await;
+        if info["result"]["rooms"] == 1 {
+            assert_eq!(info["result"]["obligations"], obligations);
+            break;
+        }
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+    }
+    tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
+    assert_eq!(watcher.call("get_server_info", json!([])).await["result"]["rooms"], 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn tickets_fund_the_private_room_they_are_used_in() {
+    let server = TestServer::start(GameConfig::default()).await;
+    let mut host = server.login().await;
+    let invite_code = host.call("create_private_room", json!([])).await["result"]["invite_code"].clone();
+    let mut guest = player_with_ticket(&server).await;
+
+    let public_rewards = |rooms: Value| rooms["result"][0]["available_rewards"].as_u64().unwrap();
+    let before = public_rewards(guest.call("list_rooms", json!([])).await);
+    assert_eq!(guest.call("join_room", json!({ "invite_code": invite_code })).await["result"], Value::Null);
+    let after = public_rewards(guest.call("list_rooms", json!([])).await);
+    let economy = EconomyConfig::default();
+    assert_eq!(before - after, economy.new_player_food_to_add * economy.multiplier as u64);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn empty_private_rooms_close_and_free_their_creator() {
+    let server = TestServer::start(GameConfig {
+        gameplay: GameplayConfig {
+            max_rooms: 2,
+            empty_room_secs: 1,
+            ..Default::default()
+        },
+        ..Default::default()
+    }).await;
+    let mut host = server.login().await;
+    assert!(host.call("create_private_room", json!([])).await["error"].is_null());
+    assert_eq!(host.call("get_server_info", json!([])).await["result"]["rooms"], 2);
+
+    loop {
+        let created = host.call("create_private_room", json!([])).await;
+        if created["error"].is_null() {
+            break;
+        }
+        assert_eq!(created["error"]["data"][0], "You already have a private room");
+        tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+    }
+}
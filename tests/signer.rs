@@ -0,0 +1,88 @@
+use std::{env, fs, str::FromStr};
+
+use ethers::prelude::*;
+use ethers::types::transaction::eip2718::TypedTransaction;
+use tokio::net::TcpListener;
+
+use agario_rust::config::{ChainConfig, Secret};
+use agario_rust::signer::{self, RemoteSigner, ServerSigner};
+
+
+const SECRET_KEY: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+
+
+async fn start_remote(wallet: LocalWallet) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    tokio::spawn(signer::serve(wallet, listener));
+    url
+}
+
+
+#[tokio::test]
+async fn remote_signer_signs_like_the_local_wallet() {
+    let wallet = LocalWallet::new(&mut rand::thread_rng()).with_chain_id(28u64);
+    let url = start_remote(wallet.clone()).await;
+    let remote = ServerSigner::Remote(RemoteSigner::connect(&url).await.unwrap().with_chain_id(28u64));
+    assert_eq!(remote.address(), wallet.address());
+
+    let message = "let's play";
+    assert_eq!(
+        remote.sign_message(message).await.unwrap(),
+        wallet.sign_message(message).await.unwrap(),
+    );
+
+    let tx: TypedTransaction = TransactionRequest::new()
+        .to(Address::random())
+        .value(1000)
+        .gas(21000)
+        .gas_price(1)
+        .nonce(0)
+        .into();
+    let signature = remote.sign_transaction(&tx).await.unwrap();
+    assert_eq!(signature, wallet.sign_transaction(&tx).await.unwrap());
+    let mut signed = tx.clone();
+    signed.set_chain_id(28u64);
+    assert_eq!(signature.recover(signed.sighash()).unwrap(), wallet.address());
+}
+
+#[tokio::test]
+async fn loads_an_encrypted_keystore() {
+    let dir = env::temp_dir().join(format!("agario-keystore-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (wallet, name) = LocalWallet::new_keystore(&dir, &mut rand::thread_rng(), "hunter2", None).unwrap();
+    let password_file = dir.join("password");
+    fs::write(&password_file, "hunter2\n").unwrap();
+
+    let config = ChainConfig {
+        chain_id: 28,
+        keystore_path: Some(dir.join(name)),
+        keystore_password_file: Some(password_file),
+        ..Default::default()
+    };
+    let signer = signer::load(&config).await.unwrap();
+    assert_eq!(signer.address(), wallet.address());
+    assert_eq!(signer.chain_id(), 28);
+}
+
+#[tokio::test]
+async fn secret_key_never_shows_in_debug_output() {
+    let config = ChainConfig {
+        chain_id: 28,
+        secret_key: Secret::new(String::from(SECRET_KEY)),
+        ..Default::default()
+    };
+    assert!(!format!("{:?}", config).contains(SECRET_KEY));
+    assert_eq!(config.secret_key.to_string(), "<redacted>");
+
+    let signer = signer::load(&config).await.unwrap();
+    assert_eq!(signer.address(), LocalWallet::from_str(SECRET_KEY).unwrap().address());
+    assert!(!format!("{:?}", signer).contains(SECRET_KEY));
+
+    let bad = ChainConfig {
+        secret_key: Secret::new(String::from("0xnot-a-key")),
+        ..config
+    };
+    let err = signer::load(&bad).await.unwrap_err().to_string();
+    assert!(!err.contains("not-a-key"), "{}", err);
+}
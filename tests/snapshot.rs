@@ -0,0 +1,200 @@
+mod common;
+
+use serde_json::json;
+
+use agario_rust::config::GameplayConfig;
+use agario_rust::game::GameConfig;
+use agario_rust::protocol::{self, Channel, DecodeError, Entity, Quantizer, Stamp, View};
+use agario_rust::snapshot::{SnapshotEncoder, SnapshotError, SnapshotReceiver};
+use common::{Frame, TestServer};
+
+
+const QUANTIZER: Quantizer = Quantizer { width: 5000., height: 5000. };
+const VIEW: View = View { x: 100., y: 200., visible_range: 300. };
+const STAMP: Stamp = Stamp { tick: 40, input: 7 };
+
+fn food(count: u32) -> Vec<Entity> {
+    (1..=count)
+        .map(|id| QUANTIZER.entity(id, 0, id as f64 * 10., 50., 4., id as f64))
+        .collect()
+}
+
+fn is_keyframe(data: &[u8]) -> bool {
+    data[1] == 0
+}
+
+
+#[test]
+fn positions_survive_quantization() {
+    let entity = QUANTIZER.entity(7, 3, 1234.56, 4999.9, 23.3, 300.5);
+    let (x, y) = QUANTIZER.position(&entity);
+    assert!((x - 1234.56).abs() < 0.05);
+    assert!((y - 4999.9).abs() < 0.05);
+    assert!((QUANTIZER.radius(&entity) - 23.3).abs() <= 1. / 16.);
+    // hues above 255 degrees used to wrap
+    assert!((QUANTIZER.hue(&entity) - 300.5).abs() < 0.01);
+    // out of the arena is clamped, hues go around the wheel
+    let entity = QUANTIZER.entity(7, 3, -5., 6000., 0., 370.);
+    assert_eq!((entity.x, entity.y), (0, u16::MAX));
+    assert_eq!(entity.hue, QUANTIZER.entity(7, 3, 0., 0., 0., 10.).hue);
+}
+
+#[test]
+fn deltas_build_on_the_acknowledged_frame() {
+    let config = GameplayConfig::default();
+    let mut encoder = SnapshotEncoder::default();
+    let mut receiver = SnapshotReceiver::new(config.snapshot_history);
+
+    // keyframes until the client acknowledges one
+    let first = encoder.encode(Channel::Food, None, STAMP, QUANTIZER, food(100), &config);
+    let second = encoder.encode(Channel::Food, None, STAMP, QUANTIZER, food(100), &config);
+    assert!(is_keyframe(&first) && is_keyframe(&second));
+    let snapshot = receiver.apply(&second).unwrap();
+    assert_eq!(snapshot.entities, food(100));
+    encoder.ack(snapshot.seq);
+
+    // one eaten, one spawned and one moved
+    let mut next = food(101);
+    next.remove(10);
+    next[20].x += 3;
+    let delta = encoder.encode(Channel::Food, None, STAMP, QUANTIZER, next.clone(), &config);
+    assert!(!is_keyframe(&delta));
+    assert!(delta.len() < 50, "delta of {} bytes", delta.len());
+    assert_eq!(receiver.apply(&delta).unwrap().entities, next);
+
+    // not acknowledged, so still against the same base
+    let unchanged = encoder.encode(Channel::Food, None, STAMP, QUANTIZER, food(100), &config);
+    assert!(!is_keyframe(&unchanged));
+    assert_eq!(receiver.apply(&unchanged).unwrap().entities, food(100));
+}
+
+#[test]
+fn channels_are_tracked_apart() {
+    let config = GameplayConfig::default();
+    let mut encoder = SnapshotEncoder::default();
+    let mut receiver = SnapshotReceiver::new(config.snapshot_history);
+
+    let cells: Vec<Entity> = (1..=3)
+        .map(|id| QUANTIZER.entity(id, 42, 10., 20., 30., 120.))
+        .collect();
+    let players = encoder.encode(Channel::Players, Some(VIEW), STAMP, QUANTIZER, cells.clone(), &config);
+    encoder.ack(receiver.apply(&players).unwrap().seq);
+    // food has nothing acknowledged yet
+    assert!(is_keyframe(&encoder.encode(Channel::Food, None, STAMP, QUANTIZER, food(5), &config)));
+
+    let players = encoder.encode(Channel::Players, Some(VIEW), STAMP, QUANTIZER, cells.clone(), &config);
+    assert!(!is_keyframe(&players));
+    let snapshot = receiver.apply(&players).unwrap();
+    assert_eq!(snapshot.view, Some(VIEW));
+    assert_eq!(snapshot.entities, cells);
+    assert!(snapshot.entities.iter().all(|cell| cell.owner == 42));
+}
+
+#[test]
+fn stamps_follow_the_client_version() {
+    let config = GameplayConfig::default();
+    let mut encoder = SnapshotEncoder::default();
+    let mut receiver = SnapshotReceiver::new(config.snapshot_history);
+    let stamped = encoder.encode(Channel::Food, None, STAMP, QUANTIZER, food(5), &config);
+    assert_eq!(receiver.apply(&stamped).unwrap().stamp, STAMP);
+
+    // clients from before stamps get the frames they know
+    let version = protocol::MIN_PROTOCOL_VERSION;
+    let mut encoder = SnapshotEncoder::new(version);
+    let mut receiver = SnapshotReceiver::for_version(version, config.snapshot_history);
+    let unstamped = encoder.encode(Channel::Food, None, STAMP, QUANTIZER, food(5), &config);
+    assert_eq!(unstamped.len(), stamped.len() - 8);
+    assert_eq!(receiver.apply(&unstamped).unwrap().stamp, Stamp::default());
+}
+
+#[test]
+fn keyframes_are_sent_when_due_or_out_of_sync() {
+    let config = GameplayConfig { keyframe_interval: 3, snapshot_history: 4, ..Default::default() };
+    let mut encoder = SnapshotEncoder::default();
+    let mut receiver = SnapshotReceiver::new(config.snapshot_history);
+    let keyframe = encoder.encode(Channel::Food, None, STAMP, QUANTIZER, food(5), &config);
+    encoder.ack(receiver.apply(&keyframe).unwrap().seq);
+
+    let kinds: Vec<bool> = (0..4)
+        .map(|_| is_keyframe(&encoder.encode(Channel::Food, None, STAMP, QUANTIZER, food(5), &config)))
+        .collect();
+    assert_eq!(kinds, vec![false, false, false, true]);
+
+    // the acknowledged frame fell out of the history
+    assert!(is_keyframe(&encoder.encode(Channel::Food, None, STAMP, QUANTIZER, food(5), &config)));
+
+    let keyframe = encoder.encode(Channel::Food, None, STAMP, QUANTIZER, food(5), &config);
+    encoder.ack(receiver.apply(&keyframe).unwrap().seq);
+    assert!(!is_keyframe(&encoder.encode(Channel::Food, None, STAMP, QUANTIZER, food(5), &config)));
+    encoder.resync();
+    assert!(is_keyframe(&encoder.encode(Channel::Food, None, STAMP, QUANTIZER, food(5), &config)));
+}
+
+#[test]
+fn broken_frames_are_reported() {
+    let config = GameplayConfig::default();
+    let mut encoder = SnapshotEncoder::default();
+    let mut sender_receiver = SnapshotReceiver::new(config.snapshot_history);
+    let keyframe = encoder.encode(Channel::Food, None, STAMP, QUANTIZER, food(5), &config);
+    encoder.ack(sender_receiver.apply(&keyframe).unwrap().seq);
+    let delta = encoder.encode(Channel::Food, None, STAMP, QUANTIZER, food(6), &config);
+
+    let mut receiver = SnapshotReceiver::new(config.snapshot_history);
+    assert_eq!(receiver.apply(&delta), Err(SnapshotError::NoKeyframe));
+    receiver.apply(&keyframe).unwrap();
+    receiver.reset();
+    assert_eq!(receiver.apply(&delta), Err(SnapshotError::MissingBase(0)));
+
+    assert_eq!(
+        receiver.apply(&keyframe[..keyframe.len() - 1]),
+        Err(SnapshotError::Decode(DecodeError::Truncated)),
+    );
+    assert_eq!(receiver.apply(&[7, 0]), Err(SnapshotError::Decode(DecodeError::UnknownChannel(7))));
+    let mut trailing = keyframe.clone();
+    trailing.push(0);
+    assert_eq!(receiver.apply(&trailing), Err(SnapshotError::Decode(DecodeError::TrailingBytes)));
+}
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn streamed_food_frames_all_apply() {
+    let server = TestServer::start(GameConfig::default()).await;
+    let mut client = server.join_game().await;
+
+    // every frame has to apply on top of the ones before
+    let mut seen = 0;
+    for _ in 0..20 {
+        if let Frame::Food(food) = client.recv_frame(|f| matches!(f, Frame::Food(_))).await {
+            seen = seen.max(food.len());
+        }
+    }
+    assert!(seen > 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cells_carry_their_owner() {
+    let server = TestServer::start(GameConfig::default()).await;
+    let mut first = server.join_game().await;
+    let owners = first.recv_notification("notify_owners").await;
+    let own = owners["params"]["own"].as_u64().unwrap() as u32;
+    assert_eq!(owners["params"]["added"][0]["address"], json!(first.address));
+
+    let mut second = server.join_game().await;
+    let joined = first.recv_notification("notify_owners").await;
+    assert_eq!(joined["params"]["added"][0]["address"], json!(second.address));
+    let other = joined["params"]["added"][0]["id"].as_u64().unwrap() as u32;
+    assert_ne!(own, other);
+
+    let owners = second.call("get_owners", json!([])).await["result"].clone();
+    assert_eq!(owners.as_array().unwrap().len(), 2);
+
+    match first.recv_players_frame().await {
+        Frame::Players { x, y, cells, .. } => {
+            let mine = cells.iter()
+                .find(|c| (c.x - x).abs() < 0.1 && (c.y - y).abs() < 0.1)
+                .unwrap();
+            assert_eq!(mine.owner, own);
+        }
+        _ => unreachable!(),
+    }
+}
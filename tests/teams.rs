@@ -0,0 +1,78 @@
+mod common;
+
+use serde_json::{json, Value};
+
+use agario_rust::config::GameplayConfig;
+use agario_rust::game::{split_winnings, GameConfig};
+use common::{TestClient, TestServer};
+
+
+fn config() -> GameConfig {
+    GameConfig {
+        gameplay: GameplayConfig {
+            teams: 2,
+            default_mass: 30.,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+async fn join(server: &TestServer) -> (TestClient, Value) {
+    let mut client = server.join_game().await;
+    let team = client.recv_notification("notify_room_joined").await["params"]["team"].clone();
+    (client, team)
+}
+
+fn team_mass(metadata: &Value, team: u64) -> Option<u64> {
+    metadata["params"]["teams"].as_array()?
+        .iter()
+        .find(|t| t["team"] == json!(team))
+        .and_then(|t| t["mass"].as_u64())
+}
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn players_are_spread_over_teams() {
+    let server = TestServer::start(config()).await;
+    let (_first, first_team) = join(&server).await;
+    let (_second, second_team) = join(&server).await;
+    let (mut third, third_team) = join(&server).await;
+    assert_eq!((first_team, second_team, third_team), (json!(0), json!(1), json!(0)));
+
+    let metadata = third.recv_notification("notify_update_metadata").await;
+    assert_eq!(team_mass(&metadata, 0), Some(60));
+    assert_eq!(team_mass(&metadata, 1), Some(30));
+    assert_eq!(metadata["params"]["teams"][0]["players"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ejected_mass_leaves_the_team_total() {
+    let server = TestServer::start(config()).await;
+    let (mut client, _) = join(&server).await;
+
+    // a target this close points the way without moving the cell
+    client.target(20., 0.).await;
+    for expected in [20, 10].iter() {
+        client.eject().await;
+        loop {
+            let metadata = client.recv_notification("notify_update_metadata").await;
+            if team_mass(&metadata, 0) == Some(*expected) {
+                break;
+            }
+        }
+    }
+    // a cell keeps at least the mass it would eject
+    client.eject().await;
+    let metadata = client.recv_notification("notify_update_metadata").await;
+    assert_eq!(team_mass(&metadata, 0), Some(10));
+}
+
+#[test]
+fn winnings_are_split_by_mass() {
+    assert_eq!(split_winnings(&[1200], 0.5), (vec![600], 600));
+    assert_eq!(split_winnings(&[300, 100], 0.5), (vec![150, 50], 200));
+    // rounding leftovers go to the heaviest member
+    assert_eq!(split_winnings(&[2, 1], 0.5), (vec![2, 0], 1));
+    assert_eq!(split_winnings(&[], 0.5), (vec![], 0));
+}
@@ -0,0 +1,96 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use ethers::prelude::U256;
+use proptest::prelude::*;
+
+use agario_rust::config::EconomyConfig;
+use agario_rust::game::{Game, GameConfig};
+use agario_rust::token::{MassConversion, TokenAmount};
+
+
+fn conversion() -> impl Strategy<Value = MassConversion> {
+    (0u8..=30, 1u32..=10_000).prop_map(|(decimals, multiplier)| {
+        MassConversion::new(decimals, multiplier).unwrap()
+    })
+}
+
+fn token_amount() -> impl Strategy<Value = TokenAmount> {
+    prop_oneof![
+        any::<u64>().prop_map(U256::from),
+        any::<u128>().prop_map(U256::from),
+        any::<[u64; 4]>().prop_map(U256),
+    ].prop_map(TokenAmount::from_base_units)
+}
+
+fn new_game(decimals: u8, multiplier: u32) -> Game {
+    Game::new(
+        Arc::new(Mutex::new(HashMap::new())),
+        Arc::new(Mutex::new(HashMap::new())),
+        GameConfig {
+            economy: EconomyConfig {
+                multiplier,
+                token_decimals: decimals,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+}
+
+
+proptest! {
+    #[test]
+    fn tokens_to_mass_keeps_the_remainder(conversion in conversion(), amount in token_amount()) {
+        let (mass, remainder) = conversion.tokens_to_mass(amount);
+        prop_assert_eq!(conversion.mass_to_tokens(mass) + remainder, amount);
+        prop_assert!(remainder < conversion.mass_unit() || mass == u64::MAX);
+    }
+
+    #[test]
+    fn mass_to_tokens_round_trips(conversion in conversion(), mass in any::<u64>()) {
+        let amount = conversion.mass_to_tokens(mass);
+        prop_assert_eq!(conversion.tokens_to_mass(amount), (mass, TokenAmount::zero()));
+    }
+
+    #[test]
+    fn mass_to_tokens_is_additive(conversion in conversion(), a in any::<u32>(), b in any::<u32>()) {
+        let (a, b) = (a as u64, b as u64);
+        prop_assert_eq!(
+            conversion.mass_to_tokens(a) + conversion.mass_to_tokens(b),
+            conversion.mass_to_tokens(a + b)
+        );
+    }
+
+    #[test]
+    fn deposits_are_fully_accounted_for(
+        decimals in 0u8..=18,
+        multiplier in 1u32..=1000,
+        deposits in prop::collection::vec(any::<u128>(), 0..20),
+    ) {
+        let mut game = new_game(decimals, multiplier);
+        let mut total = TokenAmount::zero();
+        for deposit in deposits {
+            let deposit = TokenAmount::from_base_units(U256::from(deposit));
+            game.add_rewards(deposit);
+            total = total + deposit;
+        }
+        prop_assert_eq!(game.reward_balance(), total);
+    }
+}
+
+#[test]
+fn rejects_unusable_conversions() {
+    assert!(MassConversion::new(9, 0).is_none());
+    assert!(MassConversion::new(70, 1).is_none());
+}
+
+#[test]
+fn formats_whole_and_fractional_amounts() {
+    let amount = TokenAmount::from_base_units(U256::from(12_500_000_000u64));
+    assert_eq!(amount.format(9), "12.5");
+    assert_eq!(TokenAmount::from_base_units(U256::from(7)).format(9), "0.000000007");
+    assert_eq!(TokenAmount::from_base_units(U256::exp10(9) * 3).format(9), "3");
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use ethers::prelude::*;
+use serde_json::{json, Value};
+
+use agario_rust::config::{GameplayConfig, TournamentConfig};
+use agario_rust::game::GameConfig;
+use agario_rust::tournament::{advancing, prize_shares, seed_heats, unix_now};
+use common::{TestClient, TestServer};
+
+
+fn config(starts_in: u64) -> GameConfig {
+    GameConfig {
+        // no food, so everyone keeps their spawn mass and ties go by seeding
+        gameplay: GameplayConfig {
+            food_to_add_per_tick: 0,
+            ..Default::default()
+        },
+        tournament: TournamentConfig {
+            start_times: vec![unix_now() + starts_in],
+            registration_secs: 60,
+            heat_size: 2,
+            advance_per_heat: 1,
+            heat_secs: 2,
+            prize_percentages: vec![70, 30],
+        },
+        ..Default::default()
+    }
+}
+
+async fn registered(server: &TestServer) -> TestClient {
+    let mut client = server.login().await;
+    server.buy_ticket(&mut client).await;
+    let response = client.call("register_tournament", json!({ "tournament_id": 1 })).await;
+    assert_eq!(response["result"], Value::Null, "{}", response);
+    client
+}
+
+/// Waits for the player's next heat and enters it.
+async fn play_heat(client: &mut TestClient) -> Value {
+    let heat = client.recv_notification("notify_tournament_heat").await;
+    assert_eq!(client.enter_game().await["result"], Value::Null);
+    heat["params"].clone()
+}
+
+
+#[tokio::test(flavor = "multi_thread")]
+async fn registration_takes_a_ticket() {
+    let server = TestServer::start(config(60)).await;
+    let mut client = registered(&server).await;
+    assert_eq!(client.call("get_available_tickets", json!([])).await["result"], 0);
+
+    let again = client.call("register_tournament", json!({ "tournament_id": 1 })).await;
+    assert_eq!(again["error"]["message"], "You are already registered for this tournament!");
+    let unknown = client.call("register_tournament", json!({ "tournament_id": 2 })).await;
+    assert_eq!(unknown["error"]["message"], "There is no tournament with this id!");
+
+    let mut without_ticket = server.login().await;
+    let response = without_ticket.call("register_tournament", json!({ "tournament_id": 1 })).await;
+    assert_eq!(response["error"]["message"], "You have no tickets to play!");
+
+    let upcoming = client.call("list_tournaments", json!([])).await;
+    assert_eq!(upcoming["result"][0]["id"], 1);
+    assert_eq!(upcoming["result"][0]["players"], 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn heats_lead_to_a_paid_final() {
+    let mut server = TestServer::start(config(3)).await;
+    let mut first = registered(&server).await;
+    let mut second = registered(&server).await;
+    let mut third = registered(&server).await;
+
+    // three players make two heats, one player goes through from each
+    let heat = play_heat(&mut first).await;
+    assert_eq!((heat["round"].clone(), heat["final"].clone()), (json!(1), json!(false)));
+    play_heat(&mut second).await;
+    play_heat(&mut third).await;
+
+    let final_heat = play_heat(&mut first).await;
+    assert_eq!(final_heat["final"], true);
+    play_heat(&mut second).await;
+
+    // three tickets of 90 mass, split 70/30
+    for (player, prize) in [(&first, 189), (&second, 81)].iter() {
+        let winner = server.chain.next_winner().await.unwrap();
+        assert_eq!(format!("{:#x}", winner.address), player.address);
+        assert_eq!(winner.amount, U256::from(*prize) * U256::exp10(9));
+    }
+
+    let bracket = third.call("get_tournament", json!({ "tournament_id": 1 })).await;
+    assert_eq!(bracket["result"]["stage"], "finished");
+    assert_eq!(bracket["result"]["rounds"].as_array().unwrap().len(), 2);
+    assert_eq!(bracket["result"]["prizes"].as_array().unwrap().len(), 2);
+    assert_eq!(third.call("list_tournaments", json!([])).await["result"], json!([]));
+}
+
+#[test]
+fn brackets_shrink_every_round() {
+    let players: Vec<String> = (0..7).map(|i| i.to_string()).collect();
+    let heats = seed_heats(&players, 3);
+    assert_eq!(heats.iter().map(Vec::len).collect::<Vec<_>>(), [3, 2, 2]);
+
+    let results = vec![(String::from("a"), 30), (String::from("b"), 20)];
+    // a heat always drops someone, even when more could advance
+    assert_eq!(advancing(&results, 5), ["a"]);
+    assert_eq!(advancing(&results[..1], 5), ["a"]);
+
+    assert_eq!(prize_shares(101, &[50, 30, 20]), [50, 30, 20]);
+}
@@ -0,0 +1,184 @@
+mod common;
+
+use serde_json::json;
+
+use agario_rust::config::GameplayConfig;
+use agario_rust::game::GameConfig;
+use agario_rust::win::{self, Side, Standings, WinConditionKind};
+use common::TestServer;
+
+
+fn gameplay(win_condition: WinConditionKind) -> GameplayConfig {
+    GameplayConfig {
+        win_condition,
+        win_time: 3,
+        win_mass_threshold: 100,
+        win_mass_target: 500,
+        hold_secs: 2,
+        win_map_share: 0.5,
+        ..Default::default()
+    }
+}
+
+/// One second of play: players with their mass, and the food left on the map.
+fn standings(players: &[(&str, f64)], food: f64) -> Standings {
+    let mut sides: Vec<Side> = players.iter()
+        .map(|(id, mass)| Side { id: id.to_string(), members: vec![id.to_string()], mass: *mass })
+        .collect();
+    sides.sort_by(|a, b| b.mass.partial_cmp(&a.mass).unwrap());
+    Standings {
+        map_mass: players.iter().map(|(_, mass)| mass).sum::<f64>() + food,
+        sides,
+    }
+}
+
+/// Runs the condition over the seconds and returns when, and who, won.
+fn play(kind: WinConditionKind, seconds: &[Standings]) -> Option<(usize, String)> {
+    let mut condition = win::from_config(&gameplay(kind));
+    assert_eq!(condition.kind(), kind);
+    seconds.iter().enumerate().find_map(|(second, standings)| {
+        condition.tick(standings).map(|side| (second, side.id))
+    })
+}
+
+
+#[test]
+fn timed_pays_the_heaviest_when_the_counter_runs_out() {
+    let growing = [
+        standings(&[("a", 150.), ("b", 90.)], 0.),
+        standings(&[("a", 150.), ("b", 200.)], 0.),
+        standings(&[("a", 150.), ("b", 210.)], 0.),
+    ];
+    assert_eq!(play(WinConditionKind::Timed, &growing), Some((2, String::from("b"))));
+
+    // below the threshold the round restarts without a winner
+    let small = vec![standings(&[("a", 50.)], 0.); 6];
+    assert_eq!(play(WinConditionKind::Timed, &small), None);
+}
+
+#[test]
+fn mass_target_wins_as_soon_as_it_is_reached() {
+    let seconds = [
+        standings(&[("a", 450.), ("b", 300.)], 0.),
+        standings(&[("a", 450.), ("b", 500.)], 0.),
+    ];
+    assert_eq!(play(WinConditionKind::MassTarget, &seconds), Some((1, String::from("b"))));
+}
+
+#[test]
+fn king_of_the_hill_needs_an_unbroken_reign() {
+    let seconds = [
+        standings(&[("a", 200.), ("b", 150.)], 0.),
+        standings(&[("a", 200.), ("b", 150.)], 0.),
+        // b takes over right before a would have won
+        standings(&[("a", 200.), ("b", 250.)], 0.),
+        standings(&[("a", 200.), ("b", 250.)], 0.),
+        standings(&[("a", 200.), ("b", 250.)], 0.),
+    ];
+    assert_eq!(play(WinConditionKind::KingOfTheHill, &seconds), Some((4, String::from("b"))));
+
+    let mut condition = win::from_config(&gameplay(WinConditionKind::KingOfTheHill));
+    condition.tick(&standings(&[("a", 200.)], 0.));
+    assert_eq!(condition.countdown(), Some(2));
+    condition.tick(&standings(&[("a", 200.)], 0.));
+    assert_eq!(condition.countdown(), Some(1));
+    // falling under the threshold ends the reign
+    condition.tick(&standings(&[("a", 90.)], 0.));
+    assert_eq!(condition.countdown(), Some(2));
+}
+
+#[test]
+fn map_share_counts_food_and_other_players() {
+    let seconds = [
+        standings(&[("a", 200.), ("b", 100.)], 200.),
+        standings(&[("a", 260.), ("b", 100.)], 140.),
+        // half the map, but not past the threshold
+        standings(&[("a", 20.)], 20.),
+    ];
+    assert_eq!(play(WinConditionKind::MapShare, &seconds), Some((1, String::from("a"))));
+    assert_eq!(play(WinConditionKind::MapShare, &seconds[2..]), None);
+}
+
+#[test]
+fn reloads_keep_the_progress_made() {
+    let mut condition = win::from_config(&gameplay(WinConditionKind::Timed));
+    condition.tick(&Standings::default());
+    assert_eq!(condition.countdown(), Some(2));
+    condition.update_config(&GameplayConfig { win_time: 10, ..gameplay(WinConditionKind::Timed) });
+    assert_eq!(condition.countdown(), Some(2));
+    condition.update_config(&GameplayConfig { win_time: 1, ..gameplay(WinConditionKind::Timed) });
+    assert_eq!(condition.countdown(), Some(1));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rooms_play_the_configured_condition() {
+    let server = TestServer::start(GameConfig {
+        gameplay: GameplayConfig {
+            win_condition: WinConditionKind::MassTarget,
+            win_mass_target: 10,
+            ..Default::default()
+        },
+        ..Default::default()
+    }).await;
+    let mut client = server.join_game().await;
+
+    // players spawn with the target mass, there is no timer to wait for
+    client.recv_notification("notify_won").await;
+}
+
+/// A room without food, so players keep the mass they spawn with.
+async fn start_foodless(gameplay: GameplayConfig) -> TestServer {
+    TestServer::start(GameConfig {
+        gameplay: GameplayConfig {
+            max_food_in_game: 0,
+            food_to_add_per_tick: 0,
+            ..gameplay
+        },
+        ..Default::default()
+    }).await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rooms_pay_the_heaviest_when_the_timer_runs_out() {
+    let server = start_foodless(GameplayConfig {
+        win_time: 2,
+        win_mass_threshold: 5,
+        ..gameplay(WinConditionKind::Timed)
+    }).await;
+    let mut client = server.join_game().await;
+    client.recv_notification("notify_won").await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rooms_crown_the_king_of_the_hill() {
+    let server = start_foodless(GameplayConfig {
+        hold_secs: 2,
+        win_mass_threshold: 5,
+        ..gameplay(WinConditionKind::KingOfTheHill)
+    }).await;
+    let mut client = server.join_game().await;
+    // players see the reign count down
+    let metadata = client.recv_notification("notify_update_metadata").await;
+    assert!(metadata["params"]["win_counter"].as_u64().unwrap() <= 2);
+    client.recv_notification("notify_won").await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn teams_win_with_their_share_of_the_map() {
+    let server = start_foodless(GameplayConfig {
+        teams: 2,
+        // one player alone is not enough, two team mates are
+        win_mass_threshold: 15,
+        win_map_share: 0.6,
+        ..gameplay(WinConditionKind::MapShare)
+    }).await;
+    // teams are filled in turn, the first and the third play together
+    let mut first = server.join_game().await;
+    let mut second = server.join_game().await;
+    let mut third = server.join_game().await;
+
+    first.recv_notification("notify_won").await;
+    third.recv_notification("notify_won").await;
+    let rooms = second.call("list_rooms", json!([])).await;
+    assert_eq!(rooms["result"][0]["players"], 1);
+}